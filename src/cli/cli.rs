@@ -0,0 +1,315 @@
+use std::{error::Error, fs, path::{Path, PathBuf}};
+
+use crate::{helpers::{chart::{Chart, SongChart, CHART_FILENAME}, persistence::get_songs_dir, song_validation::{validate_song_directory, SongReport}, tuning::STANDARD_TUNING}, importers::{ascii_tab::import_ascii_tab, guitar_pro::GuitarProSong, imported_song::ImportedSong, midi::MidiSong, music_xml::{export_music_xml, MusicXmlSong}}, package::package::{export_package, SongPackage}, resources::song_library::{SongLibraryResource, SongMetadata}, transcription::{beat_tracking::detect_song_tempo, transcription::transcribe_song}};
+
+const USAGE: &str = "usage:
+    guitar-gaming-client import-gp <file> --list-tracks
+    guitar-gaming-client import-gp <file> [--track <index>] (--song <uuid> [--chart <arrangement>_<difficulty>] | --audio <audio file>)
+    guitar-gaming-client import-midi <file> --list-tracks
+    guitar-gaming-client import-midi <file> [--track <index>] [--channel <1-16>] [--tuning E2,A2,D3,G3,B3,E4] (--song <uuid> [--chart <arrangement>_<difficulty>] | --audio <audio file>)
+    guitar-gaming-client import-musicxml <file> --list-parts
+    guitar-gaming-client import-musicxml <file> [--part <index>] (--song <uuid> [--chart <arrangement>_<difficulty>] | --audio <audio file>)
+    guitar-gaming-client import-tab <file.txt> [--check] (--song <uuid> [--chart <arrangement>_<difficulty>] | --audio <audio file>)
+    guitar-gaming-client export-musicxml <uuid> <output.musicxml> [--bpm <bpm>]
+    guitar-gaming-client transcribe [<uuid>...] [--stem <file name>] [--overwrite]
+    guitar-gaming-client detect-tempo <uuid> [--beats-per-measure <beats>]
+    guitar-gaming-client export-package <uuid> <output.songpkg>
+    guitar-gaming-client import-package <file.songpkg> [--check] [--replace]
+    guitar-gaming-client validate [<uuid>...]";
+
+/// Runs a subcommand if one was passed, returns the exit code or None to start the game
+pub fn run_cli() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let subcommand = args.first()?;
+
+    let result = match subcommand.as_str() {
+        "import-gp" => import_guitar_pro(&args[1..]),
+        "import-midi" => import_midi(&args[1..]),
+        "import-musicxml" => import_music_xml(&args[1..]),
+        "import-tab" => import_tab(&args[1..]),
+        "export-musicxml" => export_music_xml_command(&args[1..]),
+        "transcribe" => transcribe(&args[1..]),
+        "detect-tempo" => detect_tempo(&args[1..]),
+        "export-package" => export_package_command(&args[1..]),
+        "import-package" => import_package(&args[1..]),
+        "validate" => validate(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown subcommand \"{}\"\n{}", subcommand, USAGE).into()),
+    };
+
+    match result {
+        Ok(()) => Some(0),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Some(1)
+        }
+    }
+}
+
+/// Value that follows a flag, e.g. `--track 2`
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)).map(|value| value.as_str())
+}
+
+/// Writes the import into an existing song, or creates a new song around it
+fn write_import(imported_song: &ImportedSong, args: &[String]) -> Result<(), Box<dyn Error>> {
+    for issue in &imported_song.issues {
+        eprintln!("warning: {}", issue);
+    }
+
+    if let Some(uuid) = flag_value(args, "--song") {
+        let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(uuid);
+        if !song_directory.is_dir() {
+            return Err(format!("song {} does not exist", uuid).into())
+        }
+        // Like --chart rhythm_expert, the main chart is replaced without it
+        let song_chart = match flag_value(args, "--chart") {
+            Some(name) => Some(SongChart::from_name(name).ok_or(format!("\"{}\" is not a chart, use e.g. lead_expert or rhythm_easy", name))?),
+            None => None,
+        };
+        imported_song.write_into_song(&song_directory, song_chart)?;
+        println!("imported {} notes into the {} of song {}", imported_song.chart.notes.len(), song_chart.map(|song_chart| song_chart.label()).unwrap_or("main chart".to_string()), uuid);
+    } else if let Some(audio_path) = flag_value(args, "--audio") {
+        let song_metadata = imported_song.create_song(&PathBuf::from(audio_path))?;
+        println!("created song {} with {} notes", song_metadata.uuid, imported_song.chart.notes.len());
+    } else {
+        return Err(format!("either --song or --audio is required\n{}", USAGE).into())
+    }
+
+    Ok(())
+}
+
+fn import_guitar_pro(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let song = GuitarProSong::load_from_file(&PathBuf::from(path))?;
+
+    if args.iter().any(|arg| arg == "--list-tracks") {
+        for (index, track) in song.tracks.iter().enumerate() {
+            println!("{}: {} ({} strings{})", index, track.name, track.tuning.len(), if track.is_percussion { ", percussion" } else { "" });
+        }
+        return Ok(())
+    }
+
+    let track_index = match flag_value(args, "--track") {
+        Some(track) => track.parse::<usize>()?,
+        None => song.default_track_index().ok_or("the file has no six string guitar track")?,
+    };
+
+    write_import(&song.to_imported_song(track_index)?, args)
+}
+
+/// Tuning to finger the notes on: the one passed as a flag, otherwise the tuning of the song that is imported into
+fn import_tuning(args: &[String]) -> Result<[String; 6], Box<dyn Error>> {
+    if let Some(tuning) = flag_value(args, "--tuning") {
+        let notes: Vec<String> = tuning.split(',').map(|note| note.trim().to_string()).collect();
+        return notes.try_into().map_err(|_| "the tuning needs exactly 6 notes, lowest string first".into())
+    }
+
+    if let Some(uuid) = flag_value(args, "--song") {
+        let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(uuid);
+        return Ok(SongMetadata::load_from_file(&song_directory.join("metadata.json"))?.tuning)
+    }
+
+    Ok(STANDARD_TUNING.map(|note| note.to_string()))
+}
+
+fn import_midi(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let song = MidiSong::load_from_file(&PathBuf::from(path))?;
+
+    if args.iter().any(|arg| arg == "--list-tracks") {
+        for (index, track) in song.tracks.iter().enumerate() {
+            let channels: Vec<String> = track.channels.iter().map(|channel| (channel + 1).to_string()).collect();
+            println!("{}: {} ({} notes, channels {})", index, track.name, track.note_count, channels.join(", "));
+        }
+        return Ok(())
+    }
+
+    let track_index = match flag_value(args, "--track") {
+        Some(track) => track.parse::<usize>()?,
+        None => song.default_track_index().ok_or("the file has no tracks with notes")?,
+    };
+    // Channels are numbered from 1 like in most MIDI software
+    let channel = match flag_value(args, "--channel") {
+        Some(channel) => Some(channel.parse::<u8>()?.checked_sub(1).filter(|&channel| channel < 16).ok_or("the channel should be between 1 and 16")?),
+        None => None,
+    };
+
+    write_import(&song.to_imported_song(track_index, channel, &import_tuning(args)?)?, args)
+}
+
+fn import_music_xml(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let song = MusicXmlSong::load_from_file(&PathBuf::from(path))?;
+
+    if args.iter().any(|arg| arg == "--list-parts") {
+        for (index, part) in song.parts.iter().enumerate() {
+            println!("{}: {}{}", index, part.name, if part.has_tablature { "" } else { " (no tablature)" });
+        }
+        return Ok(())
+    }
+
+    let part_index = match flag_value(args, "--part") {
+        Some(part) => part.parse::<usize>()?,
+        None => song.default_part_index().ok_or("the score has no part with tablature")?,
+    };
+
+    write_import(&song.to_imported_song(part_index)?, args)
+}
+
+fn export_music_xml_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (Some(uuid), Some(output_path)) = (args.first(), args.get(1)) else {
+        return Err(USAGE.into())
+    };
+    // Only used for charts without a tempo map
+    let bpm = flag_value(args, "--bpm").map(|bpm| bpm.parse::<f64>()).transpose()?.unwrap_or(120.0);
+
+    let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(uuid);
+    let song_metadata = SongMetadata::load_from_file(&song_directory.join("metadata.json"))?;
+    let chart = Chart::load_from_file(&song_directory.join(CHART_FILENAME))?;
+
+    fs::write(output_path, export_music_xml(&chart, &song_metadata, bpm)?)?;
+    println!("exported {} notes to {}", chart.notes.len(), output_path);
+
+    Ok(())
+}
+
+fn import_tab(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let import = import_ascii_tab(&PathBuf::from(path))?;
+
+    for issue in &import.issues {
+        eprintln!("warning: {}", issue);
+    }
+    println!("read {} notes with {} warnings", import.imported_song.chart.notes.len(), import.issues.len());
+
+    // Only report the issues, so the tab can be fixed before importing it
+    if args.iter().any(|arg| arg == "--check") {
+        return Ok(())
+    }
+
+    write_import(&import.imported_song, args)
+}
+
+/// Whether a song has a main chart or lists any other chart
+fn has_charts(song_directory: &Path) -> bool {
+    song_directory.join(CHART_FILENAME).exists()
+        || SongMetadata::load_from_file(&song_directory.join("metadata.json")).is_ok_and(|song_metadata| !song_metadata.charts.is_empty())
+}
+
+/// Draft charts for the given songs, or for every song in the library that has no chart yet
+fn transcribe(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let songs_dir = get_songs_dir().ok_or("songs directory is not available")?;
+    let overwrite = args.iter().any(|arg| arg == "--overwrite");
+    // An isolated guitar stem in the song directory transcribes much better than the full mix
+    let stem = flag_value(args, "--stem");
+
+    let uuids: Vec<&String> = args.iter().enumerate()
+        .filter(|(index, arg)| !arg.starts_with("--") && (*index == 0 || args[index - 1] != "--stem"))
+        .map(|(_, arg)| arg)
+        .collect();
+    let song_directories: Vec<PathBuf> = if uuids.is_empty() {
+        fs::read_dir(&songs_dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_dir() && (overwrite || !has_charts(path)))
+            .collect()
+    } else {
+        uuids.iter().map(|uuid| songs_dir.join(uuid)).collect()
+    };
+
+    let mut failures = 0;
+    for song_directory in &song_directories {
+        let name = song_directory.file_name().unwrap_or_default().to_string_lossy();
+        let audio_filename = stem.filter(|stem| song_directory.join(stem).is_file());
+
+        match transcribe_song(song_directory, audio_filename, overwrite) {
+            Ok(chart) => println!("{}: {} notes from {}", name, chart.notes.len(), audio_filename.unwrap_or("the song audio")),
+            Err(err) => {
+                eprintln!("{}: {}", name, err);
+                failures += 1;
+            }
+        }
+    }
+    println!("transcribed {} of {} songs", song_directories.len() - failures, song_directories.len());
+
+    if failures > 0 && failures == song_directories.len() {
+        return Err("no songs could be transcribed".into())
+    }
+
+    Ok(())
+}
+
+/// Writes the tempo map of a song estimated from its audio into its chart
+fn detect_tempo(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let uuid = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let beats_per_measure = flag_value(args, "--beats-per-measure").map(|beats| beats.parse::<u32>()).transpose()?.unwrap_or(4);
+
+    let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(uuid);
+    let estimate = detect_song_tempo(&song_directory, beats_per_measure)?;
+    println!("{:.2} bpm, first downbeat at {:.3}s (confidence {:.2})", estimate.bpm, estimate.first_downbeat_seconds, estimate.confidence);
+
+    Ok(())
+}
+
+fn export_package_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let (Some(uuid), Some(output_path)) = (args.first(), args.get(1)) else {
+        return Err(USAGE.into())
+    };
+
+    let manifest = export_package(uuid, &PathBuf::from(output_path))?;
+    println!("exported {} files to {}", manifest.files.len(), output_path);
+
+    Ok(())
+}
+
+fn import_package(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let path = args.first().filter(|arg| !arg.starts_with("--")).ok_or(USAGE)?;
+    let package = SongPackage::load_from_file(&PathBuf::from(path))?;
+    let metadata = &package.manifest.metadata;
+    println!("{} - {} ({}), {} files", metadata.artists.join(", "), metadata.title, metadata.uuid, package.manifest.files.len());
+
+    // Only validate the package
+    if args.iter().any(|arg| arg == "--check") {
+        return Ok(())
+    }
+    if package.exists() && !args.iter().any(|arg| arg == "--replace") {
+        return Err("the song is already in the library, pass --replace to update it".into())
+    }
+
+    package.unpack()?;
+    println!("imported {}", metadata.uuid);
+
+    Ok(())
+}
+
+/// Reports the problems of the given songs, or of every folder in the songs directory
+fn validate(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let song_reports: Vec<SongReport> = if args.is_empty() {
+        let (_, report) = SongLibraryResource::scan_songs_dir()?;
+        report.songs
+    } else {
+        let songs_dir = get_songs_dir().ok_or("songs directory is not available")?;
+        args.iter()
+            .map(|uuid| validate_song_directory(&songs_dir.join(uuid)).2)
+            .filter(|song_report| !song_report.issues.is_empty())
+            .collect()
+    };
+
+    for song_report in &song_reports {
+        println!("{} ({})", song_report.name(), song_report.folder);
+        for issue in &song_report.issues {
+            println!("  {}: {}", if issue.is_fatal() { "error" } else { "warning" }, issue);
+        }
+    }
+
+    let unplayable = song_reports.iter().filter(|song_report| !song_report.is_playable()).count();
+    println!("{} songs with issues, {} can't be played", song_reports.len(), unplayable);
+    if unplayable > 0 {
+        return Err(format!("{} songs can't be played", unplayable).into())
+    }
+
+    Ok(())
+}
@@ -23,7 +23,7 @@ pub const STRING_COLORS: [Color; 6] = [
 
 pub const FRET_AMOUNT: usize = 25;
 
-// Y coordinate
+// Y coordinate of frets 1 through FRET_AMOUNT, fret 0 (open string) has no fixed position
 pub const FRET_CENTERS: [f32; FRET_AMOUNT] = [
     -14.4,
     -13.2,
@@ -52,4 +52,10 @@ pub const FRET_CENTERS: [f32; FRET_AMOUNT] = [
     14.4
 ];
 
+// Amount of frets an open string note spans around the hand position
+pub const OPEN_STRING_FRET_SPAN: usize = 4;
+
+// Height of a bend on the highway, used for the bend indicator and the pitch curve
+pub const BEND_HEIGHT_PER_SEMITONE: f32 = 0.3;
+
 pub const CAMERA_Y_RANGE: [f32; 2] = [-3.0, 25.6];
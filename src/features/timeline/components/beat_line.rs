@@ -0,0 +1,108 @@
+use bevy::{prelude::*, render::mesh::PlaneMeshBuilder};
+
+use crate::{constants::ingame::{FRET_AMOUNT, TIMELINE_LENGTH}, helpers::tempo_map::{GridLine, GridLineKind}, resources::{configuration::ConfigurationResource, song_loaded::SongLoadedResource}};
+
+use super::super::timeline::Notes;
+
+#[derive(Component)]
+pub struct BeatLine {
+    pub seconds: f32,
+}
+
+/// Meshes and materials shared by the beat and measure lines, created once when the highway is spawned
+#[derive(Resource)]
+pub struct BeatLineAssets {
+    beat_mesh: Handle<Mesh>,
+    beat_material: Handle<StandardMaterial>,
+    measure_mesh: Handle<Mesh>,
+    measure_material: Handle<StandardMaterial>,
+}
+
+impl BeatLineAssets {
+    pub fn new(configuration: &ConfigurationResource, meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        // Spans the neck from the first to the last fret
+        let half_length = (configuration.fret_center(FRET_AMOUNT) - configuration.fret_center(1)).abs() / 2.0 + 0.6;
+        let mut mesh = |half_width: f32| meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(half_width, half_length) }, subdivisions: 0 }.build());
+        let (beat_mesh, measure_mesh) = (mesh(0.02), mesh(0.05));
+        let mut material = |brightness: f32| materials.add(StandardMaterial {
+            base_color: Color::srgb(brightness, brightness, brightness),
+            perceptual_roughness: 0.9,
+            metallic: 0.0,
+            ..Default::default()
+        });
+
+        // Measure lines are thicker and brighter than beat lines
+        Self {
+            beat_mesh,
+            beat_material: material(0.35),
+            measure_mesh,
+            measure_material: material(0.7),
+        }
+    }
+}
+
+fn spawn_beat_line(
+    configuration: &Res<ConfigurationResource>,
+    builder: &mut ChildBuilder,
+    beat_line_assets: &BeatLineAssets,
+    grid_line: GridLine,
+) {
+    let (mesh, material) = match grid_line.kind {
+        GridLineKind::Measure => (&beat_line_assets.measure_mesh, &beat_line_assets.measure_material),
+        _ => (&beat_line_assets.beat_mesh, &beat_line_assets.beat_material),
+    };
+    let first_fret = configuration.fret_center(1);
+    let last_fret = configuration.fret_center(FRET_AMOUNT);
+
+    builder.spawn((PbrBundle {
+        mesh: mesh.clone(),
+        material: material.clone(),
+        transform: Transform {
+            translation: Vec3::new(-TIMELINE_LENGTH, (first_fret + last_fret) / 2.0, 0.0),
+            rotation: Quat::from_rotation_x(90_f32.to_radians()),
+            ..Default::default()
+        },
+        ..Default::default()
+    }, BeatLine { seconds: grid_line.seconds }));
+}
+
+/// Spawns the beat and measure lines of the tempo map that are approaching and moves them along with the notes
+pub fn update_beat_lines(
+    mut commands: Commands,
+    song_loaded: Res<SongLoadedResource>,
+    configuration: Res<ConfigurationResource>,
+    notes_wrapper_query: Query<Entity, With<Notes>>,
+    mut beat_lines_query: Query<(Entity, &BeatLine, &mut Transform)>,
+    beat_line_assets: Res<BeatLineAssets>,
+) {
+    let Some(song_progress) = &song_loaded.progress else { return };
+    let current_time = song_progress.timer.elapsed_secs();
+
+    for (entity, beat_line, mut transform) in beat_lines_query.iter_mut() {
+        // Passed lines, and lines that are out of reach after scrubbing back in the editor
+        if beat_line.seconds < current_time || beat_line.seconds - configuration.approach_rate > current_time {
+            commands.entity(entity).despawn_recursive();
+            continue
+        }
+
+        // Same position on the timeline as the head of a note starting at the line
+        let progress = 1.0 - (beat_line.seconds - current_time) / configuration.approach_rate;
+        transform.translation.x = (TIMELINE_LENGTH * progress) - TIMELINE_LENGTH;
+    }
+
+    // Charts without a tempo map have no known beats
+    if song_loaded.tempo_map.is_empty() {
+        return
+    }
+
+    let Ok(notes_wrapper) = notes_wrapper_query.get_single() else { return };
+    let grid_lines = song_loaded.tempo_map.grid_lines(current_time, current_time + configuration.approach_rate, 1);
+    for grid_line in grid_lines {
+        let exists = beat_lines_query.iter().any(|(_, beat_line, _)| (beat_line.seconds - grid_line.seconds).abs() < 0.0001);
+        if !exists {
+            commands.entity(notes_wrapper).with_children(|builder| {
+                spawn_beat_line(&configuration, builder, &beat_line_assets, grid_line);
+            });
+        }
+    }
+}
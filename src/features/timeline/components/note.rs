@@ -1,7 +1,10 @@
 
 use bevy::{prelude::*, render::mesh::PlaneMeshBuilder};
+use bevy_mod_billboard::{prelude::*, BillboardLockAxis};
 
-use crate::{constants::ingame::{FRET_CENTERS, STRING_CENTERS, STRING_COLORS, TIMELINE_LENGTH}, helpers::notes::NoteEvent, resources::{configuration::ConfigurationResource, song_loaded::SongLoadedResource}};
+use crate::{constants::ingame::{BEND_HEIGHT_PER_SEMITONE, FRET_CENTERS, OPEN_STRING_FRET_SPAN, STRING_COLORS, TIMELINE_LENGTH}, helpers::notes::{Legato, NoteEvent}, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource, song_loaded::{NoteEventWithScore, SongLoadedResource}}};
+
+use super::{pitch_track::{needs_pitch_tracking, technique_accuracy, NotePitchTrack}, sustain::{needs_sustain_scoring, NoteSustain}};
 
 #[derive(Event)]
 pub struct NoteTriggeredEvent(pub Note);
@@ -25,23 +28,114 @@ pub struct NoteFrontFadeout {
 #[derive(Component)]
 pub struct GuideStartMarker;
 
+const COLOR_SUSTAIN_LOST: Color = Color::srgb(0.3, 0.3, 0.3);
+
+// Amount of points used to draw the expected curve of a bend
+const BEND_CURVE_STEPS: usize = 16;
+
 #[derive(Component)]
 pub struct TrailMarker {
     length: f32
 }
 
+/// Y coordinate of a note, open strings are centered on the fret window around the hand position
+fn note_center(
+    configuration: &ConfigurationResource,
+    hand_position: &HandPositionResource,
+    note_event: &NoteEvent,
+) -> f32 {
+    if note_event.is_open_string() {
+        let (first_fret, last_fret) = hand_position.fret_window();
+        (configuration.fret_center(first_fret) + configuration.fret_center(last_fret)) / 2.0
+    } else {
+        configuration.fret_center(note_event.fret_index)
+    }
+}
+
+/// Bends in tab notation count whole tones: 1 semitone -> "½", 2 -> "full", 3 -> "1½", a quarter tone -> "¼"
+fn format_bend_semitones(semitones: f32) -> String {
+    let half_tones = semitones.round() as i32;
+
+    match half_tones {
+        ..=0 => "¼".to_string(),
+        1 => "½".to_string(),
+        2 => "full".to_string(),
+        _ if half_tones % 2 == 1 => format!("{}½", half_tones / 2),
+        _ => format!("{}", half_tones / 2),
+    }
+}
+
+/// Tab notation for the playing techniques of a note, e.g. "PM" or "b full ~"
+fn technique_label(note_event: &NoteEvent) -> Option<String> {
+    let mut parts: Vec<String> = vec![];
+
+    if let Some(legato) = note_event.legato {
+        parts.push(match legato {
+            Legato::HammerOn => "h".to_string(),
+            Legato::PullOff => "p".to_string(),
+        });
+    }
+    if let Some(bend) = &note_event.bend {
+        parts.push(format!("b{}", format_bend_semitones(bend.semitones)));
+    }
+    if let Some(slide_to_fret) = note_event.slide_to_fret {
+        let direction = if slide_to_fret > note_event.fret_index { "/" } else { "\\" };
+        parts.push(format!("{}{}", direction, slide_to_fret));
+    }
+    if note_event.palm_mute {
+        parts.push("PM".to_string());
+    }
+    if note_event.harmonic {
+        parts.push("<>".to_string());
+    }
+    if note_event.vibrato {
+        parts.push("~".to_string());
+    }
+    if note_event.tremolo {
+        parts.push("TP".to_string());
+    }
+
+    if parts.is_empty() {
+        return None
+    }
+
+    Some(parts.join(" "))
+}
+
 pub fn spawn_note(
     configuration: &Res<ConfigurationResource>,
+    hand_position: &Res<HandPositionResource>,
     builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
     note_event: NoteEvent,
 ) {
     // let length = (note_event.duration_seconds * configuration.approach_rate)/2.0;
     let length = note_event.duration_seconds / configuration.approach_rate * TIMELINE_LENGTH / 2.0;
+    let string_center = configuration.string_center(note_event.string_index);
+
+    // Open strings are drawn as a bar across the whole fret window
+    let fret_width = FRET_CENTERS[1] - FRET_CENTERS[0];
+    let note_width = if note_event.is_open_string() {
+        fret_width * OPEN_STRING_FRET_SPAN as f32
+    } else { fret_width };
+
+    // Palm muted notes get a darker, flatter head and harmonics a diamond shaped head
+    let head_color = if note_event.palm_mute {
+        STRING_COLORS[note_event.string_index].mix(&Color::BLACK, 0.4)
+    } else { STRING_COLORS[note_event.string_index] };
+    let (head_mesh, head_rotation) = if note_event.harmonic {
+        (Cuboid::new(0.6, 0.6, 0.6), Quat::from_rotation_x(45_f32.to_radians()))
+    } else if note_event.palm_mute {
+        (Cuboid::new(0.6, note_width - 0.1, 0.14), Quat::IDENTITY)
+    } else {
+        (Cuboid::new(0.6, note_width - 0.1, 0.26), Quat::IDENTITY)
+    };
+
     builder.spawn((SpatialBundle {
         transform: Transform {
-            translation: Vec3 { x: -TIMELINE_LENGTH, y: FRET_CENTERS[note_event.fret_index], z: 0.0 },
+            translation: Vec3 { x: -TIMELINE_LENGTH, y: note_center(configuration, hand_position, &note_event), z: 0.0 },
             ..Default::default()
         },
         ..Default::default()
@@ -49,20 +143,86 @@ pub fn spawn_note(
 
         // Note head
         builder.spawn((PbrBundle {
-            mesh: meshes.add(Mesh::from(Cuboid::new(0.6, 1.1, 0.26))),
+            mesh: meshes.add(Mesh::from(head_mesh)),
             material: materials.add(StandardMaterial {
-                base_color: STRING_COLORS[note_event.string_index],
+                base_color: head_color,
                 alpha_mode: AlphaMode::Blend,
                 perceptual_roughness: 0.9,
                 metallic: 0.0,
                 ..Default::default()
             }),
             transform: Transform {
-                translation: Vec3 { x: 0.0, y: 0.0, z: STRING_CENTERS[note_event.string_index] },
+                translation: Vec3 { x: 0.0, y: 0.0, z: string_center },
+                rotation: head_rotation,
                 ..Default::default()
             },
             ..default()
-        }, NoteHead { initial_color: STRING_COLORS[note_event.string_index] }));
+        }, NoteHead { initial_color: head_color }));
+
+        // Bend indicator, grows with the amount of semitones to bend
+        if let Some(bend) = &note_event.bend {
+            let height = 0.2 + bend.semitones * BEND_HEIGHT_PER_SEMITONE;
+            builder.spawn(PbrBundle {
+                mesh: meshes.add(Mesh::from(Cuboid::new(0.1, 0.1, height))),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::WHITE,
+                    perceptual_roughness: 0.9,
+                    metallic: 0.0,
+                    ..Default::default()
+                }),
+                transform: Transform {
+                    translation: Vec3::new(0.0, 0.0, string_center + 0.13 + height / 2.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+
+            // Expected bend curve along the trail, the measured pitch is drawn on top of this while playing
+            for step in 0..=BEND_CURVE_STEPS {
+                let position = step as f32 / BEND_CURVE_STEPS as f32;
+                builder.spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(Cuboid::new(0.05, 0.05, 0.05))),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::srgba(1.0, 1.0, 1.0, 0.5),
+                        alpha_mode: AlphaMode::Blend,
+                        unlit: true,
+                        ..Default::default()
+                    }),
+                    transform: Transform {
+                        translation: Vec3::new(-length * 2.0 * position, 0.0, string_center + bend.semitones_at(position) * BEND_HEIGHT_PER_SEMITONE),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            }
+        }
+
+        // Technique label
+        if let Some(label) = technique_label(&note_event) {
+            builder.spawn((BillboardTextBundle {
+                transform: Transform {
+                    translation: Vec3 { x: 0.0, y: 0.0, z: string_center + 0.45 },
+                    rotation: configuration.mirror_rotation(Quat { w: 0.5, x: 0.5, y: -0.5, z: -0.5 }),
+                    scale: Vec3::splat(0.0085),
+                    ..Default::default()
+                },
+                text: Text::from_sections([
+                    TextSection {
+                        value: label,
+                        style: TextStyle {
+                            font_size: 28.0,
+                            font: asset_server.load("fonts/IBMPlexMono-Bold.ttf"),
+                            color: Color::WHITE,
+                        },
+                    },
+                ])
+                .with_justify(JustifyText::Center),
+                ..default()
+            }, BillboardLockAxis {
+                rotation: true,
+                ..default()
+            }));
+        }
 
         // Note trail
         builder.spawn((PbrBundle {
@@ -74,7 +234,7 @@ pub fn spawn_note(
                 ..Default::default()
             }),
             transform: Transform {
-                translation: Vec3::new(-length, 0.0, STRING_CENTERS[note_event.string_index]),
+                translation: Vec3::new(-length, 0.0, string_center),
                 rotation: Quat::from_rotation_x(90_f32.to_radians()),
                 ..Default::default()
             },
@@ -82,10 +242,11 @@ pub fn spawn_note(
         }, TrailMarker { length }));
 
 
-        if note_event.string_index > 0 {
+        // Guide markers connect notes above the bottom of the highway to the fret board
+        if string_center > 0.0 {
             // Guide start
             builder.spawn((PbrBundle {
-                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(STRING_CENTERS[note_event.string_index]/2.0, 0.04) }, subdivisions: 0 }.build()),
+                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(string_center/2.0, 0.04) }, subdivisions: 0 }.build()),
                 material: materials.add(StandardMaterial {
                     base_color: Color::srgb(0.4, 0.4, 0.4),
                     perceptual_roughness: 0.9,
@@ -93,14 +254,14 @@ pub fn spawn_note(
                     ..Default::default()
                 }),
                 transform: Transform {
-                    translation: Vec3::new(0.0, 0.0, STRING_CENTERS[note_event.string_index]/2.0),
+                    translation: Vec3::new(0.0, 0.0, string_center/2.0),
                     rotation: Quat::from_rotation_y(90_f32.to_radians()),
                     ..Default::default()
                 },
                 ..Default::default()
             }, GuideStartMarker));
             builder.spawn((PbrBundle {
-                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new((FRET_CENTERS[1]-FRET_CENTERS[0])/2.0, 0.04) }, subdivisions: 0 }.build()),
+                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(note_width/2.0, 0.04) }, subdivisions: 0 }.build()),
                 material: materials.add(StandardMaterial {
                     base_color: Color::srgb(0.4, 0.4, 0.4),
                     perceptual_roughness: 0.9,
@@ -117,7 +278,7 @@ pub fn spawn_note(
 
             // Guide end
             builder.spawn(PbrBundle {
-                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(STRING_CENTERS[note_event.string_index]/2.0, 0.04) }, subdivisions: 0 }.build()),
+                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(string_center/2.0, 0.04) }, subdivisions: 0 }.build()),
                 material: materials.add(StandardMaterial {
                     base_color: Color::srgb(0.4, 0.4, 0.4),
                     perceptual_roughness: 0.9,
@@ -125,14 +286,14 @@ pub fn spawn_note(
                     ..Default::default()
                 }),
                 transform: Transform {
-                    translation: Vec3::new(-length*2.0, 0.0, STRING_CENTERS[note_event.string_index]/2.0),
+                    translation: Vec3::new(-length*2.0, 0.0, string_center/2.0),
                     rotation: Quat::from_rotation_z((90_f32).to_radians()),
                     ..Default::default()
                 },
                 ..Default::default()
             });
             builder.spawn(PbrBundle {
-                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new((FRET_CENTERS[1]-FRET_CENTERS[0])/2.0, 0.04) }, subdivisions: 0 }.build()),
+                mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(note_width/2.0, 0.04) }, subdivisions: 0 }.build()),
                 material: materials.add(StandardMaterial {
                     base_color: Color::srgb(0.4, 0.4, 0.4),
                     perceptual_roughness: 0.9,
@@ -154,25 +315,41 @@ pub fn update_note(
     time: Res<Time>,
     mut commands: Commands,
     configuration: Res<ConfigurationResource>,
+    hand_position: Res<HandPositionResource>,
     mut event_song_note_triggered: EventReader<NoteTriggeredEvent>,
     front_query: Query<(Entity, &NoteHead), With<NoteHead>>,
     guide_start_marker_query: Query<Entity, With<GuideStartMarker>>,
-    mut notes_query: Query<(Entity, &mut Note, &mut Transform, &Children), (With<Note>, Without<NoteFrontFadeout>, Without<NoteHead>, Without<TrailMarker>)>,
+    mut notes_query: Query<(Entity, &mut Note, &mut Transform, &Children, Option<&NotePitchTrack>, Option<&NoteSustain>), (With<Note>, Without<NoteFrontFadeout>, Without<NoteHead>, Without<TrailMarker>)>,
     mut front_with_fadeout_query: Query<(Entity, &NoteHead, &mut NoteFrontFadeout, &mut Transform), (With<NoteHead>, With<NoteFrontFadeout>, Without<TrailMarker>)>,
     mut trail_marker_query: Query<(&TrailMarker, &Handle<Mesh>, &mut Transform), (With<TrailMarker>, Without<NoteFrontFadeout>, Without<NoteHead>)>,
     material_handle_query: Query<&mut Handle<StandardMaterial>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
-    song_loaded: Res<SongLoadedResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
 ) {
     let song_elapsed_seconds = song_loaded.progress.as_ref().unwrap().timer.elapsed_secs();
-    for (entity, note, mut transform, children) in notes_query.iter_mut() {
+    for (entity, note, mut transform, children, pitch_track, sustain) in notes_query.iter_mut() {
         // Update note position
         // Progress of the head on the timeline. 0 is at the end of the timeline, 1 is against the fret board
         let progress = 1.0 - ((note.note_event.start_time_seconds - song_elapsed_seconds) / configuration.approach_rate).min(1.0);
         let new_position = (TIMELINE_LENGTH * progress) - TIMELINE_LENGTH;
         transform.translation.x = new_position;
 
+        // Open strings follow the hand position until they reach the fret board
+        if note.note_event.is_open_string() && note.note_event.start_time_seconds >= song_elapsed_seconds {
+            transform.translation.y = note_center(&configuration, &hand_position, &note.note_event);
+        }
+
+        // Slides move towards their target fret over the duration of the note
+        if let Some(slide_to_fret) = note.note_event.slide_to_fret {
+            if note.note_event.start_time_seconds < song_elapsed_seconds {
+                let t = ((song_elapsed_seconds - note.note_event.start_time_seconds) / note.note_event.duration_seconds).clamp(0.0, 1.0);
+                let from = note_center(&configuration, &hand_position, &note.note_event);
+                let to = configuration.fret_center(slide_to_fret);
+                transform.translation.y = from + (to - from) * t;
+            }
+        }
+
         // Song notes past the fret board
         if note.note_event.start_time_seconds < song_elapsed_seconds {
 
@@ -195,10 +372,30 @@ pub fn update_note(
                             *mesh = PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(new_length, 0.1) }, subdivisions: 0 }.build();
                         }
                     }
+
+                    // Grey out the rest of the trail once the sustain is lost
+                    if let (Ok(_), Some(sustain)) = (trail_marker_query.get(child), sustain) {
+                        if !sustain.held {
+                            if let Ok(material_handle) = material_handle_query.get(child) {
+                                if let Some(material) = materials.get_mut(material_handle) {
+                                    material.base_color = COLOR_SUSTAIN_LOST;
+                                }
+                            }
+                        }
+                    }
                 }
             }
 
             if note.note_event.start_time_seconds + note.note_event.duration_seconds < song_elapsed_seconds {
+                if let Some(song_progress) = song_loaded.progress.as_mut() {
+                    song_progress.notes_with_score.push(NoteEventWithScore {
+                        missed: !note.triggered,
+                        note_event: note.note_event.clone(),
+                        technique_accuracy: pitch_track.and_then(|pitch_track| technique_accuracy(&note.note_event, &pitch_track.track)),
+                        sustain_ratio: sustain.map(|sustain| sustain.held_ratio(&note.note_event)),
+                    });
+                }
+
                 commands.entity(entity).despawn_recursive();
             }
 
@@ -225,8 +422,16 @@ pub fn update_note(
 
     // Process trigger events
     for event in event_song_note_triggered.read() {
-        for (_, mut note, _, children) in notes_query.iter_mut() {
+        for (entity, mut note, _, children, _, _) in notes_query.iter_mut() {
             if note.note_event.equals(&event.0.note_event) {
+                // Bends and vibrato are judged on the pitch over the whole duration of the note
+                if !note.triggered && needs_pitch_tracking(&note.note_event) {
+                    commands.entity(entity).insert(NotePitchTrack::default());
+                }
+                if !note.triggered && needs_sustain_scoring(&note.note_event) {
+                    commands.entity(entity).insert(NoteSustain::default());
+                }
+
                 for &child in children.iter() {
                     // Fade out the head
                     if let Ok((song_note_front, _)) = front_query.get(child) {
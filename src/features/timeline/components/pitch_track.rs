@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+
+use crate::{constants::ingame::{BEND_HEIGHT_PER_SEMITONE, TIMELINE_LENGTH}, helpers::{notes::NoteEvent, pitch_tracking::{PitchSample, PitchTrack, BEND_TOLERANCE_CENTS}, tuning::frequency_from_note_number}, resources::{configuration::ConfigurationResource, input_device::InputDeviceResource, song_loaded::SongLoadedResource}};
+
+use super::note::Note;
+
+const COLOR_PITCH_GOOD: Color = Color::srgb(0.06, 0.88, 0.07);
+const COLOR_PITCH_BAD: Color = Color::srgb(0.88, 0.06, 0.07);
+
+/// Added to bend and vibrato notes once they are triggered
+#[derive(Component, Default)]
+pub struct NotePitchTrack {
+    pub track: PitchTrack,
+}
+
+#[derive(Component)]
+pub struct PitchCurveMarker;
+
+/// Mesh and materials shared by every pitch sample, created once when the song starts
+#[derive(Resource)]
+pub struct PitchCurveAssets {
+    mesh: Handle<Mesh>,
+    material_good: Handle<StandardMaterial>,
+    material_bad: Handle<StandardMaterial>,
+}
+
+impl PitchCurveAssets {
+    pub fn new(meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) -> Self {
+        let mut material = |color: Color| materials.add(StandardMaterial {
+            base_color: color,
+            unlit: true,
+            ..Default::default()
+        });
+
+        Self {
+            mesh: meshes.add(Mesh::from(Cuboid::new(0.08, 0.08, 0.08))),
+            material_good: material(COLOR_PITCH_GOOD),
+            material_bad: material(COLOR_PITCH_BAD),
+        }
+    }
+}
+
+/// Whether a note needs its pitch tracked while it sustains
+pub fn needs_pitch_tracking(note_event: &NoteEvent) -> bool {
+    note_event.bend.is_some() || note_event.vibrato
+}
+
+/// 0 to 1 accuracy of the bend and/or vibrato of a note
+pub fn technique_accuracy(note_event: &NoteEvent, pitch_track: &PitchTrack) -> Option<f32> {
+    let mut accuracy: Option<f32> = None;
+
+    if let Some(bend) = &note_event.bend {
+        accuracy = Some(pitch_track.bend_accuracy(bend));
+    }
+    if note_event.vibrato {
+        let vibrato_accuracy = pitch_track.vibrato_accuracy(note_event.duration_seconds);
+        accuracy = Some(accuracy.unwrap_or(1.0) * vibrato_accuracy);
+    }
+
+    accuracy
+}
+
+pub fn update_pitch_track(
+    mut commands: Commands,
+    configuration: Res<ConfigurationResource>,
+    input_device: Res<InputDeviceResource>,
+    song_loaded: Res<SongLoadedResource>,
+    mut notes_query: Query<(Entity, &Note, &mut NotePitchTrack)>,
+    pitch_curve_assets: Res<PitchCurveAssets>,
+) {
+    if let (Some(song_progress), Some(song_metadata), Some(audio_stream)) = (&song_loaded.progress, &song_loaded.metadata, &input_device.audio_stream_main) {
+        let elapsed_secs = song_progress.timer.elapsed_secs();
+
+        // The pitch is only detected once per frame, and only if a note is sustaining
+        let mut frequency: Option<Option<f32>> = None;
+
+        for (entity, note, mut pitch_track) in notes_query.iter_mut() {
+            let note_event = &note.note_event;
+            if !note.triggered || elapsed_secs < note_event.start_time_seconds || elapsed_secs > note_event.start_time_seconds + note_event.duration_seconds {
+                continue
+            }
+
+            if let (Some(midi_pitch), Some(frequency)) = (note_event.midi_pitch(&song_metadata.tuning), *frequency.get_or_insert_with(|| audio_stream.get_pitch())) {
+                let cents = 1200.0 * (frequency / frequency_from_note_number(midi_pitch as i32)).log2();
+                let position = (elapsed_secs - note_event.start_time_seconds) / note_event.duration_seconds;
+
+                if !pitch_track.track.push(position, cents) {
+                    continue
+                }
+
+                // Draw the measured pitch where the trail is being consumed
+                let sample = PitchSample { position, cents };
+                let is_accurate = match &note_event.bend {
+                    Some(bend) => PitchTrack::is_on_bend(bend, &sample),
+                    None => cents.abs() <= BEND_TOLERANCE_CENTS * 2.0,
+                };
+                let trail_length = note_event.duration_seconds / configuration.approach_rate * TIMELINE_LENGTH;
+
+                commands.entity(entity).with_children(|builder| {
+                    builder.spawn((PbrBundle {
+                        mesh: pitch_curve_assets.mesh.clone(),
+                        material: if is_accurate { pitch_curve_assets.material_good.clone() } else { pitch_curve_assets.material_bad.clone() },
+                        transform: Transform {
+                            translation: Vec3::new(
+                                -trail_length * position,
+                                0.0,
+                                configuration.string_center(note_event.string_index) + cents / 100.0 * BEND_HEIGHT_PER_SEMITONE,
+                            ),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    }, PitchCurveMarker));
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+
+use crate::{helpers::{notes::NoteEvent, tuning::frequency_from_note_number}, resources::{input_device::InputDeviceResource, song_loaded::SongLoadedResource}};
+
+use super::note::Note;
+
+// Notes shorter than this are only scored on their attack
+pub const MIN_SUSTAIN_SECONDS: f32 = 0.5;
+const SUSTAIN_POINTS_PER_SECOND: f32 = 50.0;
+// Below this amplitude the string is considered muted
+const SUSTAIN_MIN_AMPLITUDE: f32 = 0.5;
+const SUSTAIN_TOLERANCE_CENTS: f32 = 40.0;
+// Vibrato moves the pitch around on purpose, so it gets more room
+const SUSTAIN_VIBRATO_TOLERANCE_CENTS: f32 = 120.0;
+// How long the note may be off before the sustain counts as lost
+const SUSTAIN_GRACE_SECONDS: f32 = 0.15;
+
+/// Added to long notes once they are triggered
+#[derive(Component)]
+pub struct NoteSustain {
+    pub held: bool,
+    pub points: f32,
+    pub held_seconds: f32,
+    off_seconds: f32,
+}
+
+impl Default for NoteSustain {
+    fn default() -> Self {
+        NoteSustain { held: true, points: 0.0, held_seconds: 0.0, off_seconds: 0.0 }
+    }
+}
+
+impl NoteSustain {
+    /// 0 to 1, how much of the sustain was held
+    pub fn held_ratio(&self, note_event: &NoteEvent) -> f32 {
+        (self.held_seconds / note_event.duration_seconds).min(1.0)
+    }
+}
+
+pub fn needs_sustain_scoring(note_event: &NoteEvent) -> bool {
+    note_event.duration_seconds >= MIN_SUSTAIN_SECONDS
+}
+
+/// Cents the note should be at, following bends and slides over the duration of the note
+fn expected_cents(note_event: &NoteEvent, position: f32) -> f32 {
+    if let Some(bend) = &note_event.bend {
+        return bend.semitones_at(position) * 100.0
+    }
+    if let Some(slide_to_fret) = note_event.slide_to_fret {
+        return (slide_to_fret as f32 - note_event.fret_index as f32) * 100.0 * position
+    }
+
+    0.0
+}
+
+pub fn update_sustain(
+    time: Res<Time>,
+    input_device: Res<InputDeviceResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    mut notes_query: Query<(&Note, &mut NoteSustain)>,
+) {
+    let tuning = song_loaded.metadata.as_ref().map(|song_metadata| song_metadata.tuning.clone());
+
+    if let (Some(song_progress), Some(tuning), Some(audio_stream)) = (song_loaded.progress.as_mut(), tuning, &input_device.audio_stream_main) {
+        let elapsed_secs = song_progress.timer.elapsed_secs();
+        let delta_secs = time.delta_seconds();
+
+        // The input is only analysed once per frame, and only if a note is sustaining
+        let mut amplitude: Option<f32> = None;
+        let mut frequency: Option<Option<f32>> = None;
+
+        for (note, mut sustain) in notes_query.iter_mut() {
+            let note_event = &note.note_event;
+            if !sustain.held || elapsed_secs < note_event.start_time_seconds || elapsed_secs > note_event.start_time_seconds + note_event.duration_seconds {
+                continue
+            }
+
+            let is_ringing = *amplitude.get_or_insert_with(|| audio_stream.get_amplitude()) > SUSTAIN_MIN_AMPLITUDE;
+            let is_on_pitch = is_ringing && match (note_event.midi_pitch(&tuning), *frequency.get_or_insert_with(|| audio_stream.get_pitch())) {
+                (Some(midi_pitch), Some(frequency)) => {
+                    let cents = 1200.0 * (frequency / frequency_from_note_number(midi_pitch as i32)).log2();
+                    let position = (elapsed_secs - note_event.start_time_seconds) / note_event.duration_seconds;
+                    let tolerance = if note_event.vibrato { SUSTAIN_VIBRATO_TOLERANCE_CENTS } else { SUSTAIN_TOLERANCE_CENTS };
+
+                    (cents - expected_cents(note_event, position)).abs() <= tolerance
+                }
+                // Without a pitch reading (e.g. harmonics or noisy input) only the amplitude is judged
+                _ => true,
+            };
+
+            if is_on_pitch {
+                sustain.off_seconds = 0.0;
+                sustain.held_seconds += delta_secs;
+                sustain.points += SUSTAIN_POINTS_PER_SECOND * delta_secs;
+                song_progress.score += SUSTAIN_POINTS_PER_SECOND * delta_secs;
+            } else {
+                sustain.off_seconds += delta_secs;
+                if sustain.off_seconds > SUSTAIN_GRACE_SECONDS {
+                    sustain.held = false;
+                }
+            }
+        }
+    }
+}
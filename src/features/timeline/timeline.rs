@@ -1,6 +1,6 @@
 use bevy::{prelude::*, render::mesh::PlaneMeshBuilder};
 
-use crate::{constants::ingame::{FRET_CENTERS, TIMELINE_LENGTH}, resources::{configuration::ConfigurationResource, song_loaded::SongLoadedResource}};
+use crate::{constants::ingame::{FRET_AMOUNT, TIMELINE_LENGTH}, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource, song_loaded::SongLoadedResource}};
 
 use super::components::note::{spawn_note, Note};
 
@@ -8,6 +8,7 @@ use super::components::note::{spawn_note, Note};
 pub struct Notes;
 
 pub fn spawn_timeline(
+    configuration: &Res<ConfigurationResource>,
     builder: &mut ChildBuilder,
     meshes: &mut ResMut<Assets<Mesh>>,
     materials: &mut ResMut<Assets<StandardMaterial>>,
@@ -18,8 +19,10 @@ pub fn spawn_timeline(
     }, Notes));
 
     // Spawn fret guides
-    for (fret_index, fret_center) in FRET_CENTERS.iter().enumerate() {
-        if fret_index == 0 {
+    let fret_direction = configuration.fret_direction();
+    for fret_index in 1..=FRET_AMOUNT {
+        let fret_center = configuration.fret_center(fret_index);
+        if fret_index == 1 {
             builder.spawn(PbrBundle {
                 mesh: meshes.add(PlaneMeshBuilder { plane: Plane3d { normal: Dir3::Y, half_size: Vec2::new(30.0, 0.02) }, subdivisions: 0 }.build()),
                 material: materials.add(StandardMaterial {
@@ -30,7 +33,7 @@ pub fn spawn_timeline(
                     ..Default::default()
                 }),
                 transform: Transform {
-                    translation: Vec3::new(-30.0, fret_center - 0.6, 0.0),
+                    translation: Vec3::new(-30.0, fret_center - 0.6 * fret_direction, 0.0),
                     // rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
                     rotation: Quat::from_rotation_x(90_f32.to_radians()),
                     scale: Vec3::new(1.0, 1.0, 1.0),
@@ -51,7 +54,7 @@ pub fn spawn_timeline(
                     ..Default::default()
                 }),
                 transform: Transform {
-                    translation: Vec3::new(-30.0, fret_center + 0.6, 0.0),
+                    translation: Vec3::new(-30.0, fret_center + 0.6 * fret_direction, 0.0),
                     // rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
                     rotation: Quat::from_rotation_x(90_f32.to_radians()),
                     scale: Vec3::new(1.0, 1.0, 1.0),
@@ -66,10 +69,12 @@ pub fn spawn_timeline(
 
 pub fn update_timeline(
     mut commands: Commands,
-    song_loaded: Res<SongLoadedResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    asset_server: Res<AssetServer>,
     notes_wrapper_query: Query<Entity, With<Notes>>,
     mut notes_query: Query<(Entity, &mut Transform, &Note)>,
     configuration: Res<ConfigurationResource>,
+    hand_position: Res<HandPositionResource>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
@@ -91,13 +96,20 @@ pub fn update_timeline(
                 break
             }
 
+            // Notes above the level of their phrase are left out
+            if let Some(dynamic_difficulty) = song_loaded.dynamic_difficulty.as_mut() {
+                if !dynamic_difficulty.is_playable(&note_event) {
+                    continue
+                }
+            }
+
             let existing_song_note = notes_query.iter_mut().find(|(_, _, note)| {
                 note.note_event.equals(&note_event)
             });
 
             if existing_song_note.is_none() {
                 commands.entity(notes_wrapper).with_children(|builder| {
-                    spawn_note(&configuration, builder, &mut meshes, &mut materials, note_event);
+                    spawn_note(&configuration, &hand_position, builder, &asset_server, &mut meshes, &mut materials, note_event);
                 });
             }
         }
@@ -0,0 +1,51 @@
+use std::{error::Error, fs::File, path::Path};
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::{formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint};
+
+/// What the header of an audio file says about it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioInfo {
+    pub duration_seconds: f32,
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+impl AudioInfo {
+    /// Reads the header of an audio file, without decoding the audio
+    /// Files whose header doesn't say how long they are are measured by walking over their packets
+    pub fn read_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let media_source_stream = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+            hint.with_extension(extension);
+        }
+
+        let probed = symphonia::default::get_probe().format(&hint, media_source_stream, &FormatOptions::default(), &MetadataOptions::default())?;
+        let mut format = probed.format;
+        let track = format.default_track().ok_or("the file has no audio track")?;
+        let track_id = track.id;
+        let codec_params = track.codec_params.clone();
+
+        let sample_rate = codec_params.sample_rate.ok_or("the file has no sample rate")?;
+        let channels = codec_params.channels.map(|channels| channels.count()).unwrap_or(1);
+        let frames = match codec_params.n_frames {
+            Some(frames) => frames,
+            None => {
+                let mut frames = 0;
+                while let Ok(packet) = format.next_packet() {
+                    if packet.track_id() == track_id {
+                        frames += packet.dur;
+                    }
+                }
+                frames
+            }
+        };
+
+        Ok(Self {
+            duration_seconds: frames as f32 / sample_rate as f32,
+            sample_rate,
+            channels,
+        })
+    }
+}
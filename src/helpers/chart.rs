@@ -0,0 +1,182 @@
+use std::{error::Error, fs::File, io::Read, path::Path};
+
+use bevy::log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::{notes::NoteEvent, tempo_map::{GridLineKind, TempoMap}};
+
+pub const CHART_FILENAME: &str = "chart.json";
+
+/// File a chart of a song is stored in, the main chart when no arrangement and difficulty is given
+pub fn chart_filename(song_chart: Option<SongChart>) -> String {
+    song_chart.map(|song_chart| song_chart.filename()).unwrap_or(CHART_FILENAME.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+    Expert,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard, Difficulty::Expert];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+
+    pub fn harder(&self) -> Difficulty {
+        Difficulty::ALL[(*self as usize + 1).min(Difficulty::ALL.len() - 1)]
+    }
+
+    pub fn easier(&self) -> Difficulty {
+        Difficulty::ALL[(*self as usize).saturating_sub(1)]
+    }
+
+    /// "chart_easy.json", the file a chart of this difficulty is stored in
+    pub fn chart_filename(&self) -> String {
+        format!("chart_{}.json", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arrangement {
+    Lead,
+    Rhythm,
+    Bass,
+}
+
+impl Arrangement {
+    pub const ALL: [Arrangement; 3] = [Arrangement::Lead, Arrangement::Rhythm, Arrangement::Bass];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Arrangement::Lead => "lead",
+            Arrangement::Rhythm => "rhythm",
+            Arrangement::Bass => "bass",
+        }
+    }
+}
+
+/// A chart a song offers, listed in the metadata of the song
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SongChart {
+    pub arrangement: Arrangement,
+    pub difficulty: Difficulty,
+}
+
+impl SongChart {
+    /// Every arrangement at every difficulty
+    pub fn all() -> Vec<SongChart> {
+        Arrangement::ALL.iter()
+            .flat_map(|&arrangement| Difficulty::ALL.iter().map(move |&difficulty| SongChart { arrangement, difficulty }))
+            .collect()
+    }
+
+    /// Reads a chart name like "rhythm_expert"
+    pub fn from_name(name: &str) -> Option<SongChart> {
+        Self::all().into_iter().find(|song_chart| format!("{}_{}", song_chart.arrangement.name(), song_chart.difficulty.name()) == name)
+    }
+
+    /// Lead charts are stored as "chart_expert.json", other arrangements as "chart_rhythm_expert.json"
+    pub fn filename(&self) -> String {
+        match self.arrangement {
+            Arrangement::Lead => self.difficulty.chart_filename(),
+            arrangement => format!("chart_{}_{}.json", arrangement.name(), self.difficulty.name()),
+        }
+    }
+
+    /// "lead expert"
+    pub fn label(&self) -> String {
+        format!("{} {}", self.arrangement.name(), self.difficulty.name())
+    }
+}
+
+// Measures per phrase for charts that don't mark their phrases
+const MEASURES_PER_PHRASE: usize = 4;
+
+/// A section of a song, like a verse or a riff, that lasts until the next phrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Phrase {
+    pub start_seconds: f32,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Chart {
+    pub notes: Vec<NoteEvent>,
+    #[serde(default, skip_serializing_if = "TempoMap::is_empty")]
+    pub tempo_map: TempoMap,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub phrases: Vec<Phrase>,
+}
+
+impl Chart {
+    /// Reads a chart as it is on disk, notes off the fret board included
+    pub fn read_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        let mut chart: Chart = serde_json::from_str(&contents)?;
+        chart.sort_notes();
+
+        Ok(chart)
+    }
+
+    /// Reads a chart to play or edit, notes off the fret board are left out
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut chart = Self::read_from_file(path)?;
+
+        let note_amount = chart.notes.len();
+        chart.notes.retain(|note_event| note_event.is_on_fretboard());
+        if chart.notes.len() < note_amount {
+            warn!("left out {} notes of {:?} that are off the fret board", note_amount - chart.notes.len(), path);
+        }
+
+        Ok(chart)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+
+    /// Time at which the last note of the chart has finished
+    pub fn end_seconds(&self) -> f32 {
+        self.notes.iter()
+            .map(|note_event| note_event.start_time_seconds + note_event.duration_seconds)
+            .fold(0.0, f32::max)
+    }
+
+    /// The phrases of the chart, or a phrase every few measures of the tempo map when it has none
+    pub fn phrases_or_measures(&self) -> Vec<Phrase> {
+        if !self.phrases.is_empty() {
+            let mut phrases = self.phrases.clone();
+            phrases.sort_by(|a, b| a.start_seconds.partial_cmp(&b.start_seconds).unwrap());
+            return phrases
+        }
+
+        self.tempo_map.grid_lines(0.0, self.end_seconds(), 1).iter()
+            .filter(|grid_line| grid_line.kind == GridLineKind::Measure)
+            .step_by(MEASURES_PER_PHRASE)
+            .map(|grid_line| Phrase { start_seconds: grid_line.seconds, name: String::new() })
+            .collect()
+    }
+
+    pub fn sort_notes(&mut self) {
+        self.notes.sort_by(|a, b| a.start_time_seconds.partial_cmp(&b.start_time_seconds).unwrap());
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use super::{chart::Difficulty, notes::NoteEvent};
+
+/// Most notes played at once, and the shortest time between two attacks, at a difficulty
+fn difficulty_limits(difficulty: Difficulty) -> (usize, f32) {
+    match difficulty {
+        Difficulty::Easy => (1, 0.6),
+        Difficulty::Medium => (2, 0.3),
+        Difficulty::Hard => (3, 0.15),
+        Difficulty::Expert => (6, 0.0),
+    }
+}
+
+/// Thins out a chart for a lower difficulty by dropping attacks that follow each other too closely and shrinking chords to their lowest notes
+pub fn reduce_notes(note_events: &[NoteEvent], difficulty: Difficulty) -> Vec<NoteEvent> {
+    let (max_chord_notes, min_gap_seconds) = difficulty_limits(difficulty);
+
+    let mut sorted: Vec<&NoteEvent> = note_events.iter().collect();
+    sorted.sort_by(|a, b| a.start_time_seconds.partial_cmp(&b.start_time_seconds).unwrap().then(a.string_index.cmp(&b.string_index)));
+
+    let mut reduced: Vec<NoteEvent> = vec![];
+    let mut chord_start: Option<f32> = None;
+    let mut chord_notes = 0;
+    for note_event in sorted {
+        if chord_start == Some(note_event.start_time_seconds) {
+            if chord_notes < max_chord_notes {
+                reduced.push(note_event.clone());
+                chord_notes += 1;
+            }
+            continue
+        }
+
+        if chord_start.is_some_and(|chord_start| note_event.start_time_seconds - chord_start < min_gap_seconds) {
+            continue
+        }
+        chord_start = Some(note_event.start_time_seconds);
+        chord_notes = 1;
+        reduced.push(note_event.clone());
+    }
+
+    reduced
+}
+
+/// Tags every note with the lowest difficulty that keeps it after thinning out the chart
+pub fn tag_difficulties(note_events: &mut [NoteEvent]) {
+    let key = |note_event: &NoteEvent| (note_event.start_time_seconds.to_bits(), note_event.string_index, note_event.fret_index);
+
+    // Going from the hardest to the easiest difficulty, so the easiest one that keeps a note is tagged last
+    let mut tags = HashMap::new();
+    for difficulty in Difficulty::ALL.iter().rev() {
+        for note_event in reduce_notes(note_events, *difficulty) {
+            tags.insert(key(&note_event), *difficulty);
+        }
+    }
+
+    for note_event in note_events.iter_mut() {
+        note_event.difficulty = tags.get(&key(note_event)).copied();
+    }
+}
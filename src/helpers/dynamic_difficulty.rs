@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+
+use super::{chart::{Difficulty, Phrase}, notes::NoteEvent};
+
+// Number of recent notes the hit rate is taken over
+const ROLLING_WINDOW: usize = 16;
+// Fewer results than this are not enough to change the difficulty
+const MIN_RESULTS: usize = 6;
+const RAISE_HIT_RATE: f32 = 0.9;
+const LOWER_HIT_RATE: f32 = 0.6;
+
+#[derive(Debug, Clone, Default)]
+struct PhraseProgress {
+    start_seconds: f32,
+    level: Option<Difficulty>, // Fixed once the notes of the phrase start to approach
+    hits: usize,
+    total: usize,
+}
+
+/// Raises or lowers the difficulty phrase by phrase, following the hit rate of the recent notes
+#[derive(Debug, Clone)]
+pub struct DynamicDifficulty {
+    phrases: Vec<PhraseProgress>,
+    level: Difficulty, // Level upcoming phrases are played at
+    recent: VecDeque<bool>,
+    results_recorded: usize, // Scored notes of the song that have been recorded
+    evaluated_phrase: Option<usize>, // Last finished phrase the level was adjusted after
+}
+
+impl DynamicDifficulty {
+    pub fn new(phrases: &[Phrase], level: Difficulty) -> Self {
+        let mut phrases: Vec<PhraseProgress> = phrases.iter()
+            .map(|phrase| PhraseProgress { start_seconds: phrase.start_seconds, ..Default::default() })
+            .collect();
+        // Notes before the first phrase belong to the first phrase
+        if phrases.is_empty() {
+            phrases.push(PhraseProgress::default());
+        }
+
+        Self {
+            phrases,
+            level,
+            recent: VecDeque::new(),
+            results_recorded: 0,
+            evaluated_phrase: None,
+        }
+    }
+
+    fn phrase_index_at(&self, seconds: f32) -> usize {
+        self.phrases.iter().rposition(|phrase| phrase.start_seconds <= seconds).unwrap_or(0)
+    }
+
+    /// Level of the phrase at a time, fixing it to the current level if the phrase has none yet
+    pub fn level_at(&mut self, seconds: f32) -> Difficulty {
+        let level = self.level;
+        let index = self.phrase_index_at(seconds);
+        *self.phrases[index].level.get_or_insert(level)
+    }
+
+    /// Whether a note is played at the level of its phrase
+    pub fn is_playable(&mut self, note_event: &NoteEvent) -> bool {
+        let level = self.level_at(note_event.start_time_seconds);
+        note_event.difficulty.map(|difficulty| difficulty <= level).unwrap_or(true)
+    }
+
+    pub fn hit_rate(&self) -> Option<f32> {
+        if self.recent.len() < MIN_RESULTS {
+            return None
+        }
+        Some(self.recent.iter().filter(|hit| **hit).count() as f32 / self.recent.len() as f32)
+    }
+
+    pub fn level(&self) -> Difficulty {
+        self.level
+    }
+
+    /// Number of results that were already recorded, so only new ones are passed to `record`
+    pub fn results_recorded(&self) -> usize {
+        self.results_recorded
+    }
+
+    /// Records whether a note was hit, and adjusts the level once the phrase before it is finished
+    pub fn record(&mut self, note_event: &NoteEvent, hit: bool) {
+        self.results_recorded += 1;
+
+        let index = self.phrase_index_at(note_event.start_time_seconds);
+        self.phrases[index].total += 1;
+        if hit {
+            self.phrases[index].hits += 1;
+        }
+
+        self.recent.push_back(hit);
+        if self.recent.len() > ROLLING_WINDOW {
+            self.recent.pop_front();
+        }
+
+        // The first result of a phrase means the phrases before it are done
+        let finished_phrase = index.checked_sub(1);
+        if let Some(finished_index) = finished_phrase.filter(|_| finished_phrase > self.evaluated_phrase) {
+            self.evaluated_phrase = finished_phrase;
+
+            // Phrases with few notes are judged together with the notes before them
+            let phrase = &self.phrases[finished_index];
+            let hit_rate = if phrase.total >= MIN_RESULTS {
+                Some(phrase.hits as f32 / phrase.total as f32)
+            } else {
+                self.hit_rate()
+            };
+            match hit_rate {
+                Some(hit_rate) if hit_rate >= RAISE_HIT_RATE => self.level = self.level.harder(),
+                Some(hit_rate) if hit_rate <= LOWER_HIT_RATE => self.level = self.level.easier(),
+                _ => {}
+            }
+        }
+    }
+}
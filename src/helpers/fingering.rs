@@ -0,0 +1,174 @@
+use crate::{constants::ingame::FRET_AMOUNT, helpers::notes::note_with_octave_string_to_midi_pitch};
+
+// Candidate fingerings of a chord that are kept after ranking them on their own playability
+const MAX_CHORD_CANDIDATES: usize = 24;
+// Frets a hand can comfortably span without moving
+const HAND_SPAN: usize = 4;
+
+// Costs that are weighed against each other when choosing fingerings
+const MOVEMENT_COST_PER_FRET: f32 = 1.0;
+const SPAN_COST_PER_FRET: f32 = 0.5;
+const OUT_OF_SPAN_COST: f32 = 10.0;
+const HIGH_FRET_COST_PER_FRET: f32 = 0.05;
+
+/// A string and fret to play a pitch on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fingering {
+    pub string_index: usize,
+    pub fret_index: usize,
+}
+
+/// Every position a pitch can be played at with the given open string pitches
+fn pitch_positions(pitch: usize, tuning: &[usize; 6]) -> Vec<Fingering> {
+    tuning.iter().enumerate().filter_map(|(string_index, &open_string_pitch)| {
+        if pitch < open_string_pitch || pitch - open_string_pitch > FRET_AMOUNT {
+            return None
+        }
+        Some(Fingering { string_index, fret_index: pitch - open_string_pitch })
+    }).collect()
+}
+
+/// Fret the hand is at while playing a chord, None if only open strings are played
+fn hand_position(fingerings: &[Fingering]) -> Option<f32> {
+    let fretted: Vec<usize> = fingerings.iter().map(|fingering| fingering.fret_index).filter(|&fret| fret > 0).collect();
+    if fretted.is_empty() {
+        return None
+    }
+
+    Some(fretted.iter().sum::<usize>() as f32 / fretted.len() as f32)
+}
+
+/// How hard a chord is to play on its own
+fn chord_cost(fingerings: &[Fingering]) -> f32 {
+    let fretted = fingerings.iter().map(|fingering| fingering.fret_index).filter(|&fret| fret > 0);
+    let (lowest, highest) = fretted.fold((usize::MAX, 0), |(lowest, highest), fret| (lowest.min(fret), highest.max(fret)));
+    if highest == 0 {
+        return 0.0
+    }
+
+    let span = highest - lowest;
+    let mut cost = span as f32 * SPAN_COST_PER_FRET + highest as f32 * HIGH_FRET_COST_PER_FRET;
+    if span >= HAND_SPAN {
+        cost += OUT_OF_SPAN_COST * (span + 1 - HAND_SPAN) as f32;
+    }
+
+    cost
+}
+
+/// All ways to play the pitches of a chord on different strings, cheapest first
+fn chord_candidates(pitches: &[usize], tuning: &[usize; 6]) -> Vec<Vec<Fingering>> {
+    let mut candidates: Vec<Vec<Fingering>> = vec![vec![]];
+
+    for &pitch in pitches {
+        let positions = pitch_positions(pitch, tuning);
+        candidates = candidates.iter().flat_map(|candidate| {
+            positions.iter()
+                .filter(|position| candidate.iter().all(|used| used.string_index != position.string_index))
+                .map(|position| {
+                    let mut candidate = candidate.clone();
+                    candidate.push(*position);
+                    candidate
+                })
+                .collect::<Vec<_>>()
+        }).collect();
+
+        // Keep the search small for big chords
+        candidates.sort_by(|a, b| chord_cost(a).partial_cmp(&chord_cost(b)).unwrap());
+        candidates.truncate(MAX_CHORD_CANDIDATES * 4);
+    }
+
+    candidates.truncate(MAX_CHORD_CANDIDATES);
+    candidates
+}
+
+/// Picks a string and fret for every pitch of a sequence of chords, minimising how much the hand moves over the whole sequence
+/// Each chord returns its fingerings in the same order as its pitches, pitches that can't be played on the tuning are left out as None
+pub fn assign_fingerings(chords: &[Vec<usize>], tuning: &[usize; 6]) -> Vec<Vec<Option<Fingering>>> {
+    // Drop pitches that can't be played, and pitches that don't fit on the remaining strings
+    let playable_chords: Vec<Vec<usize>> = chords.iter().map(|pitches| {
+        let mut playable: Vec<usize> = pitches.iter().copied().filter(|&pitch| !pitch_positions(pitch, tuning).is_empty()).collect();
+        playable.sort();
+        playable.dedup();
+        playable.truncate(tuning.len());
+        while !playable.is_empty() && chord_candidates(&playable, tuning).is_empty() {
+            playable.remove(0);
+        }
+        playable
+    }).collect();
+
+    // Dynamic programming over the candidates of every chord, tracking the cheapest way to reach each candidate
+    // Chords without playable notes are skipped so they don't break the path
+    let candidates: Vec<Vec<Vec<Fingering>>> = playable_chords.iter().map(|pitches| chord_candidates(pitches, tuning)).collect();
+    let active: Vec<usize> = (0..candidates.len()).filter(|&index| !candidates[index].is_empty()).collect();
+    let mut costs: Vec<Vec<f32>> = vec![];
+    let mut previous_choices: Vec<Vec<usize>> = vec![];
+    // The last hand position along each path, so open string chords don't reset the hand
+    let mut positions: Vec<Vec<Option<f32>>> = vec![];
+
+    for (step, &index) in active.iter().enumerate() {
+        let mut step_costs = vec![];
+        let mut step_choices = vec![];
+        let mut step_positions = vec![];
+
+        for candidate in &candidates[index] {
+            let own_cost = chord_cost(candidate);
+            let position = hand_position(candidate);
+
+            let mut best: (f32, usize, Option<f32>) = (own_cost, 0, position);
+            if step > 0 {
+                best.0 = f32::MAX;
+                for (previous_index, previous_cost) in costs[step - 1].iter().enumerate() {
+                    let previous_position = positions[step - 1][previous_index];
+                    let movement = match (previous_position, position) {
+                        (Some(from), Some(to)) => (from - to).abs() * MOVEMENT_COST_PER_FRET,
+                        _ => 0.0,
+                    };
+                    let cost = previous_cost + own_cost + movement;
+                    if cost < best.0 {
+                        best = (cost, previous_index, position.or(previous_position));
+                    }
+                }
+            }
+
+            step_costs.push(best.0);
+            step_choices.push(best.1);
+            step_positions.push(best.2);
+        }
+
+        costs.push(step_costs);
+        previous_choices.push(step_choices);
+        positions.push(step_positions);
+    }
+
+    // Walk back from the cheapest final candidate
+    let mut chosen: Vec<Option<usize>> = vec![None; candidates.len()];
+    let mut choice = costs.last().and_then(|last_costs| {
+        last_costs.iter().enumerate()
+            .min_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .map(|(choice, _)| choice)
+    });
+    for step in (0..active.len()).rev() {
+        if let Some(current) = choice {
+            chosen[active[step]] = Some(current);
+            choice = Some(previous_choices[step][current]);
+        }
+    }
+
+    // Map the fingerings back to the original order of the pitches
+    chords.iter().enumerate().map(|(index, pitches)| {
+        let fingerings = chosen[index].map(|choice| &candidates[index][choice]);
+        let mut used = vec![false; playable_chords[index].len()];
+        pitches.iter().map(|pitch| {
+            let fingerings = fingerings?;
+            let position = playable_chords[index].iter().enumerate().position(|(playable_index, playable)| playable == pitch && !used[playable_index])?;
+            used[position] = true;
+            Some(fingerings[position])
+        }).collect()
+    }).collect()
+}
+
+/// MIDI pitches of the open strings of a tuning, lowest string first
+pub fn tuning_pitches(tuning: &[String; 6]) -> Option<[usize; 6]> {
+    let pitches: Vec<usize> = tuning.iter().filter_map(|note| note_with_octave_string_to_midi_pitch(note)).collect();
+    pitches.try_into().ok()
+}
@@ -1,16 +1,106 @@
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Debug, Clone)]
+use crate::constants::ingame::FRET_AMOUNT;
+
+use super::chart::Difficulty;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BendPoint {
+    pub position: f32, // 0 is the start of the note, 1 is the end
+    pub semitones: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bend {
+    pub semitones: f32, // Target of the bend
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub curve: Vec<BendPoint>,
+}
+
+impl Bend {
+    /// Semitones the string should be bent at a point in the note (0 to 1).
+    /// Without a curve the bend reaches its target halfway through the note and holds it.
+    pub fn semitones_at(&self, position: f32) -> f32 {
+        if self.curve.is_empty() {
+            return self.semitones * (position * 2.0).clamp(0.0, 1.0)
+        }
+
+        let mut previous = &self.curve[0];
+        if position <= previous.position {
+            return previous.semitones
+        }
+        for point in self.curve.iter().skip(1) {
+            if position <= point.position {
+                let t = (position - previous.position) / (point.position - previous.position).max(f32::EPSILON);
+                return previous.semitones + (point.semitones - previous.semitones) * t
+            }
+            previous = point;
+        }
+
+        previous.semitones
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Legato {
+    HammerOn,
+    PullOff,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NoteEvent {
     pub start_time_seconds: f32,
     pub duration_seconds: f32,
     // pub pitch_midi: usize,
     pub string_index: usize,
-    pub fret_index: usize,
+    pub fret_index: usize, // Matches the fret on a real guitar, 0 is an open string
+
+    // Playing techniques
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bend: Option<Bend>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub slide_to_fret: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub legato: Option<Legato>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub palm_mute: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub harmonic: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub vibrato: bool,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub tremolo: bool,
+
+    // Lowest difficulty the note is played at when the difficulty adapts to the player, untagged notes are always played
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<Difficulty>,
 }
 
 impl NoteEvent {
+    pub fn is_open_string(&self) -> bool {
+        self.fret_index == 0
+    }
+
+    /// Whether the note and the fret it slides to are on the strings and frets of the highway
+    pub fn is_on_fretboard(&self) -> bool {
+        self.string_index < 6 &&
+        self.fret_index <= FRET_AMOUNT &&
+        self.slide_to_fret.map(|slide_to_fret| slide_to_fret <= FRET_AMOUNT).unwrap_or(true)
+    }
+
+    /// MIDI pitch of the fretted note, based on the tuning of the song (lowest string first)
+    pub fn midi_pitch(&self, tuning: &[String; 6]) -> Option<usize> {
+        let open_string_pitch = note_with_octave_string_to_midi_pitch(&tuning[self.string_index])?;
+        Some(open_string_pitch + self.fret_index)
+    }
+
     pub fn equals(&self, note_event: &NoteEvent) -> bool {
         if 
             note_event.start_time_seconds == self.start_time_seconds &&
@@ -0,0 +1,118 @@
+use super::notes::Bend;
+
+// How far off the expected pitch a bend may be while still counting as accurate
+pub const BEND_TOLERANCE_CENTS: f32 = 30.0;
+// Pitch readings further off than this are most likely octave errors of the detector
+const MAX_DEVIATION_CENTS: f32 = 450.0;
+
+// Depth (peak to peak) and rate a vibrato should have to get a full score
+const VIBRATO_MIN_DEPTH_CENTS: f32 = 20.0;
+const VIBRATO_MAX_DEPTH_CENTS: f32 = 120.0;
+const VIBRATO_MIN_RATE_HZ: f32 = 3.0;
+const VIBRATO_MAX_RATE_HZ: f32 = 9.0;
+
+#[derive(Debug, Clone)]
+pub struct PitchSample {
+    pub position: f32, // 0 is the start of the note, 1 is the end
+    pub cents: f32, // Relative to the unbent pitch of the note
+}
+
+#[derive(Debug, Clone)]
+pub struct VibratoResult {
+    pub depth_cents: f32,
+    pub rate_hz: f32,
+}
+
+/// Pitch of a note measured continuously over its duration
+#[derive(Debug, Clone, Default)]
+pub struct PitchTrack {
+    pub samples: Vec<PitchSample>,
+}
+
+impl PitchTrack {
+    /// Adds a pitch reading, returns false if it was discarded as an outlier
+    pub fn push(&mut self, position: f32, cents: f32) -> bool {
+        if cents.abs() > MAX_DEVIATION_CENTS {
+            return false
+        }
+
+        self.samples.push(PitchSample { position, cents });
+        true
+    }
+
+    /// Whether a reading is within the tolerance of the bend at that point in the note
+    pub fn is_on_bend(bend: &Bend, sample: &PitchSample) -> bool {
+        (sample.cents - bend.semitones_at(sample.position) * 100.0).abs() <= BEND_TOLERANCE_CENTS
+    }
+
+    /// 0 to 1, based on whether the bend reached its target and how long it stayed on the expected curve after that
+    pub fn bend_accuracy(&self, bend: &Bend) -> f32 {
+        let target_cents = bend.semitones * 100.0;
+        let reached = self.samples.iter().any(|sample| (sample.cents - target_cents).abs() <= BEND_TOLERANCE_CENTS);
+        if !reached {
+            return 0.0
+        }
+
+        // Only judge the part of the note where the bend should be held at its target
+        let held_samples: Vec<&PitchSample> = self.samples.iter()
+            .filter(|sample| (bend.semitones_at(sample.position) - bend.semitones).abs() < 0.05)
+            .collect();
+        if held_samples.is_empty() {
+            return 1.0
+        }
+
+        let accurate = held_samples.iter().filter(|sample| Self::is_on_bend(bend, sample)).count();
+        accurate as f32 / held_samples.len() as f32
+    }
+
+    /// Depth and rate of the pitch oscillation around its average
+    pub fn vibrato(&self, duration_seconds: f32) -> Option<VibratoResult> {
+        if self.samples.len() < 4 {
+            return None
+        }
+
+        let mean = self.samples.iter().map(|sample| sample.cents).sum::<f32>() / self.samples.len() as f32;
+        let variance = self.samples.iter().map(|sample| (sample.cents - mean).powi(2)).sum::<f32>() / self.samples.len() as f32;
+
+        // Peak to peak depth of a sine wave with the same spread
+        let depth_cents = variance.sqrt() * 2.0 * std::f32::consts::SQRT_2;
+
+        let mut crossings = 0;
+        for window in self.samples.windows(2) {
+            if (window[0].cents - mean).signum() != (window[1].cents - mean).signum() {
+                crossings += 1;
+            }
+        }
+
+        let tracked_seconds = (self.samples.last().unwrap().position - self.samples[0].position) * duration_seconds;
+        if tracked_seconds <= 0.0 {
+            return None
+        }
+
+        Some(VibratoResult {
+            depth_cents,
+            rate_hz: crossings as f32 / 2.0 / tracked_seconds,
+        })
+    }
+
+    /// 0 to 1, full score when the vibrato is both deep enough and at a steady musical rate
+    pub fn vibrato_accuracy(&self, duration_seconds: f32) -> f32 {
+        if let Some(vibrato) = self.vibrato(duration_seconds) {
+            let depth_score = if vibrato.depth_cents < VIBRATO_MIN_DEPTH_CENTS {
+                vibrato.depth_cents / VIBRATO_MIN_DEPTH_CENTS
+            } else if vibrato.depth_cents > VIBRATO_MAX_DEPTH_CENTS {
+                VIBRATO_MAX_DEPTH_CENTS / vibrato.depth_cents
+            } else { 1.0 };
+
+            let rate_score = if vibrato.rate_hz < VIBRATO_MIN_RATE_HZ {
+                vibrato.rate_hz / VIBRATO_MIN_RATE_HZ
+            } else if vibrato.rate_hz > VIBRATO_MAX_RATE_HZ {
+                VIBRATO_MAX_RATE_HZ / vibrato.rate_hz
+            } else { 1.0 };
+
+            return depth_score * rate_score
+        }
+
+        0.0
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use super::{fingering::assign_fingerings, notes::NoteEvent, tuning::note_from_pitch};
+
+// Onsets closer together than this are treated as one attack
+const ONSET_DEBOUNCE_SECONDS: f32 = 0.1;
+// A note ends once no pitch has been detected for this long
+const SILENCE_SECONDS: f32 = 0.15;
+
+#[derive(Debug, Clone)]
+struct RecordedNote {
+    start_seconds: f32,
+    end_seconds: Option<f32>, // None while the note is still ringing
+    pitches: Vec<usize>, // Midi pitch of every frame the note was heard
+}
+
+impl RecordedNote {
+    /// The pitch heard most often over the note
+    fn pitch(&self) -> Option<usize> {
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for &pitch in &self.pitches {
+            *counts.entry(pitch).or_default() += 1;
+        }
+
+        counts.into_iter().max_by_key(|&(pitch, count)| (count, pitch)).map(|(pitch, _)| pitch)
+    }
+}
+
+/// Notes detected from the onsets and pitch of a performance
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub start_seconds: f32,
+    pub last_seconds: f32,
+    notes: Vec<RecordedNote>,
+    previous_onset_seconds: f32,
+    silent_since: Option<f32>,
+}
+
+impl Recording {
+    pub fn new(start_seconds: f32) -> Self {
+        Self {
+            start_seconds,
+            last_seconds: start_seconds,
+            notes: vec![],
+            previous_onset_seconds: f32::MIN,
+            silent_since: None,
+        }
+    }
+
+    /// Feeds the analysis of one frame of input audio at a point in the song
+    pub fn update(&mut self, seconds: f32, has_onset: bool, frequency: Option<f32>) {
+        self.last_seconds = seconds;
+
+        if has_onset && seconds - self.previous_onset_seconds > ONSET_DEBOUNCE_SECONDS {
+            self.previous_onset_seconds = seconds;
+            self.end_note(seconds);
+            self.notes.push(RecordedNote { start_seconds: seconds, end_seconds: None, pitches: vec![] });
+            self.silent_since = None;
+        }
+
+        match frequency {
+            Some(frequency) if frequency > 0.0 => {
+                self.silent_since = None;
+                if let Some(note) = self.notes.last_mut().filter(|note| note.end_seconds.is_none()) {
+                    note.pitches.push(note_from_pitch(frequency).max(0) as usize);
+                }
+            }
+            _ => {
+                let silent_since = *self.silent_since.get_or_insert(seconds);
+                if seconds - silent_since > SILENCE_SECONDS {
+                    self.end_note(silent_since);
+                }
+            }
+        }
+    }
+
+    fn end_note(&mut self, seconds: f32) {
+        if let Some(note) = self.notes.last_mut().filter(|note| note.end_seconds.is_none()) {
+            note.end_seconds = Some(seconds);
+        }
+    }
+
+    /// Quantises the detected notes with the given snapping and suggests a string and fret for each pitch
+    /// Attacks without a detected pitch are left out
+    pub fn to_note_events(&self, tuning: &[usize; 6], snap: impl Fn(f32) -> f32, grid_step_seconds: f32) -> Vec<NoteEvent> {
+        let mut quantised: Vec<(f32, f32, usize)> = vec![];
+        for note in &self.notes {
+            let Some(pitch) = note.pitch() else { continue };
+            let start_seconds = snap(note.start_seconds);
+            let end_seconds = snap(note.end_seconds.unwrap_or(self.last_seconds)).max(start_seconds + grid_step_seconds);
+
+            // Attacks that land on the same grid line keep the longest note
+            match quantised.last_mut() {
+                Some(previous) if previous.0 == start_seconds => {
+                    if end_seconds - start_seconds > previous.1 {
+                        *previous = (start_seconds, end_seconds - start_seconds, pitch);
+                    }
+                }
+                _ => quantised.push((start_seconds, end_seconds - start_seconds, pitch)),
+            }
+        }
+
+        // Notes are cut off where the next one starts, the guitar can't sustain a note past the next attack on a monophonic take
+        for index in 1..quantised.len() {
+            let next_start = quantised[index].0;
+            let previous = &mut quantised[index - 1];
+            previous.1 = previous.1.min(next_start - previous.0).max(grid_step_seconds);
+        }
+
+        let chords: Vec<Vec<usize>> = quantised.iter().map(|&(_, _, pitch)| vec![pitch]).collect();
+        let fingerings = assign_fingerings(&chords, tuning);
+
+        quantised.iter().zip(fingerings)
+            .filter_map(|(&(start_time_seconds, duration_seconds, _), fingering)| {
+                let fingering = fingering.into_iter().next().flatten()?;
+                Some(NoteEvent {
+                    start_time_seconds,
+                    duration_seconds,
+                    string_index: fingering.string_index,
+                    fret_index: fingering.fret_index,
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,148 @@
+use std::{collections::VecDeque, error::Error, fs::File, io::BufReader, path::{Path, PathBuf}, time::Duration};
+
+use ogg::PacketReader;
+use opus::Channels;
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+
+// Formats song audio can be in, in the order they are looked for
+pub const AUDIO_EXTENSIONS: [&str; 5] = ["mp3", "ogg", "flac", "wav", "opus"];
+// Opus is always decoded at 48kHz
+const OPUS_SAMPLE_RATE: u32 = 48000;
+// Longest opus packet is 120ms
+const OPUS_MAX_FRAME_SAMPLES: usize = 5760;
+
+pub type AudioSource = Box<dyn Source<Item = i16> + Send>;
+
+/// Part of the song audio played as a preview in song select, chosen by the mapper
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PreviewClip {
+    pub start_seconds: f32,
+    pub duration_seconds: f32,
+}
+
+/// Whether a file is in one of the supported audio formats, going by its extension
+pub fn is_audio_file(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+    AUDIO_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Audio file of a song, the one named in the metadata or otherwise the first audio.<extension> found
+pub fn find_song_audio(song_directory: &Path, audio_filename: Option<&str>) -> Option<PathBuf> {
+    if let Some(audio_filename) = audio_filename {
+        let path = song_directory.join(audio_filename);
+        return path.is_file().then_some(path)
+    }
+
+    AUDIO_EXTENSIONS.iter()
+        .map(|extension| song_directory.join(format!("audio.{}", extension)))
+        .find(|path| path.is_file())
+}
+
+/// Separate preview clip of a song, like preview.mp3 or preview.ogg
+pub fn find_preview_audio(song_directory: &Path) -> Option<PathBuf> {
+    AUDIO_EXTENSIONS.iter()
+        .map(|extension| song_directory.join(format!("preview.{}", extension)))
+        .find(|path| path.is_file())
+}
+
+/// Opens an audio file for playback or analysis
+/// mp3, ogg vorbis, flac and wav are decoded by rodio, opus (also in .ogg files) by libopus
+pub fn open_audio_source(path: &Path) -> Result<AudioSource, Box<dyn Error>> {
+    let is_opus = path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extension.eq_ignore_ascii_case("opus"));
+    if !is_opus {
+        match Decoder::new(BufReader::new(File::open(path)?)) {
+            Ok(decoder) => return Ok(Box::new(decoder)),
+            // An .ogg file that rodio can't read may hold opus instead of vorbis
+            Err(err) if !path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("ogg")) => return Err(err.into()),
+            Err(_) => {}
+        }
+    }
+
+    Ok(Box::new(OpusSource::new(File::open(path)?)?))
+}
+
+/// Samples of an ogg opus file, decoded a packet at a time
+pub struct OpusSource {
+    reader: PacketReader<BufReader<File>>,
+    decoder: opus::Decoder,
+    channels: u16,
+    skip_samples: usize, // Samples at the start the encoder added, per channel
+    buffer: VecDeque<i16>,
+}
+
+impl OpusSource {
+    pub fn new(file: File) -> Result<Self, Box<dyn Error>> {
+        let mut reader = PacketReader::new(BufReader::new(file));
+
+        // The first packet is the OpusHead header, the second holds the tags
+        let head = reader.read_packet()?.ok_or("the file is empty")?;
+        if head.data.len() < 19 || &head.data[0..8] != b"OpusHead" {
+            return Err("the file is not an ogg opus file".into())
+        }
+        let channels = head.data[9];
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]) as usize;
+        let opus_channels = match channels {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(format!("opus files with {} channels are not supported", channels).into()),
+        };
+        reader.read_packet()?.ok_or("the file has no opus tags")?;
+
+        Ok(Self {
+            reader,
+            decoder: opus::Decoder::new(OPUS_SAMPLE_RATE, opus_channels)?,
+            channels: channels as u16,
+            skip_samples: pre_skip,
+            buffer: VecDeque::new(),
+        })
+    }
+
+    /// Decodes packets until there are samples, false at the end of the file
+    fn decode_next_packet(&mut self) -> bool {
+        let channels = self.channels as usize;
+        let mut samples = vec![0; OPUS_MAX_FRAME_SAMPLES * channels];
+        while self.buffer.is_empty() {
+            let Ok(Some(packet)) = self.reader.read_packet() else {
+                return false
+            };
+            let Ok(frame_samples) = self.decoder.decode(&packet.data, &mut samples, false) else {
+                continue
+            };
+
+            let skipped = self.skip_samples.min(frame_samples);
+            self.skip_samples -= skipped;
+            self.buffer.extend(&samples[skipped * channels..frame_samples * channels]);
+        }
+        true
+    }
+}
+
+impl Iterator for OpusSource {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        if self.buffer.is_empty() && !self.decode_next_packet() {
+            return None
+        }
+        self.buffer.pop_front()
+    }
+}
+
+impl Source for OpusSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        OPUS_SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::resources::song_library::SongMetadata;
+
+use super::{audio_info::AudioInfo, chart::{Chart, CHART_FILENAME}, notes::note_with_octave_string_to_midi_pitch, song_audio::find_song_audio};
+
+// Difference between the duration in the metadata and the audio that is still fine
+pub const DURATION_TOLERANCE_SECONDS: f32 = 2.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SongIssue {
+    MissingAudio { filename: Option<String> },
+    UnreadableAudio { filename: String, message: String },
+    MissingMetadata,
+    InvalidMetadata { message: String },
+    InvalidTuning { string_index: usize, note: String },
+    MissingChart { filename: String },
+    MissingStem { filename: String },
+    InvalidChart { filename: String, message: String },
+    ChartOutOfRange { filename: String, notes: usize },
+    DurationMismatch { metadata_seconds: f32, audio_seconds: f32 },
+}
+
+impl SongIssue {
+    /// Songs with a fatal issue can't be played and are left out of the library
+    /// Notes off the fret board aren't fatal, loading the chart leaves them out
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, SongIssue::MissingAudio { .. } | SongIssue::MissingMetadata | SongIssue::InvalidMetadata { .. })
+    }
+}
+
+impl fmt::Display for SongIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SongIssue::MissingAudio { filename: Some(filename) } => write!(f, "{} is listed in the metadata but missing", filename),
+            SongIssue::MissingAudio { filename: None } => write!(f, "there is no audio file, like audio.mp3 or audio.ogg"),
+            SongIssue::UnreadableAudio { filename, message } => write!(f, "the header of {} can't be read: {}", filename, message),
+            SongIssue::MissingMetadata => write!(f, "metadata.json is missing"),
+            SongIssue::InvalidMetadata { message } => write!(f, "metadata.json is not valid: {}", message),
+            SongIssue::InvalidTuning { string_index, note } => write!(f, "string {} of the tuning is \"{}\", which is not a note like E2", string_index + 1, note),
+            SongIssue::MissingChart { filename } => write!(f, "{} is listed in the metadata but missing", filename),
+            SongIssue::MissingStem { filename } => write!(f, "the stem {} is listed in the metadata but missing", filename),
+            SongIssue::InvalidChart { filename, message } => write!(f, "{} is not valid: {}", filename, message),
+            SongIssue::ChartOutOfRange { filename, notes } => write!(f, "{} has {} notes off the fretboard or outside the song", filename, notes),
+            SongIssue::DurationMismatch { metadata_seconds, audio_seconds } => write!(f, "the metadata says the song lasts {:.1}s but the audio lasts {:.1}s", metadata_seconds, audio_seconds),
+        }
+    }
+}
+
+/// Problems found in one folder of the songs directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SongReport {
+    pub folder: String,
+    pub title: Option<String>,
+    pub issues: Vec<SongIssue>,
+}
+
+impl SongReport {
+    pub fn is_playable(&self) -> bool {
+        !self.issues.iter().any(|issue| issue.is_fatal())
+    }
+
+    /// "Artist - Title" when the metadata could be read, otherwise the folder name
+    pub fn name(&self) -> &str {
+        self.title.as_deref().unwrap_or(&self.folder)
+    }
+}
+
+/// Every folder of the songs directory that has problems
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LibraryReport {
+    pub songs: Vec<SongReport>,
+}
+
+impl LibraryReport {
+    pub fn issue_count(&self) -> usize {
+        self.songs.iter().map(|song_report| song_report.issues.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+}
+
+fn validate_chart(song_directory: &Path, filename: &str, duration_seconds: f32, issues: &mut Vec<SongIssue>) {
+    // Read as it is on disk, playing leaves out the notes off the fret board
+    let chart = match Chart::read_from_file(&song_directory.join(filename)) {
+        Ok(chart) => chart,
+        Err(err) => {
+            issues.push(SongIssue::InvalidChart { filename: filename.to_string(), message: err.to_string() });
+            return
+        }
+    };
+
+    let notes = chart.notes.iter().filter(|note_event| {
+        !note_event.is_on_fretboard() ||
+        note_event.start_time_seconds < 0.0 ||
+        note_event.start_time_seconds > duration_seconds
+    }).count();
+    if notes > 0 {
+        issues.push(SongIssue::ChartOutOfRange { filename: filename.to_string(), notes });
+    }
+}
+
+/// Checks a folder of the songs directory, returns its metadata when the song can be played and what the audio header says
+pub fn validate_song_directory(song_directory: &Path) -> (Option<SongMetadata>, Option<AudioInfo>, SongReport) {
+    let mut report = SongReport {
+        folder: song_directory.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        ..Default::default()
+    };
+
+    // The metadata may name the audio file, so it's read first
+    let metadata_path = song_directory.join("metadata.json");
+    let song_metadata = if metadata_path.is_file() {
+        SongMetadata::load_from_file(&metadata_path)
+            .map_err(|err| report.issues.push(SongIssue::InvalidMetadata { message: err.to_string() }))
+            .ok()
+    } else {
+        report.issues.push(SongIssue::MissingMetadata);
+        None
+    };
+
+    let audio_filename = song_metadata.as_ref().and_then(|song_metadata| song_metadata.audio_filename.clone());
+    let audio_info = match find_song_audio(song_directory, audio_filename.as_deref()) {
+        Some(audio_path) => AudioInfo::read_from_file(&audio_path)
+            .map_err(|err| report.issues.push(SongIssue::UnreadableAudio {
+                filename: audio_path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+                message: err.to_string(),
+            }))
+            .ok(),
+        None => {
+            report.issues.push(SongIssue::MissingAudio { filename: audio_filename });
+            None
+        }
+    };
+
+    let Some(song_metadata) = song_metadata else {
+        return (None, audio_info, report)
+    };
+    // Notes are checked against the length of the audio when it's known
+    let duration_seconds = audio_info.as_ref().map(|audio_info| audio_info.duration_seconds).unwrap_or(song_metadata.duration_seconds);
+    report.title = Some(format!("{} - {}", song_metadata.artists.join(", "), song_metadata.title));
+
+    for (string_index, note) in song_metadata.tuning.iter().enumerate() {
+        if note_with_octave_string_to_midi_pitch(note).is_none() {
+            report.issues.push(SongIssue::InvalidTuning { string_index, note: note.clone() });
+        }
+    }
+
+    // The main chart is optional, the charts listed in the metadata are not
+    if song_directory.join(CHART_FILENAME).exists() {
+        validate_chart(song_directory, CHART_FILENAME, duration_seconds, &mut report.issues);
+    }
+    for song_chart in &song_metadata.charts {
+        let filename = song_chart.filename();
+        if song_directory.join(&filename).exists() {
+            validate_chart(song_directory, &filename, duration_seconds, &mut report.issues);
+        } else {
+            report.issues.push(SongIssue::MissingChart { filename });
+        }
+    }
+
+    for stem in &song_metadata.stems {
+        if !song_directory.join(&stem.filename).is_file() {
+            report.issues.push(SongIssue::MissingStem { filename: stem.filename.clone() });
+        }
+    }
+
+    if let Some(audio_info) = &audio_info {
+        if (audio_info.duration_seconds - song_metadata.duration_seconds).abs() > DURATION_TOLERANCE_SECONDS {
+            report.issues.push(SongIssue::DurationMismatch { metadata_seconds: song_metadata.duration_seconds, audio_seconds: audio_info.duration_seconds });
+        }
+    }
+
+    let playable = report.is_playable();
+    (playable.then_some(song_metadata), audio_info, report)
+}
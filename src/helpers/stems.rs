@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StemKind {
+    Guitar,
+    Backing,
+    Vocals,
+}
+
+impl StemKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StemKind::Guitar => "guitar",
+            StemKind::Backing => "backing",
+            StemKind::Vocals => "vocals",
+        }
+    }
+}
+
+/// Separate audio file of one part of the song, played together with the other stems instead of the full mix
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongStem {
+    pub kind: StemKind,
+    pub filename: String,
+}
+
+/// How the guitar stem of a song is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuitarStemMode {
+    #[default]
+    Normal,
+    Muted,
+    FadeOnMiss, // Muted, but faded in for a moment when the player misses a note
+}
+
+impl GuitarStemMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuitarStemMode::Normal => "normal",
+            GuitarStemMode::Muted => "muted",
+            GuitarStemMode::FadeOnMiss => "on misses",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            GuitarStemMode::Normal => GuitarStemMode::Muted,
+            GuitarStemMode::Muted => GuitarStemMode::FadeOnMiss,
+            GuitarStemMode::FadeOnMiss => GuitarStemMode::Normal,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TempoChange {
+    pub start_seconds: f32, // Always the start of a measure
+    pub bpm: f32,
+    pub beats_per_measure: u32,
+    pub beat_unit: u32, // 4 when the beat is a quarter note, 8 when it's an eighth note
+}
+
+impl Default for TempoChange {
+    fn default() -> Self {
+        TempoChange { start_seconds: 0.0, bpm: 120.0, beats_per_measure: 4, beat_unit: 4 }
+    }
+}
+
+impl TempoChange {
+    pub fn beat_seconds(&self) -> f32 {
+        60.0 / self.bpm
+    }
+
+    /// "4/4"
+    pub fn time_signature(&self) -> String {
+        format!("{}/{}", self.beats_per_measure, self.beat_unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridLineKind {
+    Measure,
+    Beat,
+    Subdivision,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridLine {
+    pub seconds: f32,
+    pub kind: GridLineKind,
+}
+
+/// Tempo and time signature changes of a chart, sorted by start
+/// The first change also applies before its start, a chart without changes is 120 BPM 4/4 from the start of the song
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TempoMap {
+    pub changes: Vec<TempoChange>,
+}
+
+impl TempoMap {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    fn segments(&self) -> Vec<TempoChange> {
+        if self.changes.is_empty() {
+            return vec![TempoChange::default()]
+        }
+        self.changes.clone()
+    }
+
+    fn segment_index_at(segments: &[TempoChange], seconds: f32) -> usize {
+        segments.iter().rposition(|change| change.start_seconds <= seconds).unwrap_or(0)
+    }
+
+    pub fn change_at(&self, seconds: f32) -> TempoChange {
+        let segments = self.segments();
+        segments[Self::segment_index_at(&segments, seconds)].clone()
+    }
+
+    pub fn beat_seconds_at(&self, seconds: f32) -> f32 {
+        self.change_at(seconds).beat_seconds()
+    }
+
+    /// Measure, beat and subdivision lines from one time up to another, with a number of lines per beat
+    pub fn grid_lines(&self, from_seconds: f32, to_seconds: f32, subdivision: u32) -> Vec<GridLine> {
+        let segments = self.segments();
+        let subdivision = subdivision.max(1) as i64;
+        let mut lines = vec![];
+
+        for (index, change) in segments.iter().enumerate() {
+            let segment_start = if index == 0 { f32::MIN } else { change.start_seconds };
+            let segment_end = segments.get(index + 1).map(|next| next.start_seconds).unwrap_or(f32::MAX);
+            let from = from_seconds.max(segment_start);
+            let to = to_seconds.min(segment_end);
+            if from > to {
+                continue
+            }
+
+            let step = change.beat_seconds() / subdivision as f32;
+            let lines_per_measure = subdivision * change.beats_per_measure.max(1) as i64;
+            let first = ((from - change.start_seconds) / step).ceil() as i64;
+            let last = ((to - change.start_seconds) / step).floor() as i64;
+            for line in first..=last {
+                let seconds = change.start_seconds + line as f32 * step;
+                if seconds >= segment_end {
+                    break
+                }
+
+                let kind = if line.rem_euclid(lines_per_measure) == 0 {
+                    GridLineKind::Measure
+                } else if line.rem_euclid(subdivision) == 0 {
+                    GridLineKind::Beat
+                } else {
+                    GridLineKind::Subdivision
+                };
+                lines.push(GridLine { seconds, kind });
+            }
+        }
+
+        lines
+    }
+
+    /// Closest grid line to a time, with a number of lines per beat
+    pub fn snap(&self, seconds: f32, subdivision: u32) -> f32 {
+        let segments = self.segments();
+        let index = Self::segment_index_at(&segments, seconds);
+        let change = &segments[index];
+
+        let step = change.beat_seconds() / subdivision.max(1) as f32;
+        let snapped = ((seconds - change.start_seconds) / step).round() * step + change.start_seconds;
+
+        // A grid line past the next change snaps onto the start of that change
+        let snapped = match segments.get(index + 1) {
+            Some(next) if snapped > next.start_seconds || (next.start_seconds - seconds).abs() < (snapped - seconds).abs() => next.start_seconds,
+            _ => snapped,
+        };
+
+        snapped.max(0.0)
+    }
+
+    /// Adds a change, replacing a change that starts at the same time
+    pub fn set_change(&mut self, change: TempoChange) {
+        self.changes.retain(|existing| (existing.start_seconds - change.start_seconds).abs() > f32::EPSILON);
+        self.changes.push(change);
+        self.changes.sort_by(|a, b| a.start_seconds.partial_cmp(&b.start_seconds).unwrap());
+    }
+
+    /// Changes the change in effect at a time, creating the first change if the map is empty
+    pub fn edit_change_at(&mut self, seconds: f32, edit: impl FnOnce(&mut TempoChange)) {
+        if self.changes.is_empty() {
+            self.changes.push(TempoChange::default());
+        }
+        let index = Self::segment_index_at(&self.changes, seconds);
+        edit(&mut self.changes[index]);
+        self.changes.sort_by(|a, b| a.start_seconds.partial_cmp(&b.start_seconds).unwrap());
+    }
+
+    pub fn remove_change(&mut self, start_seconds: f32) {
+        self.changes.retain(|change| (change.start_seconds - start_seconds).abs() > f32::EPSILON);
+    }
+}
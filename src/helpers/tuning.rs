@@ -1,5 +1,8 @@
 pub const NOTE_STRINGS: [&str; 12] = ["C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B"];
 
+// Lowest string first
+pub const STANDARD_TUNING: [&str; 6] = ["E2", "A2", "D3", "G3", "B3", "E4"];
+
 pub fn note_from_pitch(frequency: f32) -> i32 {
     let note_num = 12.0 * (frequency / 440.0).log2();
     (note_num.round() as i32) + 69
@@ -17,3 +20,8 @@ pub fn cents_off_from_pitch(frequency: f32, note: i32) -> i32 {
 pub fn octave_from_note(note: i32) -> i32 {
     (note / 12) - 1
 }
+
+/// 40 -> "E2", the format used for the tuning of songs
+pub fn note_name_from_note_number(note: i32) -> String {
+    format!("{}{}", NOTE_STRINGS[note.rem_euclid(12) as usize], octave_from_note(note))
+}
@@ -0,0 +1,490 @@
+use std::{collections::HashMap, error::Error, fmt, fs, path::Path};
+
+use regex::Regex;
+
+use crate::{constants::ingame::FRET_AMOUNT, helpers::{chart::Chart, fingering::tuning_pitches, notes::{Bend, Legato, NoteEvent}, tuning::{note_name_from_note_number, STANDARD_TUNING}}};
+
+use super::imported_song::{measures_to_tempo_map, quarters_to_seconds, ImportedSong};
+
+const DEFAULT_TEMPO: f64 = 120.0;
+const STRING_COUNT: usize = 6;
+
+// Beats per measure and beat unit
+type TimeSignature = (u32, u32);
+
+/// A measure or line that could not be read, or could be read in more than one way
+#[derive(Debug, Clone)]
+pub struct TabIssue {
+    pub line: usize, // Starts at 1
+    pub message: String,
+}
+
+impl fmt::Display for TabIssue {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// The parsed tab together with everything that should be checked by hand
+pub struct AsciiTabImport {
+    pub imported_song: ImportedSong,
+    pub issues: Vec<TabIssue>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TabNote {
+    start_quarters: f64,
+    measure_end_quarters: f64,
+    string_index: usize,
+    fret: usize,
+    legato: Option<Legato>,
+    slide_to_fret: Option<usize>,
+    bend: Option<Bend>,
+    palm_mute: bool,
+    harmonic: bool,
+    vibrato: bool,
+}
+
+/// Technique that waits for the next fret number on a string
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pending {
+    None,
+    Legato(Legato),
+    Slide,
+    BendTarget,
+    Release,
+}
+
+struct TabParser {
+    tab_line: Regex,
+    bpm_hint: Regex,
+    time_hint: Regex,
+    measure_hint: Regex,
+    measure_bpm: Regex, // BPM within a measure hint
+    measure_time: Regex, // Time signature within a measure hint
+    title_hint: Regex,
+    artist_hint: Regex,
+    issues: Vec<TabIssue>,
+    notes: Vec<TabNote>,
+    time_signature: TimeSignature,
+    measure_hints: HashMap<usize, (Option<f64>, Option<TimeSignature>)>, // Measure number to BPM and time signature
+    tempo_changes: Vec<(f64, f64)>,
+    measures: Vec<(f64, u32, u32)>, // Start in quarter notes and time signature of every measure
+    measure_count: usize,
+    measure_start_quarters: f64,
+    has_bpm: bool,
+}
+
+/// Parses a time signature like "3/4" into the beats per measure and the beat unit
+fn time_signature(numerator: &str, denominator: &str) -> Option<TimeSignature> {
+    let numerator: u32 = numerator.parse().ok()?;
+    let denominator: u32 = denominator.parse().ok()?;
+    if numerator == 0 || denominator == 0 {
+        return None
+    }
+
+    Some((numerator, denominator))
+}
+
+/// "C#" -> 1
+fn note_name_offset(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    let offset = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let accidental = match chars.next() {
+        Some('#') => 1,
+        Some('b') => -1,
+        None => 0,
+        _ => return None,
+    };
+
+    Some(offset + accidental)
+}
+
+impl TabParser {
+    fn new() -> Self {
+        Self {
+            tab_line: Regex::new(r"^\s*(?P<label>[A-Ga-g][#b]?)?\s*\|(?P<body>.*)$").unwrap(),
+            bpm_hint: Regex::new(r"(?i)^\s*(bpm|tempo)\s*[:=]?\s*(?P<bpm>\d+(\.\d+)?)\s*$").unwrap(),
+            time_hint: Regex::new(r"(?i)^\s*time\s*[:=]?\s*(?P<numerator>\d+)\s*/\s*(?P<denominator>\d+)\s*$").unwrap(),
+            measure_hint: Regex::new(r"(?i)^\s*measure\s+(?P<measure>\d+)\s*:(?P<hints>.*)$").unwrap(),
+            measure_bpm: Regex::new(r"(?i)bpm\s*[:=]?\s*(\d+(\.\d+)?)").unwrap(),
+            measure_time: Regex::new(r"(\d+)\s*/\s*(\d+)").unwrap(),
+            title_hint: Regex::new(r"(?i)^\s*title\s*:\s*(?P<title>.+?)\s*$").unwrap(),
+            artist_hint: Regex::new(r"(?i)^\s*(artist|by)\s*:\s*(?P<artist>.+?)\s*$").unwrap(),
+            issues: vec![],
+            notes: vec![],
+            time_signature: (4, 4),
+            measure_hints: HashMap::new(),
+            tempo_changes: vec![(0.0, DEFAULT_TEMPO)],
+            measures: vec![],
+            measure_count: 0,
+            measure_start_quarters: 0.0,
+            has_bpm: false,
+        }
+    }
+
+    fn issue(&mut self, line_index: usize, message: String) {
+        self.issues.push(TabIssue { line: line_index + 1, message });
+    }
+
+    fn is_tab_line(&self, line: &str) -> bool {
+        self.tab_line.captures(line)
+            .and_then(|captures| captures.name("body"))
+            .map(|body| body.as_str().matches('-').count() >= 2)
+            .unwrap_or(false)
+    }
+
+    fn set_bpm(&mut self, bpm: f64) {
+        self.has_bpm = true;
+        self.tempo_changes.retain(|change| change.0 < self.measure_start_quarters);
+        self.tempo_changes.push((self.measure_start_quarters, bpm));
+    }
+
+    /// Reads hint lines, returns true if the line was a hint
+    fn read_hint(&mut self, line_index: usize, line: &str, title: &mut Option<String>, artists: &mut Vec<String>) -> bool {
+        if let Some(captures) = self.bpm_hint.captures(line) {
+            let bpm: f64 = captures["bpm"].parse().unwrap_or(0.0);
+            if bpm > 0.0 {
+                self.set_bpm(bpm);
+            } else {
+                self.issue(line_index, format!("BPM {} is not valid", &captures["bpm"]));
+            }
+            return true
+        }
+        if let Some(captures) = self.time_hint.captures(line) {
+            match time_signature(&captures["numerator"], &captures["denominator"]) {
+                Some(signature) => self.time_signature = signature,
+                None => self.issue(line_index, "time signature is not valid".to_string()),
+            }
+            return true
+        }
+        if let Some(captures) = self.measure_hint.captures(line) {
+            let measure: usize = captures["measure"].parse().unwrap_or(0);
+            let hints = captures["hints"].to_string();
+            let bpm = self.measure_bpm.captures(&hints).and_then(|captures| captures[1].parse::<f64>().ok());
+            let signature = self.measure_time.captures(&hints).and_then(|captures| time_signature(&captures[1], &captures[2]));
+            if measure == 0 || (bpm.is_none() && signature.is_none()) {
+                self.issue(line_index, "measure hint is not understood, use e.g. \"measure 5: 3/4 bpm 90\"".to_string());
+            } else {
+                self.measure_hints.insert(measure, (bpm, signature));
+            }
+            return true
+        }
+        if let Some(captures) = self.title_hint.captures(line) {
+            *title = Some(captures["title"].to_string());
+            return true
+        }
+        if let Some(captures) = self.artist_hint.captures(line) {
+            artists.push(captures["artist"].to_string());
+            return true
+        }
+
+        false
+    }
+
+    /// Columns covered by a palm mute annotation like "PM-----|"
+    fn palm_mute_columns(line: &str) -> Vec<bool> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut columns = vec![false; chars.len()];
+        let mut index = 0;
+        while index + 1 < chars.len() {
+            if chars[index] == 'P' && (chars[index + 1] == 'M' || chars[index + 1] == '.') {
+                while index < chars.len() && matches!(chars[index], 'P' | 'M' | '.' | '-' | '|') {
+                    columns[index] = true;
+                    index += 1;
+                }
+            } else {
+                index += 1;
+            }
+        }
+
+        columns
+    }
+
+    /// Parses six string lines, the highest string first
+    fn read_system(&mut self, first_line_index: usize, lines: &[&str], palm_mute_line: Option<&str>) -> Option<[Option<String>; STRING_COUNT]> {
+        let mut labels: [Option<String>; STRING_COUNT] = Default::default();
+        let mut measures_per_line: Vec<Vec<(usize, String)>> = vec![]; // Column the measure starts at and its content
+
+        for (row, line) in lines.iter().enumerate() {
+            let captures = self.tab_line.captures(line).unwrap();
+            labels[row] = captures.name("label").map(|label| label.as_str().to_string());
+            let body = captures.name("body").unwrap();
+            let body_start = line[..body.start()].chars().count();
+
+            let mut measures = vec![];
+            let mut column = body_start;
+            for segment in body.as_str().split('|') {
+                let length = segment.chars().count();
+                if !segment.trim().is_empty() {
+                    measures.push((column, segment.to_string()));
+                }
+                column += length + 1;
+            }
+            measures_per_line.push(measures);
+        }
+
+        let measure_count = measures_per_line[0].len();
+        if measures_per_line.iter().any(|measures| measures.len() != measure_count) {
+            let counts: Vec<String> = measures_per_line.iter().map(|measures| measures.len().to_string()).collect();
+            self.issue(first_line_index, format!("the string lines have different numbers of measures ({}), skipped these lines", counts.join(", ")));
+            return None
+        }
+
+        let palm_mutes = palm_mute_line.map(Self::palm_mute_columns).unwrap_or_default();
+
+        for measure_index in 0..measure_count {
+            self.measure_count += 1;
+            let measure_number = self.measure_count;
+
+            if let Some((bpm, signature)) = self.measure_hints.get(&measure_number).copied() {
+                if let Some(bpm) = bpm {
+                    self.set_bpm(bpm);
+                }
+                if let Some(signature) = signature {
+                    self.time_signature = signature;
+                }
+            }
+
+            let widths: Vec<usize> = measures_per_line.iter().map(|measures| measures[measure_index].1.chars().count()).collect();
+            let width = *widths.iter().max().unwrap();
+            if widths.iter().any(|&other| other != width) {
+                self.issue(first_line_index, format!("measure {} is not aligned, the string lines have different lengths, note timing may be off", measure_number));
+            }
+
+            let measure_start = self.measure_start_quarters;
+            let measure_end = measure_start + self.time_signature.0 as f64 * 4.0 / self.time_signature.1 as f64;
+            self.measures.push((measure_start, self.time_signature.0, self.time_signature.1));
+
+            for (row, measures) in measures_per_line.iter().enumerate() {
+                let (start_column, content) = measures[measure_index].clone();
+                self.read_measure_line(first_line_index + row, measure_number, row, &content, |column| {
+                    let start_quarters = measure_start + column as f64 / width as f64 * (measure_end - measure_start);
+                    let palm_mute = palm_mutes.get(start_column + column).copied().unwrap_or(false);
+                    (start_quarters, measure_end, palm_mute)
+                });
+            }
+
+            self.measure_start_quarters = measure_end;
+        }
+
+        Some(labels)
+    }
+
+    /// Reads the notes of one string in one measure
+    fn read_measure_line(&mut self, line_index: usize, measure_number: usize, row: usize, content: &str, position: impl Fn(usize) -> (f64, f64, bool)) {
+        let string_index = STRING_COUNT - 1 - row;
+        let chars: Vec<char> = content.chars().collect();
+        let mut pending = Pending::None;
+        let mut harmonic = false;
+        let mut previous_note: Option<usize> = self.notes.iter().rposition(|note| note.string_index == string_index);
+        let mut unknown: Vec<char> = vec![];
+        let mut column = 0;
+
+        while column < chars.len() {
+            let character = chars[column];
+
+            if character.is_ascii_digit() {
+                let digits: String = chars[column..].iter().take_while(|character| character.is_ascii_digit()).collect();
+                let digit_count = digits.len();
+                let fret: usize = digits.parse().unwrap_or(0);
+
+                if digit_count > 2 || fret > FRET_AMOUNT {
+                    self.issue(line_index, format!("measure {}: \"{}\" can't be a single fret, skipped it, check whether these are separate notes", measure_number, digits));
+                    column += digit_count;
+                    pending = Pending::None;
+                    continue
+                }
+
+                match pending {
+                    Pending::BendTarget => {
+                        // "7b9" bends up to the pitch of fret 9
+                        if let Some(index) = previous_note {
+                            let bent_fret = self.notes[index].fret;
+                            if let (Some(bend), true) = (self.notes[index].bend.as_mut(), fret > bent_fret) {
+                                bend.semitones = (fret - bent_fret) as f32;
+                            }
+                        }
+                    }
+                    Pending::Release => {}
+                    _ => {
+                        let (start_quarters, measure_end_quarters, palm_mute) = position(column);
+                        let mut note = TabNote { start_quarters, measure_end_quarters, string_index, fret, harmonic, palm_mute, ..Default::default() };
+
+                        if let Pending::Legato(legato) = pending {
+                            note.legato = Some(legato);
+                        }
+                        if pending == Pending::Slide {
+                            if let Some(index) = previous_note {
+                                self.notes[index].slide_to_fret = Some(fret);
+                            }
+                        }
+
+                        previous_note = Some(self.notes.len());
+                        self.notes.push(note);
+                        harmonic = false;
+                    }
+                }
+
+                pending = Pending::None;
+                column += digit_count;
+                continue
+            }
+
+            match character {
+                '-' | ' ' | '*' | ':' | '.' | '(' | ')' | '|' => {}
+                'h' | 'H' => pending = Pending::Legato(Legato::HammerOn),
+                'p' | 'P' => pending = Pending::Legato(Legato::PullOff),
+                '/' | '\\' | 's' | 'S' => pending = Pending::Slide,
+                'b' | 'B' => {
+                    // A bend without a target fret is a full bend
+                    if let Some(index) = previous_note {
+                        self.notes[index].bend = Some(Bend { semitones: 2.0, curve: vec![] });
+                        pending = Pending::BendTarget;
+                    }
+                }
+                'r' | 'R' => pending = Pending::Release,
+                '~' | 'v' => {
+                    if let Some(index) = previous_note {
+                        self.notes[index].vibrato = true;
+                    }
+                }
+                '<' | '[' => harmonic = true,
+                '>' | ']' => {}
+                'x' | 'X' => pending = Pending::None, // Dead notes have no pitch to play
+                _ => {
+                    if !unknown.contains(&character) {
+                        unknown.push(character);
+                    }
+                }
+            }
+
+            column += 1;
+        }
+
+        if !unknown.is_empty() {
+            let unknown: String = unknown.iter().collect();
+            self.issue(line_index, format!("measure {}: unrecognised characters \"{}\" were ignored", measure_number, unknown));
+        }
+    }
+}
+
+/// Derives the tuning from the string labels, the octave of each string is the one closest to standard tuning
+fn tuning_from_labels(labels: &[Option<String>; STRING_COUNT]) -> Option<[String; 6]> {
+    let standard = tuning_pitches(&STANDARD_TUNING.map(|note| note.to_string()))?;
+    let mut tuning: Vec<String> = vec![];
+
+    for string_index in 0..STRING_COUNT {
+        let label = labels[STRING_COUNT - 1 - string_index].as_ref()?;
+        let offset = note_name_offset(label)?;
+        let standard_pitch = standard[string_index] as i32;
+        let pitch = (standard_pitch - 6..standard_pitch + 6).find(|pitch| pitch.rem_euclid(12) == offset.rem_euclid(12))?;
+        tuning.push(note_name_from_note_number(pitch));
+    }
+
+    tuning.try_into().ok()
+}
+
+/// Parses plain text tablature into a chart
+/// Besides the tab itself the text can contain the hint lines "title: ...", "artist: ...", "bpm: 120", "time: 3/4" and "measure 5: 3/4 bpm 90"
+pub fn parse_ascii_tab(text: &str) -> Result<AsciiTabImport, Box<dyn Error>> {
+    let mut parser = TabParser::new();
+    let lines: Vec<&str> = text.lines().collect();
+    let mut title = None;
+    let mut artists = vec![];
+    let mut tuning = None;
+
+    let mut line_index = 0;
+    while line_index < lines.len() {
+        let line = lines[line_index];
+        if !parser.is_tab_line(line) {
+            if !parser.read_hint(line_index, line, &mut title, &mut artists) {
+                // Lines that look like an attempt at a tab line are reported, other text is ignored
+                if line.matches('-').count() >= 8 && line.contains(|character: char| character.is_ascii_digit()) && !line.contains('|') {
+                    parser.issue(line_index, "looks like a string line but has no bar lines, skipped it".to_string());
+                }
+            }
+            line_index += 1;
+            continue
+        }
+
+        let block_start = line_index;
+        while line_index < lines.len() && parser.is_tab_line(lines[line_index]) {
+            line_index += 1;
+        }
+        let block = &lines[block_start..line_index];
+
+        if !block.len().is_multiple_of(STRING_COUNT) {
+            parser.issue(block_start, format!("found {} string lines in a row, expected {} per system, skipped these lines", block.len(), STRING_COUNT));
+            continue
+        }
+
+        // Palm mutes are annotated on the line above the system
+        let palm_mute_line = block_start.checked_sub(1).map(|index| lines[index]).filter(|line| line.contains("PM") || line.contains("P.M."));
+
+        for (system_index, system) in block.chunks(STRING_COUNT).enumerate() {
+            let first_line_index = block_start + system_index * STRING_COUNT;
+            let labels = parser.read_system(first_line_index, system, if system_index == 0 { palm_mute_line } else { None });
+            if tuning.is_none() {
+                tuning = labels.as_ref().and_then(tuning_from_labels);
+            }
+        }
+    }
+
+    if parser.notes.is_empty() {
+        let issues: Vec<String> = parser.issues.iter().map(|issue| issue.to_string()).collect();
+        return Err(format!("no notes found in the tab\n{}", issues.join("\n")).into())
+    }
+    if !parser.has_bpm {
+        parser.issue(0, format!("no BPM given, assumed {}", DEFAULT_TEMPO));
+    }
+
+    // Tab has no durations, notes last until the next note starts
+    let mut starts: Vec<f64> = parser.notes.iter().map(|note| note.start_quarters).collect();
+    starts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    starts.dedup();
+
+    let tempo_changes = parser.tempo_changes.clone();
+    let mut chart = Chart {
+        notes: parser.notes.iter().map(|note| {
+            let next_start = starts.iter().copied().find(|start| *start > note.start_quarters).unwrap_or(note.measure_end_quarters);
+            let start_time_seconds = quarters_to_seconds(&tempo_changes, note.start_quarters);
+            NoteEvent {
+                start_time_seconds: start_time_seconds as f32,
+                duration_seconds: (quarters_to_seconds(&tempo_changes, next_start) - start_time_seconds) as f32,
+                string_index: note.string_index,
+                fret_index: note.fret,
+                legato: note.legato,
+                slide_to_fret: note.slide_to_fret,
+                bend: note.bend.clone(),
+                palm_mute: note.palm_mute,
+                harmonic: note.harmonic,
+                vibrato: note.vibrato,
+                ..Default::default()
+            }
+        }).collect(),
+        tempo_map: measures_to_tempo_map(&parser.measures, &tempo_changes),
+        ..Default::default()
+    };
+    chart.sort_notes();
+
+    parser.issues.sort_by_key(|issue| issue.line);
+
+    Ok(AsciiTabImport {
+        imported_song: ImportedSong { title, artists, tuning, chart, issues: vec![] },
+        issues: parser.issues,
+    })
+}
+
+pub fn import_ascii_tab(path: &Path) -> Result<AsciiTabImport, Box<dyn Error>> {
+    parse_ascii_tab(&fs::read_to_string(path)?)
+}
@@ -0,0 +1,837 @@
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+use crate::helpers::{chart::Chart, notes::{Bend, BendPoint, Legato, NoteEvent}, tuning::note_name_from_note_number};
+
+use super::imported_song::{measures_to_tempo_map, quarters_to_seconds, ImportedSong};
+
+// Bends are stored in 25 per quarter tone, so 100 is a full bend of 2 semitones, at positions from 0 to 60
+const BEND_SEMITONE: f32 = 50.0;
+const BEND_POSITION: f32 = 60.0;
+
+const MIDI_CHANNEL_COUNT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Version {
+    Gp3,
+    Gp4,
+    Gp5_00,
+    Gp5_10,
+}
+
+impl Version {
+    fn is_gp5(&self) -> bool {
+        matches!(self, Version::Gp5_00 | Version::Gp5_10)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuitarProTrack {
+    pub name: String,
+    pub tuning: Vec<i32>, // MIDI pitch of each string, highest string first
+    pub is_percussion: bool,
+    pub capo: i32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MeasureHeader {
+    numerator: u8,
+    denominator: u8,
+    repeat_open: bool,
+    repeat_close: i32, // How many times to jump back to the repeat start
+    repeat_alternative: u8, // Bit mask of the repeat passes this measure is played on
+}
+
+impl MeasureHeader {
+    fn length_quarters(&self) -> f64 {
+        self.numerator as f64 * 4.0 / self.denominator as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct GpNote {
+    string: usize, // 1 is the highest string
+    fret: i32,
+    is_tie: bool,
+    is_dead: bool,
+    bend: Option<Bend>,
+    hammer: bool, // Hammer-on or pull-off into the next note on the string
+    slide: bool, // Slide into the next note on the string
+    palm_mute: bool,
+    harmonic: bool,
+    vibrato: bool,
+    tremolo: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct GpBeat {
+    start_quarters: f64, // Relative to the start of the measure
+    duration_quarters: f64,
+    notes: Vec<GpNote>,
+    tempo: Option<i32>,
+}
+
+/// Raw contents of a Guitar Pro file, only what is needed to build a chart
+#[derive(Debug, Clone)]
+pub struct GuitarProSong {
+    pub title: String,
+    pub artist: String,
+    pub tempo: i32,
+    pub tracks: Vec<GuitarProTrack>,
+    measure_headers: Vec<MeasureHeader>,
+    measures: Vec<Vec<Vec<GpBeat>>>, // Measure, track, beats of all voices
+}
+
+struct GpReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    version: Version,
+}
+
+impl<'a> GpReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if self.position + count > self.data.len() {
+            return Err(format!("unexpected end of file at byte {}", self.position).into())
+        }
+        let bytes = &self.data[self.position..self.position + count];
+        self.position += count;
+
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), Box<dyn Error>> {
+        self.read_bytes(count)?;
+        Ok(())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_i8(&mut self) -> Result<i8, Box<dyn Error>> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    fn read_bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    fn read_i16(&mut self) -> Result<i16, Box<dyn Error>> {
+        let bytes = self.read_bytes(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, Box<dyn Error>> {
+        let bytes = self.read_bytes(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn read_string(&mut self, size: usize, length: usize) -> Result<String, Box<dyn Error>> {
+        let bytes = self.read_bytes(size)?;
+        // Strings are stored in the Windows-1252 code page, which matches Latin-1 for letters
+        Ok(bytes[..length.min(size)].iter().map(|&byte| byte as char).collect())
+    }
+
+    /// Length byte followed by a fixed amount of bytes
+    fn read_byte_size_string(&mut self, size: usize) -> Result<String, Box<dyn Error>> {
+        let length = self.read_u8()? as usize;
+        self.read_string(size, length)
+    }
+
+    /// Size int, then a length byte followed by the string
+    fn read_int_byte_size_string(&mut self) -> Result<String, Box<dyn Error>> {
+        let size = self.read_i32()?.max(1) as usize - 1;
+        let length = self.read_u8()? as usize;
+        self.read_string(size, length)
+    }
+
+    /// Length int followed by the string
+    fn read_int_size_string(&mut self) -> Result<String, Box<dyn Error>> {
+        let length = self.read_i32()?.max(0) as usize;
+        self.read_string(length, length)
+    }
+
+    fn read_version(&mut self) -> Result<Version, Box<dyn Error>> {
+        let version = self.read_byte_size_string(30)?;
+        let version = match version.get(version.len().saturating_sub(5)..) {
+            Some("v3.00") => Version::Gp3,
+            Some("v4.00") | Some("v4.06") | Some("L4.06") => Version::Gp4,
+            Some("v5.00") => Version::Gp5_00,
+            Some("v5.10") => Version::Gp5_10,
+            _ => return Err(format!("unsupported Guitar Pro version \"{}\"", version).into()),
+        };
+        self.version = version;
+
+        Ok(version)
+    }
+
+    fn read_song(&mut self) -> Result<GuitarProSong, Box<dyn Error>> {
+        self.read_version()?;
+
+        // Song information
+        let title = self.read_int_byte_size_string()?;
+        let _subtitle = self.read_int_byte_size_string()?;
+        let artist = self.read_int_byte_size_string()?;
+        let information_count = if self.version.is_gp5() { 6 } else { 5 };
+        for _ in 0..information_count {
+            self.read_int_byte_size_string()?;
+        }
+        let notice_lines = self.read_i32()?;
+        for _ in 0..notice_lines {
+            self.read_int_byte_size_string()?;
+        }
+
+        if !self.version.is_gp5() {
+            let _triplet_feel = self.read_bool()?;
+        }
+
+        // Lyrics
+        if self.version != Version::Gp3 {
+            self.read_i32()?;
+            for _ in 0..5 {
+                self.read_i32()?;
+                self.read_int_size_string()?;
+            }
+        }
+
+        let tempo = match self.version {
+            Version::Gp3 => {
+                let tempo = self.read_i32()?;
+                self.read_i32()?; // Key
+                tempo
+            }
+            Version::Gp4 => {
+                let tempo = self.read_i32()?;
+                self.read_i32()?; // Key
+                self.read_i8()?; // Octave
+                tempo
+            }
+            Version::Gp5_00 | Version::Gp5_10 => {
+                // Master effect
+                if self.version == Version::Gp5_10 {
+                    self.read_i32()?;
+                    self.read_i32()?;
+                    self.skip(11)?;
+                }
+
+                // Page setup
+                self.skip(7 * 4 + 2)?;
+                for _ in 0..10 {
+                    self.read_int_byte_size_string()?;
+                }
+
+                self.read_int_byte_size_string()?; // Tempo name
+                let tempo = self.read_i32()?;
+                if self.version == Version::Gp5_10 {
+                    self.read_bool()?; // Hide tempo
+                }
+                self.read_i8()?; // Key
+                self.read_i32()?; // Octave
+                tempo
+            }
+        };
+
+        // MIDI channels
+        self.skip(MIDI_CHANNEL_COUNT * 12)?;
+
+        if self.version.is_gp5() {
+            // Directions and master reverb
+            self.skip(19 * 2)?;
+            self.read_i32()?;
+        }
+
+        let measure_count = self.read_i32()?.max(0) as usize;
+        let track_count = self.read_i32()?.max(0) as usize;
+
+        let mut measure_headers: Vec<MeasureHeader> = vec![];
+        for index in 0..measure_count {
+            let header = self.read_measure_header(index, measure_headers.last())?;
+            measure_headers.push(header);
+        }
+
+        let mut tracks = vec![];
+        for index in 0..track_count {
+            tracks.push(self.read_track(index)?);
+        }
+        if self.version.is_gp5() {
+            self.skip(if self.version == Version::Gp5_00 { 2 } else { 1 })?;
+        }
+
+        let mut measures = vec![];
+        for _ in 0..measure_count {
+            let mut measure = vec![];
+            for track in &tracks {
+                measure.push(self.read_measure(track)?);
+            }
+            measures.push(measure);
+        }
+
+        Ok(GuitarProSong { title, artist, tempo, tracks, measure_headers, measures })
+    }
+
+    fn read_measure_header(&mut self, index: usize, previous: Option<&MeasureHeader>) -> Result<MeasureHeader, Box<dyn Error>> {
+        if self.version.is_gp5() && index > 0 {
+            self.skip(1)?;
+        }
+
+        let flags = self.read_u8()?;
+        let mut header = MeasureHeader {
+            numerator: previous.map(|previous| previous.numerator).unwrap_or(4),
+            denominator: previous.map(|previous| previous.denominator).unwrap_or(4),
+            ..Default::default()
+        };
+
+        if flags & 0x01 != 0 {
+            header.numerator = self.read_i8()?.max(1) as u8;
+        }
+        if flags & 0x02 != 0 {
+            header.denominator = self.read_i8()?.max(1) as u8;
+        }
+        header.repeat_open = flags & 0x04 != 0;
+        if flags & 0x08 != 0 {
+            let repeat_close = self.read_i8()? as i32;
+            header.repeat_close = if self.version.is_gp5() { repeat_close - 1 } else { repeat_close };
+        }
+
+        if self.version.is_gp5() {
+            if flags & 0x20 != 0 {
+                self.read_marker()?;
+            }
+            if flags & 0x10 != 0 {
+                header.repeat_alternative = self.read_u8()?;
+            }
+            if flags & 0x40 != 0 {
+                self.skip(2)?; // Key signature
+            }
+            if flags & 0x03 != 0 {
+                self.skip(4)?; // Beams
+            }
+            if flags & 0x10 == 0 {
+                self.skip(1)?;
+            }
+            self.read_u8()?; // Triplet feel
+        } else {
+            if flags & 0x10 != 0 {
+                // Older versions store the number of the ending instead of a mask
+                let alternative = self.read_u8()?;
+                header.repeat_alternative = if alternative > 0 && alternative <= 8 { 1 << (alternative - 1) } else { 0 };
+            }
+            if flags & 0x20 != 0 {
+                self.read_marker()?;
+            }
+            if flags & 0x40 != 0 {
+                self.skip(2)?; // Key signature
+            }
+        }
+
+        Ok(header)
+    }
+
+    fn read_marker(&mut self) -> Result<(), Box<dyn Error>> {
+        self.read_int_byte_size_string()?;
+        self.skip(4)?; // Color
+
+        Ok(())
+    }
+
+    fn read_track(&mut self, index: usize) -> Result<GuitarProTrack, Box<dyn Error>> {
+        if self.version.is_gp5() && (index == 0 || self.version == Version::Gp5_00) {
+            self.skip(1)?;
+        }
+
+        let flags = self.read_u8()?;
+        let name = self.read_byte_size_string(40)?;
+        let string_count = self.read_i32()?.clamp(0, 7) as usize;
+        let mut tuning = vec![];
+        for string in 0..7 {
+            let pitch = self.read_i32()?;
+            if string < string_count {
+                tuning.push(pitch);
+            }
+        }
+        self.read_i32()?; // Port
+        self.read_i32()?; // Channel
+        self.read_i32()?; // Effect channel
+        self.read_i32()?; // Fret count
+        let capo = self.read_i32()?;
+        self.skip(4)?; // Color
+
+        if self.version.is_gp5() {
+            self.read_i16()?; // Display flags
+            self.read_u8()?; // Auto accentuation
+            self.read_u8()?; // Bank
+
+            // RSE settings
+            self.read_u8()?;
+            self.skip(3 * 4 + 12)?;
+            self.read_rse_instrument()?;
+            if self.version == Version::Gp5_10 {
+                self.skip(4)?; // Equalizer
+                self.read_int_byte_size_string()?;
+                self.read_int_byte_size_string()?;
+            }
+        }
+
+        Ok(GuitarProTrack { name, tuning, is_percussion: flags & 0x01 != 0, capo })
+    }
+
+    fn read_rse_instrument(&mut self) -> Result<(), Box<dyn Error>> {
+        self.skip(3 * 4)?;
+        if self.version == Version::Gp5_00 {
+            self.skip(3)?;
+        } else {
+            self.skip(4)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_measure(&mut self, track: &GuitarProTrack) -> Result<Vec<GpBeat>, Box<dyn Error>> {
+        let mut beats = vec![];
+        let voice_count = if self.version.is_gp5() { 2 } else { 1 };
+
+        for _ in 0..voice_count {
+            let mut start_quarters = 0.0;
+            let beat_count = self.read_i32()?.max(0);
+            for _ in 0..beat_count {
+                let beat = self.read_beat(track, start_quarters)?;
+                start_quarters += beat.duration_quarters;
+                beats.push(beat);
+            }
+        }
+
+        if self.version.is_gp5() {
+            self.read_u8()?; // Line break
+        }
+
+        Ok(beats)
+    }
+
+    fn read_beat(&mut self, track: &GuitarProTrack, start_quarters: f64) -> Result<GpBeat, Box<dyn Error>> {
+        let flags = self.read_u8()?;
+        let mut is_empty = false;
+        if flags & 0x40 != 0 {
+            is_empty = self.read_u8()? == 0x00;
+        }
+
+        let duration_quarters = self.read_duration(flags)?;
+        let mut beat = GpBeat {
+            start_quarters,
+            duration_quarters: if is_empty { 0.0 } else { duration_quarters },
+            ..Default::default()
+        };
+
+        if flags & 0x02 != 0 {
+            self.read_chord()?;
+        }
+        if flags & 0x04 != 0 {
+            self.read_int_byte_size_string()?; // Text
+        }
+
+        let mut beat_effects = GpNote::default();
+        if flags & 0x08 != 0 {
+            beat_effects = self.read_beat_effects()?;
+        }
+        if flags & 0x10 != 0 {
+            beat.tempo = self.read_mix_table_change()?;
+        }
+
+        let string_flags = self.read_u8()?;
+        for string in 1..=track.tuning.len() {
+            if string_flags & (1 << (7 - string)) != 0 {
+                let mut note = self.read_note(string)?;
+                note.vibrato |= beat_effects.vibrato;
+                note.harmonic |= beat_effects.harmonic;
+                beat.notes.push(note);
+            }
+        }
+
+        if self.version.is_gp5() {
+            let flags = self.read_i16()?;
+            if flags & 0x0800 != 0 {
+                self.read_u8()?;
+            }
+        }
+
+        Ok(beat)
+    }
+
+    /// Duration of a beat in quarter notes
+    fn read_duration(&mut self, flags: u8) -> Result<f64, Box<dyn Error>> {
+        let value = 2_f64.powi(self.read_i8()? as i32 + 2);
+        let mut quarters = 4.0 / value;
+
+        if flags & 0x01 != 0 {
+            quarters *= 1.5;
+        }
+        if flags & 0x20 != 0 {
+            let enters = self.read_i32()?;
+            let times = match enters {
+                3 => 2,
+                5..=7 => 4,
+                9..=13 => 8,
+                _ => enters,
+            };
+            quarters *= times as f64 / enters.max(1) as f64;
+        }
+
+        Ok(quarters)
+    }
+
+    fn read_chord(&mut self) -> Result<(), Box<dyn Error>> {
+        let new_format = self.read_bool()?;
+
+        if !new_format {
+            self.read_int_byte_size_string()?;
+            let first_fret = self.read_i32()?;
+            if first_fret != 0 {
+                self.skip(6 * 4)?;
+            }
+        } else if self.version == Version::Gp3 {
+            self.skip(1 + 3 + 5 * 4 + 1)?;
+            self.read_byte_size_string(22)?;
+            self.skip(3 * 4 + 4 + 6 * 4 + 4 + 6 * 4 + 7 + 1)?;
+        } else {
+            self.skip(1 + 3 + 3 + 2 * 4 + 1)?;
+            self.read_byte_size_string(22)?;
+            self.skip(3 + 4 + 7 * 4 + 1 + 5 * 3 + 7 + 1 + 7 + 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Effects that apply to every note of a beat, returned as a note so they can be merged
+    fn read_beat_effects(&mut self) -> Result<GpNote, Box<dyn Error>> {
+        let mut effects = GpNote::default();
+
+        if self.version == Version::Gp3 {
+            let flags = self.read_u8()?;
+            effects.vibrato = flags & 0x03 != 0;
+            effects.harmonic = flags & 0x0C != 0;
+            if flags & 0x20 != 0 {
+                self.read_u8()?; // Slap effect
+                self.read_i32()?; // Tremolo bar
+            }
+            if flags & 0x40 != 0 {
+                self.skip(2)?; // Stroke
+            }
+        } else {
+            let flags1 = self.read_u8()?;
+            let flags2 = self.read_u8()?;
+            effects.vibrato = flags1 & 0x03 != 0;
+            if flags1 & 0x20 != 0 {
+                self.read_i8()?; // Slap effect
+            }
+            if flags2 & 0x04 != 0 {
+                self.read_bend()?; // Tremolo bar
+            }
+            if flags1 & 0x40 != 0 {
+                self.skip(2)?; // Stroke
+            }
+            if flags2 & 0x02 != 0 {
+                self.read_i8()?; // Pick stroke
+            }
+        }
+
+        Ok(effects)
+    }
+
+    /// Returns the new tempo if the mix table changes it
+    fn read_mix_table_change(&mut self) -> Result<Option<i32>, Box<dyn Error>> {
+        self.read_i8()?; // Instrument
+        if self.version.is_gp5() {
+            self.read_rse_instrument()?;
+            if self.version == Version::Gp5_00 {
+                self.skip(1)?;
+            }
+        }
+
+        let mut values = vec![];
+        for _ in 0..6 {
+            values.push(self.read_i8()?); // Volume, balance, chorus, reverb, phaser, tremolo
+        }
+        if self.version.is_gp5() {
+            self.read_int_byte_size_string()?; // Tempo name
+        }
+        let tempo = self.read_i32()?;
+
+        // Transition durations of the changed values
+        for value in values {
+            if value >= 0 {
+                self.read_i8()?;
+            }
+        }
+        if tempo >= 0 {
+            self.read_i8()?;
+            if self.version == Version::Gp5_10 {
+                self.read_bool()?; // Hide tempo
+            }
+        }
+
+        if self.version != Version::Gp3 {
+            self.read_i8()?; // Apply to all tracks flags
+        }
+        if self.version.is_gp5() {
+            self.read_i8()?; // Wah
+            if self.version == Version::Gp5_10 {
+                self.read_int_byte_size_string()?;
+                self.read_int_byte_size_string()?;
+            }
+        }
+
+        Ok(if tempo > 0 { Some(tempo) } else { None })
+    }
+
+    fn read_note(&mut self, string: usize) -> Result<GpNote, Box<dyn Error>> {
+        let flags = self.read_u8()?;
+        let mut note = GpNote { string, ..Default::default() };
+
+        if flags & 0x20 != 0 {
+            let note_type = self.read_u8()?;
+            note.is_tie = note_type == 2;
+            note.is_dead = note_type == 3;
+        }
+        if !self.version.is_gp5() && flags & 0x01 != 0 {
+            self.skip(2)?; // Duration and tuplet
+        }
+        if flags & 0x10 != 0 {
+            self.read_i8()?; // Velocity
+        }
+        if flags & 0x20 != 0 {
+            note.fret = self.read_i8()? as i32;
+        }
+        if flags & 0x80 != 0 {
+            self.skip(2)?; // Fingering
+        }
+        if self.version.is_gp5() {
+            if flags & 0x01 != 0 {
+                self.skip(8)?; // Duration percent
+            }
+            self.read_u8()?;
+        }
+        if flags & 0x08 != 0 {
+            self.read_note_effects(&mut note)?;
+        }
+
+        Ok(note)
+    }
+
+    fn read_note_effects(&mut self, note: &mut GpNote) -> Result<(), Box<dyn Error>> {
+        let flags1 = self.read_u8()?;
+        let flags2 = if self.version == Version::Gp3 { 0 } else { self.read_u8()? };
+
+        note.hammer = flags1 & 0x02 != 0;
+        note.palm_mute = flags2 & 0x02 != 0;
+        note.vibrato |= flags2 & 0x40 != 0;
+
+        if flags1 & 0x01 != 0 {
+            note.bend = self.read_bend()?;
+        }
+        if flags1 & 0x10 != 0 {
+            self.skip(if self.version.is_gp5() { 5 } else { 4 })?; // Grace note
+        }
+
+        if self.version == Version::Gp3 {
+            note.slide = flags1 & 0x04 != 0;
+            return Ok(())
+        }
+
+        if flags2 & 0x04 != 0 {
+            self.read_i8()?;
+            note.tremolo = true;
+        }
+        if flags2 & 0x08 != 0 {
+            let slide = self.read_u8()?;
+            // Only slides between two notes, slides into or out of nowhere don't have a target fret
+            note.slide = if self.version.is_gp5() { slide & 0x03 != 0 } else { slide == 1 || slide == 2 };
+        }
+        if flags2 & 0x10 != 0 {
+            note.harmonic = true;
+            let harmonic_type = self.read_i8()?;
+            if self.version.is_gp5() {
+                match harmonic_type {
+                    2 => self.skip(3)?, // Artificial
+                    3 => self.skip(1)?, // Tapped
+                    _ => {}
+                }
+            }
+        }
+        if flags2 & 0x20 != 0 {
+            self.skip(2)?; // Trill
+        }
+
+        Ok(())
+    }
+
+    fn read_bend(&mut self) -> Result<Option<Bend>, Box<dyn Error>> {
+        self.read_i8()?; // Type
+        self.read_i32()?; // Value
+        let point_count = self.read_i32()?.max(0);
+
+        let mut curve = vec![];
+        for _ in 0..point_count {
+            let position = self.read_i32()? as f32 / BEND_POSITION;
+            let semitones = self.read_i32()? as f32 / BEND_SEMITONE;
+            self.read_bool()?; // Vibrato
+            curve.push(BendPoint { position, semitones });
+        }
+
+        let semitones = curve.iter().map(|point| point.semitones).fold(0.0, f32::max);
+        if semitones <= 0.0 {
+            return Ok(None)
+        }
+
+        Ok(Some(Bend { semitones, curve }))
+    }
+}
+
+impl GuitarProSong {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match path.extension().and_then(|extension| extension.to_str()).map(|extension| extension.to_lowercase()).as_deref() {
+            Some("gpx") | Some("gp") => return Err("Guitar Pro 6 and 7 files are not supported, export the song as .gp5 from Guitar Pro".into()),
+            _ => {}
+        }
+
+        let data = fs::read(path)?;
+        let mut reader = GpReader { data: &data, position: 0, version: Version::Gp3 };
+        reader.read_song()
+    }
+
+    /// The first track that can be played on a six string guitar
+    pub fn default_track_index(&self) -> Option<usize> {
+        self.tracks.iter().position(|track| !track.is_percussion && track.tuning.len() == 6)
+    }
+
+    /// Measure indexes in playback order, with repeats and alternate endings unfolded
+    fn playback_order(&self) -> Vec<usize> {
+        let mut order = vec![];
+        let mut repeat_start = 0;
+        let mut repeat_pass = 1;
+        let mut index = 0;
+
+        while index < self.measure_headers.len() {
+            let header = &self.measure_headers[index];
+            if header.repeat_open && index != repeat_start {
+                repeat_start = index;
+                repeat_pass = 1;
+            }
+
+            let is_played = header.repeat_alternative == 0 || (repeat_pass <= 8 && header.repeat_alternative & (1 << (repeat_pass - 1)) != 0);
+            if is_played {
+                order.push(index);
+            }
+
+            if is_played && header.repeat_close > 0 && repeat_pass <= header.repeat_close {
+                repeat_pass += 1;
+                index = repeat_start;
+                continue
+            }
+            if header.repeat_close > 0 && repeat_pass > header.repeat_close {
+                repeat_pass = 1;
+                repeat_start = index + 1;
+            }
+
+            index += 1;
+        }
+
+        order
+    }
+
+    pub fn to_imported_song(&self, track_index: usize) -> Result<ImportedSong, Box<dyn Error>> {
+        let track = self.tracks.get(track_index).ok_or(format!("track {} does not exist", track_index))?;
+        if track.is_percussion || track.tuning.len() != 6 {
+            return Err(format!("track \"{}\" is not a six string guitar track", track.name).into())
+        }
+
+        // Collect the notes and tempo changes with their position in quarter notes
+        let mut tempo_changes: Vec<(f64, f64)> = vec![(0.0, self.tempo.max(1) as f64)];
+        let mut notes: Vec<(f64, f64, GpNote)> = vec![];
+        let mut measures: Vec<(f64, u32, u32)> = vec![];
+        let mut measure_start_quarters = 0.0;
+
+        for measure_index in self.playback_order() {
+            let header = &self.measure_headers[measure_index];
+            measures.push((measure_start_quarters, header.numerator as u32, header.denominator as u32));
+
+            for (index, beats) in self.measures[measure_index].iter().enumerate() {
+                for beat in beats {
+                    let beat_start = measure_start_quarters + beat.start_quarters;
+                    if let Some(tempo) = beat.tempo {
+                        tempo_changes.push((beat_start, tempo as f64));
+                    }
+
+                    if index == track_index {
+                        for note in &beat.notes {
+                            if note.is_dead {
+                                continue
+                            }
+
+                            // Tied notes lengthen the previous note on the same string
+                            if note.is_tie {
+                                if let Some((start, duration, _)) = notes.iter_mut().rev().find(|(_, _, previous)| previous.string == note.string) {
+                                    *duration = beat_start + beat.duration_quarters - *start;
+                                }
+                                continue
+                            }
+
+                            notes.push((beat_start, beat.duration_quarters, note.clone()));
+                        }
+                    }
+                }
+            }
+
+            measure_start_quarters += header.length_quarters();
+        }
+
+        tempo_changes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let string_count = track.tuning.len();
+        let mut note_events: Vec<NoteEvent> = notes.iter().map(|(start, duration, note)| {
+            let start_time_seconds = quarters_to_seconds(&tempo_changes, *start);
+            NoteEvent {
+                start_time_seconds: start_time_seconds as f32,
+                duration_seconds: (quarters_to_seconds(&tempo_changes, start + duration) - start_time_seconds) as f32,
+                string_index: string_count - note.string,
+                fret_index: (note.fret + track.capo).max(0) as usize,
+                bend: note.bend.clone(),
+                palm_mute: note.palm_mute,
+                harmonic: note.harmonic,
+                vibrato: note.vibrato,
+                tremolo: note.tremolo,
+                ..Default::default()
+            }
+        }).collect();
+
+        // Hammer-ons, pull-offs and slides are stored on the note they start from
+        let mut next_on_string: HashMap<usize, usize> = HashMap::new();
+        for index in (0..notes.len()).rev() {
+            let note = &notes[index].2;
+            if let Some(&next_index) = next_on_string.get(&note.string) {
+                let next_fret = note_events[next_index].fret_index;
+                if note.hammer {
+                    note_events[next_index].legato = Some(if next_fret >= note_events[index].fret_index { Legato::HammerOn } else { Legato::PullOff });
+                }
+                if note.slide {
+                    note_events[index].slide_to_fret = Some(next_fret);
+                }
+            }
+            next_on_string.insert(note.string, index);
+        }
+
+        let mut chart = Chart { notes: note_events, tempo_map: measures_to_tempo_map(&measures, &tempo_changes), ..Default::default() };
+        chart.sort_notes();
+
+        let mut tuning = track.tuning.iter().rev().map(|&pitch| note_name_from_note_number(pitch));
+
+        let mut imported_song = ImportedSong {
+            title: if self.title.is_empty() { None } else { Some(self.title.clone()) },
+            artists: if self.artist.is_empty() { vec![] } else { vec![self.artist.clone()] },
+            tuning: Some(std::array::from_fn(|_| tuning.next().unwrap())),
+            chart,
+            issues: vec![],
+        };
+        // The capo can push the highest notes past the last fret
+        imported_song.leave_out_notes_off_fretboard();
+
+        Ok(imported_song)
+    }
+}
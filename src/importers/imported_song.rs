@@ -0,0 +1,216 @@
+use std::{error::Error, fs, path::Path};
+
+use uuid::Uuid;
+
+use crate::{constants::ingame::FRET_AMOUNT, helpers::{audio_info::AudioInfo, chart::{chart_filename, Chart, SongChart, CHART_FILENAME}, fingering::tuning_pitches, persistence::get_songs_dir, song_audio::is_audio_file, tempo_map::{TempoChange, TempoMap}, tuning::STANDARD_TUNING}, resources::song_library::SongMetadata};
+
+use super::{ascii_tab::import_ascii_tab, guitar_pro::GuitarProSong, midi::MidiSong, music_xml::MusicXmlSong};
+
+// Tempo of a file without tempo information, in quarter notes per minute
+const DEFAULT_IMPORT_TEMPO: f64 = 120.0;
+
+/// File extensions that can be imported
+pub const IMPORT_EXTENSIONS: [&str; 10] = ["gp3", "gp4", "gp5", "mid", "midi", "musicxml", "xml", "mxl", "txt", "tab"];
+
+/// A track, a channel of a MIDI track or a part that can be imported from a file
+#[derive(Debug, Clone)]
+pub struct ImportChoice {
+    pub label: String,
+    pub index: usize, // Track or part index
+    pub channel: Option<u8>, // Only the notes of this MIDI channel, all channels when not given
+}
+
+/// A loaded file that can be imported, formats without a tuning are fingered on standard tuning
+pub enum ImportSource {
+    GuitarPro(GuitarProSong),
+    Midi(MidiSong),
+    MusicXml(MusicXmlSong),
+    AsciiTab(ImportedSong), // Tabs have a single track
+}
+
+impl ImportSource {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+
+        Ok(match extension.as_str() {
+            "gp3" | "gp4" | "gp5" | "gpx" | "gp" => Self::GuitarPro(GuitarProSong::load_from_file(path)?),
+            "mid" | "midi" => Self::Midi(MidiSong::load_from_file(path)?),
+            "musicxml" | "xml" | "mxl" => Self::MusicXml(MusicXmlSong::load_from_file(path)?),
+            "txt" | "tab" => {
+                let import = import_ascii_tab(path)?;
+                let mut imported_song = import.imported_song;
+                imported_song.issues.extend(import.issues.iter().map(|issue| issue.to_string()));
+                Self::AsciiTab(imported_song)
+            }
+            _ => return Err(format!("{:?} is not a supported file", path).into()),
+        })
+    }
+
+    /// Everything in the file that can be played on a six string guitar
+    pub fn choices(&self) -> Vec<ImportChoice> {
+        match self {
+            Self::GuitarPro(song) => song.tracks.iter().enumerate()
+                .filter(|(_, track)| !track.is_percussion && track.tuning.len() == 6)
+                .map(|(index, track)| ImportChoice { label: track.name.clone(), index, channel: None })
+                .collect(),
+            // A track with notes on more than one channel can also be imported a channel at a time
+            Self::Midi(song) => song.tracks.iter().enumerate()
+                .filter(|(_, track)| track.note_count > 0)
+                .flat_map(|(index, track)| {
+                    let all_channels = ImportChoice { label: track.name.clone(), index, channel: None };
+                    let channels = track.channels.iter()
+                        .filter(|_| track.channels.len() > 1)
+                        .map(move |&channel| ImportChoice { label: format!("{} (channel {})", track.name, channel + 1), index, channel: Some(channel) });
+                    std::iter::once(all_channels).chain(channels)
+                })
+                .collect(),
+            Self::MusicXml(song) => song.parts.iter().enumerate()
+                .filter(|(_, part)| part.has_tablature)
+                .map(|(index, part)| ImportChoice { label: part.name.clone(), index, channel: None })
+                .collect(),
+            Self::AsciiTab(_) => vec![ImportChoice { label: "tab".to_string(), index: 0, channel: None }],
+        }
+    }
+
+    /// Position in the choices of the track the file would be imported from by default
+    pub fn default_choice(&self, choices: &[ImportChoice]) -> usize {
+        let default_index = match self {
+            Self::GuitarPro(song) => song.default_track_index(),
+            Self::Midi(song) => song.default_track_index(),
+            Self::MusicXml(song) => song.default_part_index(),
+            Self::AsciiTab(_) => None,
+        };
+        choices.iter().position(|choice| Some(choice.index) == default_index && choice.channel.is_none()).unwrap_or(0)
+    }
+
+    pub fn import(&self, choice: &ImportChoice) -> Result<ImportedSong, Box<dyn Error>> {
+        match self {
+            Self::GuitarPro(song) => song.to_imported_song(choice.index),
+            Self::Midi(song) => song.to_imported_song(choice.index, choice.channel, &STANDARD_TUNING.map(|note| note.to_string())),
+            Self::MusicXml(song) => song.to_imported_song(choice.index),
+            Self::AsciiTab(imported_song) => Ok(imported_song.clone()),
+        }
+    }
+}
+
+/// Converts a position in quarter notes to seconds, with the tempo changes as (quarter notes, BPM) sorted by position
+pub fn quarters_to_seconds(tempo_changes: &[(f64, f64)], quarters: f64) -> f64 {
+    let mut seconds = 0.0;
+    for (index, (change_quarters, bpm)) in tempo_changes.iter().enumerate() {
+        if *change_quarters >= quarters {
+            break
+        }
+        let next_quarters = tempo_changes.get(index + 1).map(|change| change.0).unwrap_or(f64::MAX).min(quarters);
+        seconds += (next_quarters - change_quarters) * 60.0 / bpm;
+    }
+    seconds
+}
+
+/// Tempo map of an import, with the measures as (quarter notes, beats per measure, beat unit) in the order they are played
+/// A tempo change inside a measure shows on the grid from the next measure, as the changes of a tempo map start a measure
+pub fn measures_to_tempo_map(measures: &[(f64, u32, u32)], tempo_changes: &[(f64, f64)]) -> TempoMap {
+    let mut tempo_map = TempoMap::default();
+    let mut next_measure_quarters = 0.0;
+
+    for &(start_quarters, beats_per_measure, beat_unit) in measures {
+        let beat_unit = beat_unit.max(1);
+        let quarter_bpm = tempo_changes.iter().rev()
+            .find(|change| change.0 <= start_quarters + f64::EPSILON)
+            .or(tempo_changes.first())
+            .map(|change| change.1)
+            .unwrap_or(DEFAULT_IMPORT_TEMPO);
+        let change = TempoChange {
+            start_seconds: quarters_to_seconds(tempo_changes, start_quarters) as f32,
+            bpm: (quarter_bpm * beat_unit as f64 / 4.0) as f32,
+            beats_per_measure,
+            beat_unit,
+        };
+
+        // Measures that are shorter or longer than their time signature, like pickups, restart the grid
+        let continues_grid = (start_quarters - next_measure_quarters).abs() < 1e-6;
+        let is_same = tempo_map.changes.last().is_some_and(|last| last.bpm == change.bpm && last.beats_per_measure == beats_per_measure && last.beat_unit == beat_unit);
+        if !(continues_grid && is_same) {
+            tempo_map.changes.push(change);
+        }
+        next_measure_quarters = start_quarters + beats_per_measure as f64 * 4.0 / beat_unit as f64;
+    }
+
+    tempo_map
+}
+
+/// A chart converted from another format, together with whatever song information the format contained
+#[derive(Debug, Clone)]
+pub struct ImportedSong {
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub tuning: Option<[String; 6]>, // Lowest string first
+    pub chart: Chart,
+    pub issues: Vec<String>, // Parts of the file that were left out of the chart
+}
+
+impl ImportedSong {
+    /// Leaves out notes that don't fit on the fret board, like notes a capo pushes past the last fret
+    pub fn leave_out_notes_off_fretboard(&mut self) {
+        let note_amount = self.chart.notes.len();
+        self.chart.notes.retain(|note_event| note_event.is_on_fretboard());
+        if self.chart.notes.len() < note_amount {
+            self.issues.push(format!("left out {} notes that are past fret {} or off the strings", note_amount - self.chart.notes.len(), FRET_AMOUNT));
+        }
+    }
+
+    /// Replaces a chart of an existing song, the main chart when no arrangement and difficulty is given
+    /// A new chart is listed in the metadata. Importing the main chart replaces the tuning if the import has one,
+    /// the other charts share the tuning of the song, so an import in another tuning is refused
+    pub fn write_into_song(&self, song_directory: &Path, song_chart: Option<SongChart>) -> Result<(), Box<dyn Error>> {
+        let metadata_path = song_directory.join("metadata.json");
+        let mut song_metadata = SongMetadata::load_from_file(&metadata_path)?;
+        match (song_chart, &self.tuning) {
+            (Some(song_chart), Some(tuning)) if tuning_pitches(tuning) != tuning_pitches(&song_metadata.tuning) => {
+                return Err(format!("the import is in {} but the song is in {}, the {} chart has to use the tuning of the song", tuning.join(" "), song_metadata.tuning.join(" "), song_chart.label()).into())
+            }
+            (None, Some(tuning)) => song_metadata.tuning = tuning.clone(),
+            _ => {}
+        }
+        if let Some(song_chart) = song_chart {
+            song_metadata.add_chart(song_chart);
+        }
+
+        self.chart.save_to_file(&song_directory.join(chart_filename(song_chart)))?;
+        song_metadata.save_to_file(&metadata_path)?;
+
+        Ok(())
+    }
+
+    /// Adds a new song to the library using the import and an audio file
+    pub fn create_song(&self, audio_path: &Path) -> Result<SongMetadata, Box<dyn Error>> {
+        if !is_audio_file(audio_path) {
+            return Err(format!("{:?} is not an mp3, ogg, flac, wav or opus file", audio_path).into())
+        }
+        let audio_filename = format!("audio.{}", audio_path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase());
+
+        let uuid = Uuid::new_v4().to_string();
+        let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(&uuid);
+        fs::create_dir_all(&song_directory)?;
+        fs::copy(audio_path, song_directory.join(&audio_filename))?;
+        let audio_info = AudioInfo::read_from_file(&song_directory.join(&audio_filename)).ok();
+
+        let song_metadata = SongMetadata {
+            uuid,
+            title: self.title.clone().unwrap_or_else(|| "untitled".to_string()),
+            artists: self.artists.clone(),
+            tuning: self.tuning.clone().unwrap_or_else(|| STANDARD_TUNING.map(|note| note.to_string())),
+            // Without a readable audio header, leave some room for the song to ring out after the last note
+            duration_seconds: audio_info.as_ref().map(|audio_info| audio_info.duration_seconds).unwrap_or(self.chart.end_seconds() + 5.0),
+            charts: vec![],
+            audio_filename: None,
+            stems: vec![],
+            preview: None,
+            audio_info,
+            has_main_chart: true,
+        };
+        song_metadata.save_to_file(&song_directory.join("metadata.json"))?;
+        self.chart.save_to_file(&song_directory.join(CHART_FILENAME))?;
+
+        Ok(song_metadata)
+    }
+}
@@ -0,0 +1,233 @@
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+
+use crate::helpers::{chart::Chart, fingering::{assign_fingerings, tuning_pitches}, notes::NoteEvent, tempo_map::TempoMap};
+
+use super::imported_song::{measures_to_tempo_map, ImportedSong};
+
+// Channel 10 is reserved for percussion by General MIDI
+const PERCUSSION_CHANNEL: u8 = 9;
+// Tempo of a file without tempo events, in microseconds per quarter note
+const DEFAULT_TEMPO: u32 = 500_000;
+
+#[derive(Debug, Clone)]
+pub struct MidiTrack {
+    pub name: String,
+    pub channels: Vec<u8>, // Channels that have notes in the track
+    pub note_count: usize,
+}
+
+#[derive(Debug, Clone)]
+struct MidiNote {
+    start_tick: u64,
+    end_tick: u64,
+    channel: u8,
+    pitch: usize,
+}
+
+/// Notes of a Standard MIDI File, grouped by track
+pub struct MidiSong {
+    pub title: Option<String>,
+    pub tracks: Vec<MidiTrack>,
+    timing: Timing,
+    tempo_changes: Vec<(u64, u32)>, // Tick and microseconds per quarter note
+    time_signatures: Vec<(u64, u32, u32)>, // Tick, beats per measure and beat unit
+    notes: Vec<Vec<MidiNote>>,
+}
+
+impl MidiSong {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        let smf = Smf::parse(&data)?;
+
+        let mut title = None;
+        let mut tracks = vec![];
+        let mut notes = vec![];
+        let mut tempo_changes = vec![];
+        let mut time_signatures = vec![];
+
+        for (track_index, track_events) in smf.tracks.iter().enumerate() {
+            let mut name = String::new();
+            let mut track_notes: Vec<MidiNote> = vec![];
+            let mut playing: HashMap<(u8, u8), Vec<usize>> = HashMap::new();
+            let mut tick: u64 = 0;
+
+            for event in track_events {
+                tick += event.delta.as_int() as u64;
+
+                match event.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => tempo_changes.push((tick, tempo.as_int())),
+                    // The beat unit is stored as a power of two
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, _, _)) => {
+                        time_signatures.push((tick, numerator.max(1) as u32, 1u32 << denominator.min(6)));
+                    }
+                    TrackEventKind::Meta(MetaMessage::TrackName(bytes)) => name = String::from_utf8_lossy(bytes).trim().to_string(),
+                    TrackEventKind::Midi { channel, message } => {
+                        let channel = channel.as_int();
+                        match message {
+                            MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                                playing.entry((channel, key.as_int())).or_default().push(track_notes.len());
+                                track_notes.push(MidiNote { start_tick: tick, end_tick: tick, channel, pitch: key.as_int() as usize });
+                            }
+                            MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                                // Overlapping notes of the same key are ended in the order they started
+                                if let Some(started) = playing.get_mut(&(channel, key.as_int())) {
+                                    if !started.is_empty() {
+                                        let index = started.remove(0);
+                                        track_notes[index].end_tick = tick;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // The name of the first track is the song title in most files
+            if track_index == 0 && !name.is_empty() {
+                title = Some(name.clone());
+            }
+
+            let mut channels: Vec<u8> = track_notes.iter().map(|note| note.channel).collect();
+            channels.sort();
+            channels.dedup();
+            tracks.push(MidiTrack { name, channels, note_count: track_notes.len() });
+            notes.push(track_notes);
+        }
+
+        tempo_changes.sort_by_key(|change| change.0);
+        time_signatures.sort_by_key(|time_signature| time_signature.0);
+
+        Ok(Self { title, tracks, timing: smf.header.timing, tempo_changes, time_signatures, notes })
+    }
+
+    /// The track with the most notes that aren't percussion
+    pub fn default_track_index(&self) -> Option<usize> {
+        self.tracks.iter().enumerate()
+            .filter(|(_, track)| track.note_count > 0 && track.channels.iter().any(|&channel| channel != PERCUSSION_CHANNEL))
+            .max_by_key(|(_, track)| track.note_count)
+            .map(|(index, _)| index)
+    }
+
+    fn ticks_to_seconds(&self, tick: u64) -> f64 {
+        match self.timing {
+            Timing::Metrical(ticks_per_beat) => {
+                let ticks_per_beat = ticks_per_beat.as_int().max(1) as f64;
+                let mut seconds = 0.0;
+                let mut previous_tick = 0;
+                let mut tempo = DEFAULT_TEMPO;
+
+                for &(change_tick, change_tempo) in &self.tempo_changes {
+                    if change_tick >= tick {
+                        break
+                    }
+                    seconds += (change_tick - previous_tick) as f64 / ticks_per_beat * tempo as f64 / 1_000_000.0;
+                    previous_tick = change_tick;
+                    tempo = change_tempo;
+                }
+
+                seconds + (tick - previous_tick) as f64 / ticks_per_beat * tempo as f64 / 1_000_000.0
+            }
+            Timing::Timecode(fps, ticks_per_frame) => tick as f64 / (fps.as_f32() as f64 * ticks_per_frame.max(1) as f64),
+        }
+    }
+
+    /// Tempo map up to a tick, files timed in SMPTE frames have no tempo
+    fn tempo_map(&self, end_tick: u64) -> TempoMap {
+        let Timing::Metrical(ticks_per_beat) = self.timing else {
+            return TempoMap::default()
+        };
+        let ticks_per_quarter = ticks_per_beat.as_int().max(1) as u64;
+
+        let mut tempo_changes: Vec<(f64, f64)> = vec![(0.0, 60_000_000.0 / DEFAULT_TEMPO as f64)];
+        tempo_changes.extend(self.tempo_changes.iter().map(|&(tick, tempo)| (tick as f64 / ticks_per_quarter as f64, 60_000_000.0 / tempo.max(1) as f64)));
+
+        // Measures follow the time signatures, a new time signature starts a measure
+        let mut measures: Vec<(f64, u32, u32)> = vec![];
+        let mut time_signature = (4, 4);
+        let mut next_time_signature = 0;
+        let mut tick = 0;
+        while tick <= end_tick {
+            while let Some(&(change_tick, beats_per_measure, beat_unit)) = self.time_signatures.get(next_time_signature).filter(|change| change.0 <= tick) {
+                time_signature = (beats_per_measure, beat_unit);
+                tick = tick.max(change_tick);
+                next_time_signature += 1;
+            }
+            measures.push((tick as f64 / ticks_per_quarter as f64, time_signature.0, time_signature.1));
+
+            let measure_ticks = (ticks_per_quarter * 4 * time_signature.0 as u64 / time_signature.1 as u64).max(1);
+            let next_change_tick = self.time_signatures.get(next_time_signature).map(|change| change.0).unwrap_or(u64::MAX);
+            tick = (tick + measure_ticks).min(next_change_tick);
+        }
+
+        measures_to_tempo_map(&measures, &tempo_changes)
+    }
+
+    /// Converts a track to a chart, fingering the pitches on the given tuning
+    /// If a channel is given only the notes on that channel are used
+    pub fn to_imported_song(&self, track_index: usize, channel: Option<u8>, tuning: &[String; 6]) -> Result<ImportedSong, Box<dyn Error>> {
+        let track_notes = self.notes.get(track_index).ok_or(format!("track {} does not exist", track_index))?;
+        let tuning_pitches = tuning_pitches(tuning).ok_or("the tuning is not valid")?;
+
+        let mut track_notes: Vec<&MidiNote> = track_notes.iter()
+            .filter(|note| channel.map(|channel| note.channel == channel).unwrap_or(note.channel != PERCUSSION_CHANNEL))
+            .collect();
+        if track_notes.is_empty() {
+            return Err("the selected track and channel have no notes".into())
+        }
+        track_notes.sort_by_key(|note| (note.start_tick, note.pitch));
+
+        // Notes that start together are fingered as one chord
+        let mut chords: Vec<Vec<&MidiNote>> = vec![];
+        for note in track_notes {
+            match chords.last_mut() {
+                Some(chord) if chord[0].start_tick == note.start_tick => chord.push(note),
+                _ => chords.push(vec![note]),
+            }
+        }
+
+        let chord_pitches: Vec<Vec<usize>> = chords.iter().map(|chord| chord.iter().map(|note| note.pitch).collect()).collect();
+        let fingerings = assign_fingerings(&chord_pitches, &tuning_pitches);
+
+        let mut note_events = vec![];
+        let mut left_out_amount = 0;
+        for (chord, chord_fingerings) in chords.iter().zip(fingerings) {
+            for (note, fingering) in chord.iter().zip(chord_fingerings) {
+                // Pitches outside the range of the guitar are left out
+                let Some(fingering) = fingering else {
+                    left_out_amount += 1;
+                    continue
+                };
+
+                let start_time_seconds = self.ticks_to_seconds(note.start_tick);
+                note_events.push(NoteEvent {
+                    start_time_seconds: start_time_seconds as f32,
+                    duration_seconds: (self.ticks_to_seconds(note.end_tick) - start_time_seconds) as f32,
+                    string_index: fingering.string_index,
+                    fret_index: fingering.fret_index,
+                    ..Default::default()
+                });
+            }
+        }
+
+        let end_tick = chords.iter().flatten().map(|note| note.end_tick).max().unwrap_or(0);
+        let mut chart = Chart { notes: note_events, tempo_map: self.tempo_map(end_tick), ..Default::default() };
+        chart.sort_notes();
+
+        let mut issues = vec![];
+        if left_out_amount > 0 {
+            issues.push(format!("left out {} notes outside the range of the tuning", left_out_amount));
+        }
+
+        Ok(ImportedSong {
+            title: self.title.clone(),
+            artists: vec![],
+            tuning: Some(tuning.clone()),
+            chart,
+            issues,
+        })
+    }
+}
@@ -0,0 +1,637 @@
+use std::{collections::HashMap, error::Error, fmt::Write, fs::{self, File}, io::Read, path::Path};
+
+use roxmltree::{Document, Node, ParsingOptions};
+use zip::ZipArchive;
+
+use crate::{helpers::{chart::Chart, fingering::tuning_pitches, notes::{Bend, Legato, NoteEvent}, tempo_map::TempoChange, tuning::note_name_from_note_number}, resources::song_library::SongMetadata};
+
+use super::imported_song::{measures_to_tempo_map, quarters_to_seconds, ImportedSong};
+
+// Divisions of a quarter note in exported files, allows sixteenths and eighth note triplets
+const EXPORT_DIVISIONS: i64 = 12;
+const DEFAULT_TEMPO: f64 = 120.0;
+// Points to the score inside a compressed .mxl file
+const CONTAINER_FILENAME: &str = "META-INF/container.xml";
+
+// Pitch names with sharps as (step, alter)
+const PITCH_STEPS: [(&str, i32); 12] = [("C", 0), ("C", 1), ("D", 0), ("D", 1), ("E", 0), ("F", 0), ("F", 1), ("G", 0), ("G", 1), ("A", 0), ("A", 1), ("B", 0)];
+
+#[derive(Debug, Clone)]
+pub struct MusicXmlPart {
+    pub id: String,
+    pub name: String,
+    pub has_tablature: bool,
+}
+
+/// A note of a TAB staff, with its position in quarter notes
+#[derive(Debug, Clone, Default)]
+struct TabNote {
+    start_quarters: f64,
+    duration_quarters: f64,
+    string: usize, // 1 is the highest string
+    fret: usize,
+    legato_start: Option<Legato>, // The next note on the string is a hammer-on or pull-off
+    slide_start: bool, // Slides into the next note on the string
+    bend: Option<Bend>,
+    palm_mute: bool,
+    harmonic: bool,
+    vibrato: bool,
+    tremolo: bool,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|child| child.text()).map(|text| text.trim())
+}
+
+fn child_number<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    child_text(node, name).and_then(|text| text.parse().ok())
+}
+
+fn has_start(node: Node, name: &str) -> bool {
+    node.descendants().any(|descendant| descendant.has_tag_name(name) && descendant.attribute("type") == Some("start"))
+}
+
+/// A MusicXML score, from an uncompressed .musicxml file or a compressed .mxl file
+pub struct MusicXmlSong {
+    contents: String,
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub parts: Vec<MusicXmlPart>,
+}
+
+impl MusicXmlSong {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let is_compressed = path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extension.eq_ignore_ascii_case("mxl"));
+        let contents = if is_compressed { Self::read_compressed(path)? } else { fs::read_to_string(path)? };
+        let document = Self::parse(&contents)?;
+        let root = document.root_element();
+        if !root.has_tag_name("score-partwise") {
+            return Err("only partwise MusicXML scores are supported".into())
+        }
+
+        let title = child(root, "work").and_then(|work| child_text(work, "work-title"))
+            .or_else(|| child_text(root, "movement-title"))
+            .filter(|title| !title.is_empty())
+            .map(|title| title.to_string());
+        let artists = child(root, "identification")
+            .map(|identification| identification.children()
+                .filter(|creator| creator.has_tag_name("creator") && creator.attribute("type") != Some("lyricist"))
+                .filter_map(|creator| creator.text().map(|text| text.trim().to_string()))
+                .filter(|creator| !creator.is_empty())
+                .collect())
+            .unwrap_or_default();
+
+        let part_list = child(root, "part-list").ok_or("the score has no part list")?;
+        let parts = part_list.children().filter(|node| node.has_tag_name("score-part")).map(|score_part| {
+            let id = score_part.attribute("id").unwrap_or_default().to_string();
+            let has_tablature = root.children()
+                .find(|part| part.has_tag_name("part") && part.attribute("id") == Some(id.as_str()))
+                .map(|part| part.descendants().any(|node| node.has_tag_name("technical") && child(node, "fret").is_some()))
+                .unwrap_or(false);
+
+            MusicXmlPart {
+                name: child_text(score_part, "part-name").unwrap_or_default().to_string(),
+                id,
+                has_tablature,
+            }
+        }).collect();
+
+        Ok(Self { contents, title, artists, parts })
+    }
+
+    /// Reads the score out of a compressed file, the first root file of the container is the score
+    fn read_compressed(path: &Path) -> Result<String, Box<dyn Error>> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+
+        let mut container = String::new();
+        archive.by_name(CONTAINER_FILENAME).map_err(|_| "the compressed score has no container file")?.read_to_string(&mut container)?;
+        let score_path = Self::parse(&container)?.descendants()
+            .find(|node| node.has_tag_name("rootfile"))
+            .and_then(|rootfile| rootfile.attribute("full-path"))
+            .ok_or("the container file doesn't point to a score")?
+            .to_string();
+
+        let mut contents = String::new();
+        archive.by_name(&score_path).map_err(|_| format!("the compressed score has no {}", score_path))?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    fn parse(contents: &str) -> Result<Document<'_>, Box<dyn Error>> {
+        // Scores reference the MusicXML DTD in their doctype
+        Ok(Document::parse_with_options(contents, ParsingOptions { allow_dtd: true, ..Default::default() })?)
+    }
+
+    /// The first part that has tablature
+    pub fn default_part_index(&self) -> Option<usize> {
+        self.parts.iter().position(|part| part.has_tablature)
+    }
+
+    pub fn to_imported_song(&self, part_index: usize) -> Result<ImportedSong, Box<dyn Error>> {
+        let part_info = self.parts.get(part_index).ok_or(format!("part {} does not exist", part_index))?;
+        if !part_info.has_tablature {
+            return Err(format!("part \"{}\" has no tablature", part_info.name).into())
+        }
+
+        let document = Self::parse(&self.contents)?;
+        let part = document.root_element().children()
+            .find(|part| part.has_tag_name("part") && part.attribute("id") == Some(part_info.id.as_str()))
+            .ok_or(format!("part \"{}\" has no measures", part_info.name))?;
+
+        let mut divisions = 1.0;
+        let mut time_signature: (u32, u32) = (4, 4); // Beats and beat unit
+        let mut string_count = 6;
+        let mut capo = 0;
+        let mut tuning_lines: HashMap<usize, i32> = HashMap::new(); // Line 1 is the lowest string
+        let mut tempo_changes: Vec<(f64, f64)> = vec![(0.0, DEFAULT_TEMPO)];
+        let mut notes: Vec<TabNote> = vec![];
+        let mut measures: Vec<(f64, u32, u32)> = vec![];
+        let mut measure_start_quarters = 0.0;
+
+        for measure in part.children().filter(|node| node.has_tag_name("measure")) {
+            let mut cursor = 0.0; // In divisions, from the start of the measure
+            let mut measure_length: f64 = 0.0;
+            let mut previous_start = 0.0;
+
+            for element in measure.children().filter(|node| node.is_element()) {
+                match element.tag_name().name() {
+                    "attributes" => {
+                        if let Some(value) = child_number::<f64>(element, "divisions") {
+                            divisions = value.max(1.0);
+                        }
+                        if let Some(time) = child(element, "time") {
+                            if let (Some(beats), Some(beat_type)) = (child_text(time, "beats"), child_number::<u32>(time, "beat-type")) {
+                                // Compound signatures like 3+2 add up
+                                let beats: u32 = beats.split('+').filter_map(|beat| beat.trim().parse::<u32>().ok()).sum();
+                                time_signature = (beats.max(1), beat_type.max(1));
+                            }
+                        }
+                        if let Some(staff_details) = child(element, "staff-details") {
+                            string_count = child_number(staff_details, "staff-lines").unwrap_or(string_count);
+                            capo = child_number(staff_details, "capo").unwrap_or(capo);
+                            for staff_tuning in staff_details.children().filter(|node| node.has_tag_name("staff-tuning")) {
+                                let line = staff_tuning.attribute("line").and_then(|line| line.parse().ok());
+                                let step = child_text(staff_tuning, "tuning-step").and_then(|step| PITCH_STEPS.iter().position(|(name, alter)| *name == step && *alter == 0));
+                                let alter = child_number::<f64>(staff_tuning, "tuning-alter").unwrap_or(0.0) as i32;
+                                let octave = child_number::<i32>(staff_tuning, "tuning-octave");
+                                if let (Some(line), Some(step), Some(octave)) = (line, step, octave) {
+                                    tuning_lines.insert(line, (octave + 1) * 12 + step as i32 + alter);
+                                }
+                            }
+                        }
+                    }
+                    "direction" | "sound" => {
+                        let sound = if element.has_tag_name("sound") { Some(element) } else { child(element, "sound") };
+                        if let Some(tempo) = sound.and_then(|sound| sound.attribute("tempo")).and_then(|tempo| tempo.parse::<f64>().ok()) {
+                            if tempo > 0.0 {
+                                tempo_changes.push((measure_start_quarters + cursor / divisions, tempo));
+                            }
+                        }
+                    }
+                    "backup" => cursor -= child_number::<f64>(element, "duration").unwrap_or(0.0),
+                    "forward" => {
+                        cursor += child_number::<f64>(element, "duration").unwrap_or(0.0);
+                        measure_length = measure_length.max(cursor);
+                    }
+                    "note" => {
+                        if child(element, "grace").is_some() {
+                            continue
+                        }
+
+                        let duration = child_number::<f64>(element, "duration").unwrap_or(0.0);
+                        let start = if child(element, "chord").is_some() { previous_start } else {
+                            previous_start = cursor;
+                            cursor += duration;
+                            measure_length = measure_length.max(cursor);
+                            previous_start
+                        };
+
+                        if let Some(note) = Self::read_tab_note(element, measure_start_quarters + start / divisions, duration / divisions) {
+                            let note = TabNote { fret: note.fret + capo, ..note };
+
+                            // Scores with a standard and a TAB staff have every note twice
+                            if notes.iter().rev().take(string_count * 2).any(|other| other.start_quarters == note.start_quarters && other.string == note.string) {
+                                continue
+                            }
+
+                            // Tied notes lengthen the previous note on the same string
+                            let is_tie_stop = element.children().any(|tie| tie.has_tag_name("tie") && tie.attribute("type") == Some("stop"));
+                            if is_tie_stop {
+                                if let Some(previous) = notes.iter_mut().rev().find(|previous| previous.string == note.string) {
+                                    previous.duration_quarters = note.start_quarters + note.duration_quarters - previous.start_quarters;
+                                    continue
+                                }
+                            }
+
+                            notes.push(note);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // Pickup measures are shorter than their time signature
+            if measure.attribute("implicit") != Some("yes") {
+                measure_length = measure_length.max(time_signature.0 as f64 * 4.0 / time_signature.1 as f64 * divisions);
+            }
+            measures.push((measure_start_quarters, time_signature.0, time_signature.1));
+            measure_start_quarters += measure_length / divisions;
+        }
+
+        tempo_changes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut note_events: Vec<NoteEvent> = notes.iter().map(|note| {
+            let start_time_seconds = quarters_to_seconds(&tempo_changes, note.start_quarters);
+            NoteEvent {
+                start_time_seconds: start_time_seconds as f32,
+                duration_seconds: (quarters_to_seconds(&tempo_changes, note.start_quarters + note.duration_quarters) - start_time_seconds) as f32,
+                string_index: string_count.saturating_sub(note.string),
+                fret_index: note.fret,
+                bend: note.bend.clone(),
+                palm_mute: note.palm_mute,
+                harmonic: note.harmonic,
+                vibrato: note.vibrato,
+                tremolo: note.tremolo,
+                ..Default::default()
+            }
+        }).collect();
+
+        // Hammer-ons, pull-offs and slides start on the note before the one they lead to
+        let mut next_on_string: HashMap<usize, usize> = HashMap::new();
+        for index in (0..notes.len()).rev() {
+            if let Some(&next_index) = next_on_string.get(&notes[index].string) {
+                if notes[index].legato_start.is_some() {
+                    note_events[next_index].legato = notes[index].legato_start;
+                }
+                if notes[index].slide_start {
+                    note_events[index].slide_to_fret = Some(note_events[next_index].fret_index);
+                }
+            }
+            next_on_string.insert(notes[index].string, index);
+        }
+
+        let mut chart = Chart { notes: note_events, tempo_map: measures_to_tempo_map(&measures, &tempo_changes), ..Default::default() };
+        chart.sort_notes();
+
+        let tuning = if string_count == 6 && (1..=6).all(|line| tuning_lines.contains_key(&line)) {
+            Some(std::array::from_fn(|index| note_name_from_note_number(tuning_lines[&(index + 1)])))
+        } else { None };
+
+        let mut imported_song = ImportedSong {
+            title: self.title.clone(),
+            artists: self.artists.clone(),
+            tuning,
+            chart,
+            issues: vec![],
+        };
+        // The capo can push the highest notes past the last fret
+        imported_song.leave_out_notes_off_fretboard();
+
+        Ok(imported_song)
+    }
+
+    /// Reads the string, fret and techniques of a note, None for rests and notes without tablature
+    fn read_tab_note(element: Node, start_quarters: f64, duration_quarters: f64) -> Option<TabNote> {
+        let notations = child(element, "notations");
+        let technical = notations.and_then(|notations| child(notations, "technical"))?;
+        let ornaments = notations.and_then(|notations| child(notations, "ornaments"));
+
+        let mut note = TabNote {
+            start_quarters,
+            duration_quarters,
+            string: child_number(technical, "string")?,
+            fret: child_number(technical, "fret")?,
+            harmonic: child(technical, "harmonic").is_some(),
+            ..Default::default()
+        };
+
+        if has_start(technical, "hammer-on") {
+            note.legato_start = Some(Legato::HammerOn);
+        } else if has_start(technical, "pull-off") {
+            note.legato_start = Some(Legato::PullOff);
+        }
+        note.slide_start = notations.map(|notations| has_start(notations, "slide") || has_start(notations, "glissando")).unwrap_or(false);
+
+        if let Some(bend) = child(technical, "bend") {
+            let semitones = child_number::<f32>(bend, "bend-alter").unwrap_or(0.0);
+            if semitones > 0.0 {
+                note.bend = Some(Bend { semitones, curve: vec![] });
+            }
+        }
+
+        if let Some(ornaments) = ornaments {
+            note.vibrato = child(ornaments, "wavy-line").is_some() || child(ornaments, "vibrato").is_some();
+            note.tremolo = child(ornaments, "tremolo").is_some();
+        }
+
+        let palm_mute_play = child(element, "play").and_then(|play| child_text(play, "mute")) == Some("palm");
+        let palm_mute_text = technical.children()
+            .any(|other| other.has_tag_name("other-technical") && other.text().map(|text| text.to_lowercase().contains("palm") || text.contains("P.M.")).unwrap_or(false));
+        note.palm_mute = palm_mute_play || palm_mute_text;
+
+        Some(note)
+    }
+}
+
+fn pitch_xml(midi_pitch: usize) -> String {
+    let (step, alter) = PITCH_STEPS[midi_pitch % 12];
+    let octave = midi_pitch as i32 / 12 - 1;
+    if alter != 0 {
+        format!("<pitch><step>{}</step><alter>{}</alter><octave>{}</octave></pitch>", step, alter, octave)
+    } else {
+        format!("<pitch><step>{}</step><octave>{}</octave></pitch>", step, octave)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn quarter_bpm(change: &TempoChange) -> f64 {
+    change.bpm as f64 * 4.0 / change.beat_unit.max(1) as f64
+}
+
+fn measure_divisions(change: &TempoChange) -> i64 {
+    (change.beats_per_measure.max(1) as i64 * 4 * EXPORT_DIVISIONS / change.beat_unit.max(1) as i64).max(1)
+}
+
+/// Position of a time in divisions of a quarter note, the first tempo change also applies before its start
+fn seconds_to_divisions(tempo_changes: &[TempoChange], seconds: f32) -> i64 {
+    let mut quarters = 0.0;
+    for (index, change) in tempo_changes.iter().enumerate() {
+        let from = if index == 0 { 0.0 } else { change.start_seconds as f64 };
+        let to = tempo_changes.get(index + 1).map(|next| next.start_seconds as f64).unwrap_or(f64::MAX).min(seconds as f64);
+        if to > from {
+            quarters += (to - from) * quarter_bpm(change) / 60.0;
+        }
+    }
+    (quarters * EXPORT_DIVISIONS as f64).round() as i64
+}
+
+/// Measures of the score as (start, length, tempo change), up to the first barline at or past a position in divisions
+fn export_measures(tempo_changes: &[TempoChange], end_divisions: i64) -> Vec<(i64, i64, usize)> {
+    let mut barlines: Vec<(i64, usize)> = vec![(0, 0)];
+    'changes: for (index, change) in tempo_changes.iter().enumerate() {
+        let change_start = seconds_to_divisions(tempo_changes, change.start_seconds);
+        let change_end = tempo_changes.get(index + 1).map(|next| seconds_to_divisions(tempo_changes, next.start_seconds)).unwrap_or(i64::MAX);
+        let measure_length = measure_divisions(change);
+
+        // Measures of the first change continue back to the start of the song, leaving a pickup measure
+        let mut barline = if index == 0 { change_start.rem_euclid(measure_length) } else { change_start };
+        while barline < change_end {
+            barlines.push((barline, index));
+            if barline >= end_divisions {
+                break 'changes
+            }
+            barline += measure_length;
+        }
+    }
+
+    // A change that starts on a barline replaces the change before it
+    barlines.sort_by_key(|barline| barline.0);
+    barlines.dedup_by(|later, earlier| {
+        let is_same = later.0 == earlier.0;
+        if is_same {
+            earlier.1 = later.1;
+        }
+        is_same
+    });
+
+    barlines.windows(2).map(|pair| (pair[0].0, pair[1].0 - pair[0].0, pair[0].1)).collect()
+}
+
+/// Written name of a note value, "quarter" for 4
+fn note_type_name(beat_unit: u32) -> &'static str {
+    match beat_unit {
+        1 => "whole",
+        2 => "half",
+        8 => "eighth",
+        16 => "16th",
+        32 => "32nd",
+        _ => "quarter",
+    }
+}
+
+/// Writes a chart as a MusicXML score with a TAB staff
+/// Notes are placed on the measures of the tempo map, charts without one get 4/4 at the given BPM, and chords last until the next note
+pub fn export_music_xml(chart: &Chart, song_metadata: &SongMetadata, default_bpm: f64) -> Result<String, Box<dyn Error>> {
+    let tuning = tuning_pitches(&song_metadata.tuning).ok_or("the tuning of the song is not valid")?;
+    let tempo_changes = if chart.tempo_map.is_empty() {
+        vec![TempoChange { bpm: default_bpm as f32, ..Default::default() }]
+    } else {
+        chart.tempo_map.changes.clone()
+    };
+    let to_divisions = |seconds: f32| seconds_to_divisions(&tempo_changes, seconds);
+
+    let mut notes = chart.notes.clone();
+    notes.sort_by(|a, b| a.start_time_seconds.partial_cmp(&b.start_time_seconds).unwrap());
+
+    // Notes that start on the same grid position are written as a chord
+    let mut chords: Vec<(i64, Vec<usize>)> = vec![];
+    for (index, note) in notes.iter().enumerate() {
+        let start = to_divisions(note.start_time_seconds);
+        match chords.last_mut() {
+            Some((chord_start, chord)) if *chord_start == start && chord.iter().all(|&other| notes[other].string_index != note.string_index) => chord.push(index),
+            _ => chords.push((start, vec![index])),
+        }
+    }
+
+    // Techniques that connect a note to the next note on the same string
+    let mut legato_starts: HashMap<usize, Legato> = HashMap::new();
+    let mut slide_targets: HashMap<usize, usize> = HashMap::new(); // Target note to the note it slides from
+    let mut previous_on_string: HashMap<usize, usize> = HashMap::new();
+    for (index, note) in notes.iter().enumerate() {
+        if let Some(&previous) = previous_on_string.get(&note.string_index) {
+            if let Some(legato) = &note.legato {
+                legato_starts.insert(previous, *legato);
+            }
+            if notes[previous].slide_to_fret == Some(note.fret_index) {
+                slide_targets.insert(index, previous);
+            }
+        }
+        previous_on_string.insert(note.string_index, index);
+    }
+    let slide_sources: Vec<usize> = slide_targets.values().copied().collect();
+
+    // Every event is written in pieces that don't cross a barline, tied together
+    let mut events: Vec<(i64, i64, Option<&Vec<usize>>)> = vec![]; // Start, length and notes, rests have no notes
+    let mut position = 0;
+    for (chord_index, (start, chord)) in chords.iter().enumerate() {
+        let start = (*start).max(position);
+        if start > position {
+            events.push((position, start - position, None));
+        }
+
+        let longest = chord.iter().map(|&index| to_divisions(notes[index].start_time_seconds + notes[index].duration_seconds) - start).max().unwrap_or(1).max(1);
+        let until_next = chords.get(chord_index + 1).map(|(next_start, _)| next_start - start).unwrap_or(longest);
+        let length = longest.min(until_next).max(1);
+        events.push((start, length, Some(chord)));
+        position = start + length;
+    }
+    // Fill the last measure
+    let measures = export_measures(&tempo_changes, position.max(1));
+    let end = measures.last().map(|measure| measure.0 + measure.1).unwrap_or(position);
+    if end > position {
+        events.push((position, end - position, None));
+    }
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#)?;
+    writeln!(xml, r#"<!DOCTYPE score-partwise PUBLIC "-//Recordare//DTD MusicXML 4.0 Partwise//EN" "http://www.musicxml.org/dtds/partwise.dtd">"#)?;
+    writeln!(xml, r#"<score-partwise version="4.0">"#)?;
+    writeln!(xml, "  <work><work-title>{}</work-title></work>", escape_xml(&song_metadata.title))?;
+    writeln!(xml, "  <identification>")?;
+    for artist in &song_metadata.artists {
+        writeln!(xml, r#"    <creator type="composer">{}</creator>"#, escape_xml(artist))?;
+    }
+    writeln!(xml, "  </identification>")?;
+    writeln!(xml, r#"  <part-list><score-part id="P1"><part-name>Guitar</part-name></score-part></part-list>"#)?;
+    writeln!(xml, r#"  <part id="P1">"#)?;
+
+    let mut measure_number = 0;
+    for (start, length, chord) in events {
+        let mut piece_start = start;
+        while piece_start < start + length {
+            // Open a new measure at every barline
+            if let Some(&(measure_start, measure_length, change_index)) = measures.get(measure_number).filter(|measure| measure.0 == piece_start) {
+                if measure_number > 0 {
+                    writeln!(xml, "    </measure>")?;
+                }
+                let change = &tempo_changes[change_index];
+                let previous_change = measure_number.checked_sub(1).map(|previous| &tempo_changes[measures[previous].2]);
+                measure_number += 1;
+
+                // A first measure shorter than its time signature is a pickup
+                let is_pickup = measure_start == 0 && measure_length < measure_divisions(change);
+                writeln!(xml, r#"    <measure number="{}"{}>"#, measure_number, if is_pickup { r#" implicit="yes""# } else { "" })?;
+
+                let time = format!("<time><beats>{}</beats><beat-type>{}</beat-type></time>", change.beats_per_measure, change.beat_unit);
+                if measure_number == 1 {
+                    writeln!(xml, "      <attributes>")?;
+                    writeln!(xml, "        <divisions>{}</divisions>", EXPORT_DIVISIONS)?;
+                    writeln!(xml, "        <key><fifths>0</fifths></key>")?;
+                    writeln!(xml, "        {}", time)?;
+                    writeln!(xml, "        <clef><sign>TAB</sign><line>5</line></clef>")?;
+                    writeln!(xml, "        <staff-details>")?;
+                    writeln!(xml, "          <staff-lines>{}</staff-lines>", tuning.len())?;
+                    for (string_index, &open_string_pitch) in tuning.iter().enumerate() {
+                        let (step, alter) = PITCH_STEPS[open_string_pitch % 12];
+                        writeln!(
+                            xml,
+                            r#"          <staff-tuning line="{}"><tuning-step>{}</tuning-step>{}<tuning-octave>{}</tuning-octave></staff-tuning>"#,
+                            string_index + 1,
+                            step,
+                            if alter != 0 { format!("<tuning-alter>{}</tuning-alter>", alter) } else { String::new() },
+                            open_string_pitch / 12 - 1
+                        )?;
+                    }
+                    writeln!(xml, "        </staff-details>")?;
+                    writeln!(xml, "      </attributes>")?;
+                } else if previous_change.is_some_and(|previous| (previous.beats_per_measure, previous.beat_unit) != (change.beats_per_measure, change.beat_unit)) {
+                    writeln!(xml, "      <attributes>{}</attributes>", time)?;
+                }
+                if previous_change.map(|previous| previous.bpm != change.bpm || previous.beat_unit != change.beat_unit).unwrap_or(true) {
+                    writeln!(
+                        xml,
+                        r#"      <direction placement="above"><direction-type><metronome><beat-unit>{}</beat-unit><per-minute>{}</per-minute></metronome></direction-type><sound tempo="{}"/></direction>"#,
+                        note_type_name(change.beat_unit),
+                        change.bpm,
+                        quarter_bpm(change)
+                    )?;
+                }
+            }
+
+            let (measure_start, measure_length, _) = measures[measure_number - 1];
+            let piece_end = (start + length).min(measure_start + measure_length);
+            let piece_length = piece_end - piece_start;
+            let is_first_piece = piece_start == start;
+            let is_last_piece = piece_end == start + length;
+
+            match chord {
+                None => writeln!(xml, "      <note><rest/><duration>{}</duration></note>", piece_length)?,
+                Some(chord) => {
+                    for (chord_position, &index) in chord.iter().enumerate() {
+                        let note = &notes[index];
+                        let midi_pitch = note.midi_pitch(&song_metadata.tuning).unwrap_or(tuning[note.string_index] + note.fret_index);
+
+                        let mut ties = String::new();
+                        let mut tied = String::new();
+                        if !is_first_piece {
+                            ties.push_str(r#"<tie type="stop"/>"#);
+                            tied.push_str(r#"<tied type="stop"/>"#);
+                        }
+                        if !is_last_piece {
+                            ties.push_str(r#"<tie type="start"/>"#);
+                            tied.push_str(r#"<tied type="start"/>"#);
+                        }
+
+                        // Techniques are written on the first piece of a note
+                        let mut technical = format!("<string>{}</string><fret>{}</fret>", tuning.len() - note.string_index, note.fret_index);
+                        let mut ornaments = String::new();
+                        let mut slides = String::new();
+                        if is_first_piece {
+                            match &note.legato {
+                                Some(Legato::HammerOn) => technical.push_str(r#"<hammer-on type="stop"/>"#),
+                                Some(Legato::PullOff) => technical.push_str(r#"<pull-off type="stop"/>"#),
+                                None => {}
+                            }
+                            match legato_starts.get(&index) {
+                                Some(Legato::HammerOn) => technical.push_str(r#"<hammer-on type="start">H</hammer-on>"#),
+                                Some(Legato::PullOff) => technical.push_str(r#"<pull-off type="start">P</pull-off>"#),
+                                None => {}
+                            }
+                            if let Some(bend) = &note.bend {
+                                write!(technical, "<bend><bend-alter>{}</bend-alter></bend>", bend.semitones)?;
+                            }
+                            if note.harmonic {
+                                technical.push_str("<harmonic/>");
+                            }
+                            if note.palm_mute {
+                                technical.push_str("<other-technical>P.M.</other-technical>");
+                            }
+                            if note.vibrato {
+                                ornaments.push_str(r#"<wavy-line type="start"/><wavy-line type="stop"/>"#);
+                            }
+                            if note.tremolo {
+                                ornaments.push_str(r#"<tremolo type="single">3</tremolo>"#);
+                            }
+                            if slide_targets.contains_key(&index) {
+                                slides.push_str(r#"<slide type="stop" line-type="solid"/>"#);
+                            }
+                            if slide_sources.contains(&index) {
+                                slides.push_str(r#"<slide type="start" line-type="solid"/>"#);
+                            }
+                        }
+
+                        writeln!(
+                            xml,
+                            "      <note>{}{}<duration>{}</duration>{}<voice>1</voice><notations>{}{}{}<technical>{}</technical></notations>{}</note>",
+                            if chord_position > 0 { "<chord/>" } else { "" },
+                            pitch_xml(midi_pitch),
+                            piece_length,
+                            ties,
+                            tied,
+                            slides,
+                            if ornaments.is_empty() { String::new() } else { format!("<ornaments>{}</ornaments>", ornaments) },
+                            technical,
+                            if note.palm_mute && is_first_piece { "<play><mute>palm</mute></play>" } else { "" },
+                        )?;
+                    }
+                }
+            }
+
+            piece_start = piece_end;
+        }
+    }
+
+    if measure_number > 0 {
+        writeln!(xml, "    </measure>")?;
+    }
+    writeln!(xml, "  </part>")?;
+    writeln!(xml, "</score-partwise>")?;
+
+    Ok(xml)
+}
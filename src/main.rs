@@ -3,8 +3,8 @@ use bevy_mod_billboard::plugin::BillboardPlugin;
 use bevy_tween::prelude::*;
 use features::timeline::components::note::NoteTriggeredEvent;
 use helpers::persistence::get_data_dir;
-use resources::{configuration::ConfigurationResource, input_device::InputDeviceResource, input_devices::InputDevicesResource, song_library::SongLibraryResource, song_loaded::SongLoadedResource};
-use screens::{input_device_detail::plugin::InputDeviceDetailPlugin, input_device_overview::plugin::InputDeviceOverviewPlugin, song_play::plugin::SongPlayPlugin, song_select::plugin::SongSelectPlugin, tune::plugin::TunePlugin};
+use resources::{configuration::ConfigurationResource, input_device::InputDeviceResource, input_devices::InputDevicesResource, play_history::PlayHistoryResource, song_library::SongLibraryResource, song_library_scan::{song_library_scan_start, song_library_scan_update, SongLibraryScanResource}, song_loaded::SongLoadedResource};
+use screens::{editor::plugin::EditorPlugin, input_device_detail::plugin::InputDeviceDetailPlugin, input_device_overview::plugin::InputDeviceOverviewPlugin, song_play::plugin::SongPlayPlugin, song_select::{plugin::SongSelectPlugin, song_list::song_list_view_update}, tune::plugin::TunePlugin};
 use states::app_state::AppState;
 
 mod constants {
@@ -15,8 +15,13 @@ mod resources {
     pub mod configuration;
     pub mod input_device;
     pub mod song_library;
+    pub mod song_library_scan;
     pub mod song_loaded;
     pub mod output_audio_song;
+    pub mod hand_position;
+    pub mod guitar_stem_fade;
+    pub mod play_history;
+    pub mod song_editor;
 }
 mod components {
     pub mod button_primary;
@@ -27,6 +32,9 @@ mod features {
         pub mod timeline;
         pub mod components {
             pub mod note;
+            pub mod pitch_track;
+            pub mod sustain;
+            pub mod beat_line;
         }
     }
 }
@@ -48,12 +56,26 @@ mod screens {
         pub mod plugin;
         pub mod song_select;
         pub mod song_list;
+        pub mod library_report;
+        pub mod song_filter;
+        pub mod song_details;
+        pub mod song_preview;
+        pub mod song_import;
     }
     pub mod song_play {
         pub mod plugin;
         pub mod song_play;
         pub mod camera;
     }
+    pub mod editor {
+        pub mod plugin;
+        pub mod editor;
+        pub mod input;
+        pub mod grid;
+        pub mod record;
+        pub mod tempo;
+        pub mod chart;
+    }
 }
 mod states {
     pub mod app_state;
@@ -63,9 +85,42 @@ mod helpers {
     pub mod tuning;
     pub mod persistence;
     pub mod notes;
+    pub mod chart;
+    pub mod pitch_tracking;
+    pub mod fingering;
+    pub mod recording;
+    pub mod difficulty;
+    pub mod dynamic_difficulty;
+    pub mod song_validation;
+    pub mod tempo_map;
+    pub mod audio_info;
+    pub mod song_audio;
+    pub mod stems;
+}
+mod importers {
+    pub mod imported_song;
+    pub mod guitar_pro;
+    pub mod midi;
+    pub mod music_xml;
+    pub mod ascii_tab;
+}
+mod transcription {
+    pub mod beat_tracking;
+    pub mod multi_pitch;
+    pub mod transcription;
+}
+mod package {
+    pub mod package;
+}
+mod cli {
+    pub mod cli;
 }
 
 fn main() {
+    // Command line subcommands run without starting the game
+    if let Some(exit_code) = cli::cli::run_cli() {
+        std::process::exit(exit_code);
+    }
 
     let mut app = App::new();
     app.add_plugins(DefaultPlugins.set(WindowPlugin {
@@ -93,13 +148,21 @@ fn main() {
     app.insert_resource(InputDevicesResource::default());
     app.insert_resource(InputDeviceResource::default());
     app.insert_resource(SongLibraryResource::load_from_disk());
+    app.insert_resource(SongLibraryScanResource::default());
     app.insert_resource(SongLoadedResource::default());
+    app.insert_resource(PlayHistoryResource::load_from_disk());
+
+    // The library from the previous session is shown right away, and updated once the songs directory is scanned
+    app.add_systems(Startup, song_library_scan_start);
+    // Before the song list, so its rows are rebuilt in the same frame the library changes and never point at the wrong song
+    app.add_systems(Update, song_library_scan_update.before(song_list_view_update));
 
     app.add_plugins(InputDeviceOverviewPlugin);
     app.add_plugins(InputDeviceDetailPlugin);
     app.add_plugins(TunePlugin);
     app.add_plugins(SongSelectPlugin);
     app.add_plugins(SongPlayPlugin);
+    app.add_plugins(EditorPlugin);
 
     let directory = get_data_dir().unwrap();
     info!("data directory: {:?}", directory);
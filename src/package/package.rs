@@ -0,0 +1,211 @@
+use std::{error::Error, fs::{self, File}, io::{Read, Write}, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{helpers::{chart::{Chart, CHART_FILENAME}, persistence::get_songs_dir, song_audio::AUDIO_EXTENSIONS}, resources::song_library::SongMetadata};
+
+pub const PACKAGE_EXTENSION: &str = "songpkg";
+const MANIFEST_FILENAME: &str = "manifest.json";
+const FORMAT_VERSION: u32 = 1;
+// Files a package may hold, next to the metadata and the charts
+const PACKAGE_FILE_EXTENSIONS: [&str; 8] = ["json", "mp3", "ogg", "flac", "wav", "opus", "png", "jpg"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageFile {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// First file of a package, describing the song and every other file in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageManifest {
+    pub format_version: u32,
+    pub metadata: SongMetadata,
+    pub files: Vec<PackageFile>,
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Whether a file of a song directory belongs in a package, only plain files with a known extension do
+fn is_package_file(name: &str) -> bool {
+    let path = Path::new(name);
+    let is_plain_name = path.file_name().and_then(|file_name| file_name.to_str()) == Some(name) && !name.starts_with('.');
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+
+    is_plain_name && name != MANIFEST_FILENAME && PACKAGE_FILE_EXTENSIONS.contains(&extension.as_str())
+}
+
+/// Writes a song of the library into a single package file
+/// Besides the metadata, charts and audio this picks up album art like cover.png and a preview clip like preview.mp3
+pub fn export_package(uuid: &str, output_path: &Path) -> Result<PackageManifest, Box<dyn Error>> {
+    let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(uuid);
+    let metadata = SongMetadata::load_from_file(&song_directory.join("metadata.json"))?;
+
+    let mut names: Vec<String> = fs::read_dir(&song_directory)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().to_str().map(|name| name.to_string()))
+        .filter(|name| is_package_file(name))
+        .collect();
+    names.sort();
+
+    let mut files = vec![];
+    let mut contents = vec![];
+    for name in names {
+        let bytes = fs::read(song_directory.join(&name))?;
+        files.push(PackageFile { name: name.clone(), size: bytes.len() as u64, sha256: sha256_hex(&bytes) });
+        contents.push((name, bytes));
+    }
+    let manifest = PackageManifest { format_version: FORMAT_VERSION, metadata, files };
+
+    let mut writer = ZipWriter::new(File::create(output_path)?);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file(MANIFEST_FILENAME, options)?;
+    writer.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    for (name, bytes) in contents {
+        // Audio and images are compressed already
+        let compression_method = if name.ends_with(".json") { CompressionMethod::Deflated } else { CompressionMethod::Stored };
+        writer.start_file(name, options.compression_method(compression_method))?;
+        writer.write_all(&bytes)?;
+    }
+    writer.finish()?;
+
+    Ok(manifest)
+}
+
+/// A package that was read and checked, ready to be unpacked
+pub struct SongPackage {
+    pub manifest: PackageManifest,
+    contents: Vec<(String, Vec<u8>)>,
+}
+
+impl SongPackage {
+    /// Reads a package, checking its manifest against the files it holds
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+
+        let manifest: PackageManifest = {
+            let mut manifest_file = archive.by_name(MANIFEST_FILENAME).map_err(|_| "the package has no manifest")?;
+            let mut bytes = vec![];
+            manifest_file.read_to_end(&mut bytes)?;
+            serde_json::from_slice(&bytes)?
+        };
+        if manifest.format_version > FORMAT_VERSION {
+            return Err(format!("the package has format version {}, this version of the game reads up to {}", manifest.format_version, FORMAT_VERSION).into())
+        }
+
+        let mut contents = vec![];
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            let name = file.name().to_string();
+            if name == MANIFEST_FILENAME || file.is_dir() {
+                continue
+            }
+            // Files are unpacked next to each other, so anything with a path could escape the song directory
+            if !is_package_file(&name) {
+                return Err(format!("the package holds \"{}\", which is not a song file", name).into())
+            }
+            let Some(package_file) = manifest.files.iter().find(|package_file| package_file.name == name) else {
+                return Err(format!("\"{}\" is not listed in the manifest", name).into())
+            };
+
+            let mut bytes = vec![];
+            file.read_to_end(&mut bytes)?;
+            if bytes.len() as u64 != package_file.size || sha256_hex(&bytes) != package_file.sha256 {
+                return Err(format!("\"{}\" does not match its checksum, the package is damaged", name).into())
+            }
+            contents.push((name, bytes));
+        }
+
+        for package_file in &manifest.files {
+            if !contents.iter().any(|(name, _)| name == &package_file.name) {
+                return Err(format!("\"{}\" is listed in the manifest but missing from the package", package_file.name).into())
+            }
+        }
+
+        let package = Self { manifest, contents };
+        package.validate()?;
+
+        Ok(package)
+    }
+
+    fn file(&self, name: &str) -> Option<&[u8]> {
+        self.contents.iter().find(|(file_name, _)| file_name == name).map(|(_, bytes)| bytes.as_slice())
+    }
+
+    /// Checks that the package holds a playable song
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let metadata_bytes = self.file("metadata.json").ok_or("the package has no metadata.json")?;
+        let metadata: SongMetadata = serde_json::from_slice(metadata_bytes)?;
+        if metadata.uuid != self.manifest.metadata.uuid {
+            return Err("the metadata does not match the manifest".into())
+        }
+        if Uuid::parse_str(&metadata.uuid).is_err() {
+            return Err(format!("\"{}\" is not a valid song id", metadata.uuid).into())
+        }
+        let has_audio = match &metadata.audio_filename {
+            Some(audio_filename) => self.file(audio_filename).is_some(),
+            None => AUDIO_EXTENSIONS.iter().any(|extension| self.file(&format!("audio.{}", extension)).is_some()),
+        };
+        if !has_audio {
+            return Err("the package has no audio file".into())
+        }
+
+        for stem in &metadata.stems {
+            if self.file(&stem.filename).is_none() {
+                return Err(format!("the {} stem {} listed in the metadata is missing", stem.kind.name(), stem.filename).into())
+            }
+        }
+
+        let mut chart_names = vec![CHART_FILENAME.to_string()];
+        chart_names.extend(metadata.charts.iter().map(|song_chart| song_chart.filename()));
+        for chart_name in chart_names {
+            match self.file(&chart_name) {
+                Some(bytes) => {
+                    serde_json::from_slice::<Chart>(bytes).map_err(|err| format!("{} is not a valid chart: {}", chart_name, err))?;
+                }
+                None if chart_name == CHART_FILENAME => {}
+                None => return Err(format!("the chart {} listed in the metadata is missing", chart_name).into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn song_directory(&self) -> Option<PathBuf> {
+        get_songs_dir().map(|songs_dir| songs_dir.join(&self.manifest.metadata.uuid))
+    }
+
+    /// Whether the song of the package is already in the library
+    pub fn exists(&self) -> bool {
+        self.song_directory().is_some_and(|song_directory| song_directory.exists())
+    }
+
+    /// Unpacks the package into the library, replacing the song if it's already there
+    /// The files are written next to the library first, so a failed unpack never leaves half a song behind
+    pub fn unpack(&self) -> Result<SongMetadata, Box<dyn Error>> {
+        let song_directory = self.song_directory().ok_or("songs directory is not available")?;
+        let partial_directory = song_directory.with_file_name(format!(".{}.partial", self.manifest.metadata.uuid));
+        if partial_directory.exists() {
+            fs::remove_dir_all(&partial_directory)?;
+        }
+
+        fs::create_dir_all(&partial_directory)?;
+        for (name, bytes) in &self.contents {
+            fs::write(partial_directory.join(name), bytes)?;
+        }
+
+        if song_directory.exists() {
+            fs::remove_dir_all(&song_directory)?;
+        }
+        fs::rename(&partial_directory, &song_directory)?;
+
+        SongMetadata::load_from_file(&song_directory.join("metadata.json"))
+    }
+}
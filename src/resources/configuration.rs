@@ -4,21 +4,36 @@ use bevy::prelude::*;
 use cpal::{traits::{DeviceTrait, HostTrait}, Device};
 use serde::{Deserialize, Serialize};
 
-use crate::helpers::persistence::get_data_dir;
+use crate::{constants::ingame::{FRET_AMOUNT, FRET_CENTERS, STRING_CENTERS}, helpers::{persistence::get_data_dir, stems::GuitarStemMode}};
 
 const FILENAME: &str = "configuration.json";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ConfigurationResourceSerializable {
     pub device_name: Option<String>,
-    pub selected_device_channels: Vec<u16>
+    pub selected_device_channels: Vec<u16>,
+    #[serde(default)]
+    pub left_handed: bool,
+    #[serde(default)]
+    pub invert_strings: bool,
+    #[serde(default)]
+    pub dynamic_difficulty: bool,
+    #[serde(default)]
+    pub watch_songs_dir: bool,
+    #[serde(default)]
+    pub guitar_stem: GuitarStemMode,
 }
 
 #[derive(Resource)]
 pub struct ConfigurationResource {
     pub device: Option<Device>,
     pub selected_device_channels: Vec<u16>,
-    pub approach_rate: f32 // Meters/units per second
+    pub approach_rate: f32, // Meters/units per second
+    pub left_handed: bool, // Mirrors the fret direction of the highway
+    pub invert_strings: bool, // Puts the lowest string at the top of the highway
+    pub dynamic_difficulty: bool, // Adapts the note density of every phrase to the hit rate of the player
+    pub watch_songs_dir: bool, // Picks up songs that are added to or removed from the songs directory while playing
+    pub guitar_stem: GuitarStemMode, // Whether the guitar of songs with stems is heard
 }
 
 impl Default for ConfigurationResource {
@@ -28,6 +43,11 @@ impl Default for ConfigurationResource {
             device: None,
             selected_device_channels: vec![],
             approach_rate: 11.0,
+            left_handed: false,
+            invert_strings: false,
+            dynamic_difficulty: false,
+            watch_songs_dir: false,
+            guitar_stem: GuitarStemMode::Normal,
         }
     }
 }
@@ -39,7 +59,12 @@ impl ConfigurationResource {
                 let device_clone = self.device.clone().unwrap();
                 Some(device_clone.name().unwrap())
             } else { None },
-            selected_device_channels: self.selected_device_channels.clone()
+            selected_device_channels: self.selected_device_channels.clone(),
+            left_handed: self.left_handed,
+            invert_strings: self.invert_strings,
+            dynamic_difficulty: self.dynamic_difficulty,
+            watch_songs_dir: self.watch_songs_dir,
+            guitar_stem: self.guitar_stem,
         };
         let directory = get_data_dir().unwrap();
         let filepath = directory.join(FILENAME);
@@ -71,13 +96,57 @@ impl ConfigurationResource {
                     return ConfigurationResource {
                         device: Some(found_device),
                         selected_device_channels: serializable_configuration.selected_device_channels,
+                        left_handed: serializable_configuration.left_handed,
+                        invert_strings: serializable_configuration.invert_strings,
+                        dynamic_difficulty: serializable_configuration.dynamic_difficulty,
+                        watch_songs_dir: serializable_configuration.watch_songs_dir,
+                        guitar_stem: serializable_configuration.guitar_stem,
                         ..default()
                     }
                 }
             }
+
+            // Keep the display options even if the device is no longer available
+            return ConfigurationResource {
+                left_handed: serializable_configuration.left_handed,
+                invert_strings: serializable_configuration.invert_strings,
+                dynamic_difficulty: serializable_configuration.dynamic_difficulty,
+                watch_songs_dir: serializable_configuration.watch_songs_dir,
+                guitar_stem: serializable_configuration.guitar_stem,
+                ..default()
+            }
         }
 
         ConfigurationResource::default()
 
     }
+
+    /// 1.0 for right-handed players, -1.0 when the fret direction is mirrored
+    pub fn fret_direction(&self) -> f32 {
+        if self.left_handed { -1.0 } else { 1.0 }
+    }
+
+    /// Y coordinate of a fret (1 through FRET_AMOUNT) on the highway, taking handedness into account
+    /// Frets outside of the fret board are clamped to its first or last fret
+    pub fn fret_center(&self, fret_index: usize) -> f32 {
+        FRET_CENTERS[fret_index.clamp(1, FRET_AMOUNT) - 1] * self.fret_direction()
+    }
+
+    /// Z coordinate of a string on the highway, taking the string order into account
+    pub fn string_center(&self, string_index: usize) -> f32 {
+        if self.invert_strings {
+            STRING_CENTERS[STRING_CENTERS.len() - 1 - string_index]
+        } else {
+            STRING_CENTERS[string_index]
+        }
+    }
+
+    /// Mirrors a rotation along the fret axis when playing left-handed
+    pub fn mirror_rotation(&self, rotation: Quat) -> Quat {
+        if self.left_handed {
+            Quat::from_xyzw(-rotation.x, rotation.y, -rotation.z, rotation.w)
+        } else {
+            rotation
+        }
+    }
 }
@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+
+use crate::helpers::stems::StemKind;
+
+use super::{output_audio_song::{AudioCommand, OutputAudioControllerSong}, song_loaded::SongLoadedResource};
+
+const FADE_IN_SECONDS: f32 = 0.15;
+const FADE_OUT_SECONDS: f32 = 1.0;
+// How long the guitar stays audible after a miss before it fades out again
+const HOLD_SECONDS: f32 = 1.5;
+// Smallest volume change worth sending to the audio thread
+const VOLUME_STEP: f32 = 0.02;
+
+/// Volume of the guitar stem while it's faded in on missed notes
+#[derive(Resource, Debug, Default)]
+pub struct GuitarStemFadeResource {
+    pub active: bool, // Only set when the song plays its stems with the guitar faded in on misses
+    volume: f32,
+    sent_volume: f32,
+    hold_seconds: f32,
+    results_recorded: usize,
+}
+
+impl GuitarStemFadeResource {
+    pub fn new(active: bool) -> Self {
+        Self { active, ..Default::default() }
+    }
+
+    /// Brings the guitar back in, restarting the hold when it's already audible
+    pub fn miss(&mut self) {
+        self.hold_seconds = HOLD_SECONDS;
+    }
+
+    /// Moves the volume towards full while holding, and towards silence after
+    pub fn tick(&mut self, delta_seconds: f32) -> f32 {
+        if self.hold_seconds > 0.0 {
+            self.hold_seconds -= delta_seconds;
+            self.volume = (self.volume + delta_seconds / FADE_IN_SECONDS).min(1.0);
+        } else {
+            self.volume = (self.volume - delta_seconds / FADE_OUT_SECONDS).max(0.0);
+        }
+        self.volume
+    }
+}
+
+/// Fades the guitar stem in when notes are missed and out again once the player is back on track
+pub fn guitar_stem_fade_update(
+    time: Res<Time>,
+    song_loaded: Res<SongLoadedResource>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    mut guitar_stem_fade: ResMut<GuitarStemFadeResource>,
+) {
+    if !guitar_stem_fade.active {
+        return
+    }
+    let Some(song_progress) = song_loaded.progress.as_ref() else { return };
+
+    if song_progress.notes_with_score.iter().skip(guitar_stem_fade.results_recorded).any(|note_with_score| note_with_score.missed) {
+        guitar_stem_fade.miss();
+    }
+    guitar_stem_fade.results_recorded = song_progress.notes_with_score.len();
+
+    let volume = guitar_stem_fade.tick(time.delta_seconds());
+    let is_settled = volume == 0.0 || volume == 1.0;
+    if (volume - guitar_stem_fade.sent_volume).abs() >= VOLUME_STEP || (is_settled && volume != guitar_stem_fade.sent_volume) {
+        guitar_stem_fade.sent_volume = volume;
+        let _ = output_audio_song.sender.send(AudioCommand::SetStemVolume(StemKind::Guitar, volume));
+    }
+}
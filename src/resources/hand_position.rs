@@ -0,0 +1,25 @@
+use bevy::prelude::*;
+
+use crate::constants::ingame::{FRET_AMOUNT, OPEN_STRING_FRET_SPAN};
+
+#[derive(Resource, Debug)]
+pub struct HandPositionResource {
+    pub fret: f32, // Average fret of the fretted notes currently on the highway
+}
+
+impl Default for HandPositionResource {
+    fn default() -> Self {
+        HandPositionResource { fret: 1.0 }
+    }
+}
+
+impl HandPositionResource {
+    /// First and last fret of the window open string notes are drawn across
+    pub fn fret_window(&self) -> (usize, usize) {
+        let start = (self.fret.round() as usize)
+            .saturating_sub(OPEN_STRING_FRET_SPAN / 2 - 1)
+            .clamp(1, FRET_AMOUNT - OPEN_STRING_FRET_SPAN + 1);
+
+        (start, start + OPEN_STRING_FRET_SPAN - 1)
+    }
+}
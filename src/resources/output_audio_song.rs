@@ -1,16 +1,58 @@
-use std::{fs::File, io::BufReader, sync::mpsc::{self, Sender}, thread};
+use std::{path::Path, sync::mpsc::{self, RecvTimeoutError, Sender}, thread, time::{Duration, Instant}};
 
 use bevy::prelude::*;
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::{OutputStream, Sink, Source};
+
+use crate::helpers::{song_audio::open_audio_source, stems::StemKind};
 
 #[derive(Clone)]
 pub enum AudioCommand {
-    Play(String),
+    Play(String, f32), // File path and the position to start from in seconds
+    PlayStems(Vec<StemPlayback>, f32), // Stems mixed together, and the position to start from in seconds
+    SetStemVolume(StemKind, f32),
+    Preview(String, f32, f32), // File path, and the start and length of the clip in seconds
+    StopPreview, // Fades the preview out
     Pause,
     Resume,
     Stop,
 }
 
+#[derive(Clone)]
+pub struct StemPlayback {
+    pub kind: StemKind,
+    pub file_path: String,
+    pub volume: f32,
+}
+
+// Length of the fade in and out of previews
+const PREVIEW_FADE_SECONDS: f32 = 0.6;
+// How often the audio thread updates the volume of a fading preview
+const FADE_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A clip of a song playing on its own sink, so its volume can follow the fades
+struct PreviewPlayback {
+    sink: Sink,
+    started: Instant,
+    length_seconds: f32,
+    stopping: Option<(Instant, f32)>, // When the fade out was asked for, and the volume it started from
+}
+
+impl PreviewPlayback {
+    /// Volume for the current moment, None once the preview is over
+    fn volume(&self) -> Option<f32> {
+        let elapsed_seconds = self.started.elapsed().as_secs_f32();
+        let fade_in = (elapsed_seconds / PREVIEW_FADE_SECONDS).min(1.0);
+        let fade_out = ((self.length_seconds - elapsed_seconds) / PREVIEW_FADE_SECONDS).clamp(0.0, 1.0);
+        let mut volume = fade_in.min(fade_out);
+        if let Some((stopped, stop_volume)) = self.stopping {
+            volume = volume.min(stop_volume * (1.0 - stopped.elapsed().as_secs_f32() / PREVIEW_FADE_SECONDS).max(0.0));
+        }
+
+        let is_over = self.sink.empty() || elapsed_seconds >= self.length_seconds || (self.stopping.is_some() && volume <= 0.0);
+        (!is_over).then_some(volume)
+    }
+}
+
 #[derive(Resource)]
 pub struct OutputAudioControllerSong {
     pub sender: Sender<AudioCommand>,
@@ -23,37 +65,100 @@ pub fn output_audio_song_load(mut commands: Commands) {
 
     let thread_handle = thread::spawn(move || {
         let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-        let mut sink: Option<Sink> = None;
+        // A sink per stem, so every stem has its own volume, or a single sink for the full mix
+        let mut sinks: Vec<(Option<StemKind>, Sink)> = vec![];
+        let mut preview: Option<PreviewPlayback> = None;
+
+        // Audio thread main loop, waking up now and then to fade the preview
+        loop {
+            let command = match receiver.recv_timeout(FADE_INTERVAL) {
+                Ok(command) => Some(command),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
 
-        // Audio thread main loop
-        while let Ok(command) = receiver.recv() {
             match command {
-                AudioCommand::Play(file_path) => {
+                Some(AudioCommand::Play(file_path, start_seconds)) => {
                     // Load the file and start playing
-                    if let Ok(file) = File::open(file_path) {
-                        let source = Decoder::new(BufReader::new(file)).unwrap();
-                        let new_sink = Sink::try_new(&stream_handle).unwrap();
-                        new_sink.append(source);
-                        sink = Some(new_sink);
+                    preview = None;
+                    match open_audio_source(Path::new(&file_path)) {
+                        Ok(source) => {
+                            let new_sink = Sink::try_new(&stream_handle).unwrap();
+                            new_sink.append(source.skip_duration(Duration::from_secs_f32(start_seconds.max(0.0))));
+                            sinks = vec![(None, new_sink)];
+                        }
+                        Err(err) => error!("could not play {}: {}", file_path, err),
                     }
                 }
-                AudioCommand::Pause => {
-                    if let Some(s) = &sink {
-                        s.pause(); // Pause the current sink
+                Some(AudioCommand::PlayStems(stems, start_seconds)) => {
+                    // The sinks start paused and are started together, so the stems stay in sync
+                    preview = None;
+                    sinks.clear();
+                    for stem in stems {
+                        match open_audio_source(Path::new(&stem.file_path)) {
+                            Ok(source) => {
+                                let new_sink = Sink::try_new(&stream_handle).unwrap();
+                                new_sink.pause();
+                                new_sink.set_volume(stem.volume);
+                                new_sink.append(source.skip_duration(Duration::from_secs_f32(start_seconds.max(0.0))));
+                                sinks.push((Some(stem.kind), new_sink));
+                            }
+                            Err(err) => error!("could not play the {} stem {}: {}", stem.kind.name(), stem.file_path, err),
+                        }
+                    }
+                    for (_, s) in &sinks {
+                        s.play();
+                    }
+                }
+                Some(AudioCommand::SetStemVolume(kind, volume)) => {
+                    for (_, s) in sinks.iter().filter(|(stem_kind, _)| *stem_kind == Some(kind)) {
+                        s.set_volume(volume);
+                    }
+                }
+                Some(AudioCommand::Preview(file_path, start_seconds, length_seconds)) => {
+                    preview = None;
+                    match open_audio_source(Path::new(&file_path)) {
+                        Ok(source) => {
+                            let new_sink = Sink::try_new(&stream_handle).unwrap();
+                            new_sink.set_volume(0.0);
+                            new_sink.append(source
+                                .skip_duration(Duration::from_secs_f32(start_seconds.max(0.0)))
+                                .take_duration(Duration::from_secs_f32(length_seconds.max(0.0))));
+                            preview = Some(PreviewPlayback { sink: new_sink, started: Instant::now(), length_seconds, stopping: None });
+                        }
+                        Err(err) => error!("could not preview {}: {}", file_path, err),
+                    }
+                }
+                Some(AudioCommand::StopPreview) => {
+                    if let Some(preview) = preview.as_mut().filter(|preview| preview.stopping.is_none()) {
+                        preview.stopping = Some((Instant::now(), preview.sink.volume()));
+                    }
+                }
+                Some(AudioCommand::Pause) => {
+                    for (_, s) in &sinks {
+                        s.pause(); // Pause the current sinks
                     }
                 }
-                AudioCommand::Resume => {
-                    if let Some(s) = &sink {
+                Some(AudioCommand::Resume) => {
+                    for (_, s) in &sinks {
                         s.play(); // Resume playback from the paused position
                     }
                 }
-                AudioCommand::Stop => {
+                Some(AudioCommand::Stop) => {
                     println!("Stopping audio thread");
-                    if let Some(s) = &sink {
+                    for (_, s) in &sinks {
                         s.stop()
                     }
                     break;
                 }
+                None => {}
+            }
+
+            if let Some(playback) = &preview {
+                match playback.volume() {
+                    Some(volume) => playback.sink.set_volume(volume),
+                    None => preview = None,
+                }
             }
         }
     });
@@ -0,0 +1,76 @@
+use std::{collections::BTreeMap, fs::File, io::Read, time::{SystemTime, UNIX_EPOCH}};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::helpers::persistence::get_data_dir;
+
+use super::song_loaded::SongLoadedResource;
+
+const FILENAME: &str = "play_history.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SongPlayRecord {
+    pub last_played_millis: u64,
+    pub best_score: f32,
+    pub play_count: usize,
+}
+
+/// When every song was last played and the best score on it
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct PlayHistoryResource {
+    pub songs: BTreeMap<String, SongPlayRecord>,
+}
+
+impl PlayHistoryResource {
+    pub fn load_from_disk() -> Self {
+        let directory = get_data_dir().unwrap();
+        let filepath = directory.join(FILENAME);
+
+        if filepath.exists() {
+            let mut file = File::open(filepath).expect("Failed to open file");
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).expect("Failed to read file");
+
+            match serde_json::from_str::<PlayHistoryResource>(&contents) {
+                Ok(play_history) => return play_history,
+                Err(err) => warn!("could not read {}: {}", FILENAME, err),
+            }
+        }
+
+        PlayHistoryResource::default()
+    }
+
+    pub fn save_to_disk(&self) {
+        let directory = get_data_dir().unwrap();
+        let filepath = directory.join(FILENAME);
+        let file = File::create(filepath).expect("Failed to create file");
+        serde_json::to_writer(file, self).expect("Failed to write JSON to file");
+    }
+
+    pub fn get(&self, uuid: &str) -> Option<&SongPlayRecord> {
+        self.songs.get(uuid)
+    }
+
+    /// Records a play of a song, keeping the best score
+    pub fn record(&mut self, uuid: &str, score: f32) {
+        let record = self.songs.entry(uuid.to_string()).or_default();
+        record.last_played_millis = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_millis() as u64).unwrap_or_default();
+        record.best_score = record.best_score.max(score);
+        record.play_count += 1;
+    }
+}
+
+/// Records the song that was just played, test plays from the editor don't count
+pub fn play_history_record(
+    song_loaded: Res<SongLoadedResource>,
+    mut play_history: ResMut<PlayHistoryResource>,
+) {
+    if song_loaded.test_play {
+        return
+    }
+    let (Some(song_metadata), Some(song_progress)) = (&song_loaded.metadata, &song_loaded.progress) else { return };
+
+    play_history.record(&song_metadata.uuid, song_progress.score);
+    play_history.save_to_disk();
+}
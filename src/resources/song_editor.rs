@@ -0,0 +1,333 @@
+use std::error::Error;
+
+use bevy::{prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}};
+
+use crate::{constants::ingame::FRET_AMOUNT, helpers::{chart::{chart_filename, Chart, SongChart}, notes::NoteEvent, persistence::get_songs_dir, recording::Recording, tempo_map::TempoMap}, transcription::{beat_tracking::{estimate_tempo, fit_beats, TempoEstimate}, transcription::DecodedAudio}};
+
+use super::song_library::SongMetadata;
+
+// Amount of edits that can be undone
+const UNDO_LIMIT: usize = 200;
+// Taps needed before the tempo is set from them
+const MIN_TAPS: usize = 4;
+// A pause this long between taps starts a new series of taps
+const TAP_RESET_SECONDS: f32 = 2.0;
+// Grid subdivisions per beat that can be cycled through
+pub const GRID_SUBDIVISIONS: [u32; 6] = [1, 2, 3, 4, 6, 8];
+
+/// State of the chart editor, kept while test playing so unsaved edits aren't lost
+#[derive(Resource, Debug)]
+pub struct SongEditorResource {
+    pub song_metadata: SongMetadata,
+    pub song_chart: Option<SongChart>, // Chart that is edited, None for the main chart
+    pub chart: Chart,
+    undo_stack: Vec<Chart>,
+    redo_stack: Vec<Chart>,
+    pub selected: Option<usize>, // Index into the notes of the chart
+    pub playhead_seconds: f32,
+    pub playing: bool,
+    pub grid_subdivision: u32, // Grid lines per beat of the tempo map
+    pub string_index: usize, // String new notes are placed on
+    pub fret_index: usize, // Fret new notes are placed on
+    pub has_unsaved_changes: bool,
+    pub taps: Vec<f32>, // Times of the current series of tap tempo taps
+    pub recording: Option<Recording>, // Performance being recorded while the song plays
+    pub hover: Option<(f32, usize)>, // Snapped time and fret under the mouse pointer
+    pub revision: u64, // Increased on every change to the notes, so the highway knows to respawn them
+    pub status: String, // Feedback on the last action
+    tempo_detection: Option<Task<Result<TempoEstimate, String>>>, // Decodes and beat tracks the song in the background
+}
+
+impl SongEditorResource {
+    pub fn load(song_metadata: SongMetadata, song_chart: Option<SongChart>) -> Self {
+        let chart = Self::load_chart(&song_metadata, song_chart);
+
+        Self {
+            song_metadata,
+            song_chart,
+            chart,
+            undo_stack: vec![],
+            redo_stack: vec![],
+            selected: None,
+            playhead_seconds: 0.0,
+            playing: false,
+            grid_subdivision: 4,
+            string_index: 0,
+            fret_index: 1,
+            has_unsaved_changes: false,
+            taps: vec![],
+            recording: None,
+            hover: None,
+            revision: 0,
+            status: String::new(),
+            tempo_detection: None,
+        }
+    }
+
+    /// Reads a chart of the song, a chart that doesn't exist yet starts empty
+    fn load_chart(song_metadata: &SongMetadata, song_chart: Option<SongChart>) -> Chart {
+        let filename = chart_filename(song_chart);
+        let chart_path = get_songs_dir().unwrap().join(&song_metadata.uuid).join(filename);
+        if !chart_path.exists() {
+            return Chart::default()
+        }
+
+        Chart::load_from_file(&chart_path).unwrap_or_else(|err| {
+            warn!("could not load chart {:?}, starting with an empty chart: {}", chart_path, err);
+            Chart::default()
+        })
+    }
+
+    /// Switches to editing another chart of the song, dropping unsaved changes
+    pub fn switch_chart(&mut self, song_chart: Option<SongChart>) {
+        self.chart = Self::load_chart(&self.song_metadata, song_chart);
+        self.song_chart = song_chart;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.selected = None;
+        self.has_unsaved_changes = false;
+        self.revision += 1;
+    }
+
+    /// "main chart" or "lead expert", with a note when the chart isn't saved yet
+    pub fn chart_label(&self) -> String {
+        match self.song_chart {
+            Some(song_chart) if self.song_metadata.charts.contains(&song_chart) => song_chart.label(),
+            Some(song_chart) => format!("{} (new)", song_chart.label()),
+            None => "main chart".to_string(),
+        }
+    }
+
+    /// Saves the chart that is edited, a chart of an arrangement and difficulty is listed in the metadata
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        let song_directory = get_songs_dir().ok_or("songs directory is not available")?.join(&self.song_metadata.uuid);
+        let filename = chart_filename(self.song_chart);
+        self.chart.save_to_file(&song_directory.join(filename))?;
+
+        if let Some(song_chart) = self.song_chart.filter(|song_chart| !self.song_metadata.charts.contains(song_chart)) {
+            self.song_metadata.add_chart(song_chart);
+            self.song_metadata.save_to_file(&song_directory.join("metadata.json"))?;
+        }
+        self.has_unsaved_changes = false;
+
+        Ok(())
+    }
+
+    /// Starts estimating the tempo map from the audio of the song, unless that is already running
+    /// Decoding the whole song takes a moment, so it runs as a task and the editor keeps running
+    pub fn detect_tempo(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.tempo_detection.is_some() {
+            return Ok(())
+        }
+        let audio_path = self.song_metadata.audio_path().ok_or("the song has no audio file")?;
+        let beats_per_measure = self.chart.tempo_map.change_at(0.0).beats_per_measure;
+
+        self.tempo_detection = Some(AsyncComputeTaskPool::get().spawn(async move {
+            let audio = DecodedAudio::load_from_file(&audio_path).map_err(|err| err.to_string())?;
+            estimate_tempo(&audio, beats_per_measure).map_err(|err| err.to_string())
+        }));
+        self.status = "detecting the tempo".to_string();
+
+        Ok(())
+    }
+
+    /// Replaces the tempo map with the estimate once it's done, keeping the time signature
+    /// Returns whether the detection finished
+    pub fn apply_detected_tempo(&mut self) -> bool {
+        let Some(task) = self.tempo_detection.as_mut() else { return false };
+        let Some(result) = block_on(future::poll_once(task)) else { return false };
+        self.tempo_detection = None;
+
+        match result {
+            Ok(estimate) => {
+                let beats_per_measure = self.chart.tempo_map.change_at(0.0).beats_per_measure;
+                self.edit_tempo_map(|tempo_map| *tempo_map = estimate.to_tempo_map(beats_per_measure));
+                self.status = format!("detected {:.1} bpm, first downbeat at {:.2}s", estimate.bpm, estimate.first_downbeat_seconds);
+            }
+            Err(err) => {
+                error!("could not detect the tempo: {}", err);
+                self.status = format!("could not detect the tempo: {}", err);
+            }
+        }
+        true
+    }
+
+    /// Time between grid lines at a point in the song
+    pub fn grid_step_seconds_at(&self, seconds: f32) -> f32 {
+        self.chart.tempo_map.beat_seconds_at(seconds) / self.grid_subdivision as f32
+    }
+
+    pub fn grid_step_seconds(&self) -> f32 {
+        self.grid_step_seconds_at(self.playhead_seconds)
+    }
+
+    /// Closest grid line to a time
+    pub fn snap(&self, seconds: f32) -> f32 {
+        self.chart.tempo_map.snap(seconds, self.grid_subdivision)
+    }
+
+    /// Moves the playhead a number of grid steps, landing on the grid
+    pub fn move_playhead(&mut self, steps: i32) {
+        let mut target = self.snap(self.playhead_seconds);
+        for _ in 0..steps.abs() {
+            // Stepping back uses the tempo before the current line, so steps across a tempo change land on the grid
+            target = if steps > 0 {
+                self.snap(target + self.grid_step_seconds_at(target))
+            } else {
+                self.snap(target - self.grid_step_seconds_at(target - 0.001))
+            };
+        }
+        self.playhead_seconds = target.clamp(0.0, self.song_metadata.audio_duration_seconds());
+    }
+
+    /// Stores the current chart so the next edit can be undone
+    fn push_undo(&mut self) {
+        self.undo_stack.push(self.chart.clone());
+        if self.undo_stack.len() > UNDO_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+        self.mark_changed();
+    }
+
+    fn mark_changed(&mut self) {
+        self.has_unsaved_changes = true;
+        self.revision += 1;
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(chart) = self.undo_stack.pop() {
+            self.redo_stack.push(std::mem::replace(&mut self.chart, chart));
+            self.selected = None;
+            self.mark_changed();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(chart) = self.redo_stack.pop() {
+            self.undo_stack.push(std::mem::replace(&mut self.chart, chart));
+            self.selected = None;
+            self.mark_changed();
+        }
+    }
+
+    /// Sorts the notes while keeping the same note selected
+    fn sort_keeping_selection(&mut self, selected_note: Option<NoteEvent>) {
+        self.chart.sort_notes();
+        self.selected = selected_note.and_then(|selected_note| self.chart.notes.iter().position(|note_event| note_event.equals(&selected_note)));
+    }
+
+    /// The note on a string that starts closest to a time, within a tolerance
+    pub fn note_at(&self, seconds: f32, string_index: usize, tolerance_seconds: f32) -> Option<usize> {
+        self.chart.notes.iter().enumerate()
+            .filter(|(_, note_event)| note_event.string_index == string_index && (note_event.start_time_seconds - seconds).abs() <= tolerance_seconds)
+            .min_by(|a, b| (a.1.start_time_seconds - seconds).abs().partial_cmp(&(b.1.start_time_seconds - seconds).abs()).unwrap())
+            .map(|(index, _)| index)
+    }
+
+    /// Adds a note with the current string and fret, or selects the note that is already there
+    pub fn place_note(&mut self, seconds: f32) {
+        let start_time_seconds = self.snap(seconds);
+        if let Some(index) = self.note_at(start_time_seconds, self.string_index, 0.001) {
+            self.selected = Some(index);
+            return
+        }
+
+        self.push_undo();
+        let note_event = NoteEvent {
+            start_time_seconds,
+            duration_seconds: self.grid_step_seconds(),
+            string_index: self.string_index,
+            fret_index: self.fret_index,
+            ..Default::default()
+        };
+        self.chart.notes.push(note_event.clone());
+        self.sort_keeping_selection(Some(note_event));
+    }
+
+    /// Replaces the notes that start in a time range, as a single undo step
+    pub fn replace_range(&mut self, start_seconds: f32, end_seconds: f32, note_events: Vec<NoteEvent>) {
+        self.push_undo();
+        self.selected = None;
+        self.chart.notes.retain(|note_event| note_event.start_time_seconds < start_seconds || note_event.start_time_seconds >= end_seconds);
+        self.chart.notes.extend(note_events);
+        self.chart.sort_notes();
+    }
+
+    pub fn delete_selected(&mut self) {
+        if let Some(index) = self.selected.take() {
+            self.push_undo();
+            self.chart.notes.remove(index);
+        }
+    }
+
+    /// Changes the selected note, a single undo step is stored unless the edit continues a drag
+    pub fn edit_selected(&mut self, store_undo: bool, edit: impl FnOnce(&mut NoteEvent)) {
+        if let Some(index) = self.selected {
+            if store_undo {
+                self.push_undo();
+            } else {
+                self.mark_changed();
+            }
+
+            let note_event = &mut self.chart.notes[index];
+            edit(note_event);
+            note_event.fret_index = note_event.fret_index.min(FRET_AMOUNT);
+            note_event.duration_seconds = note_event.duration_seconds.max(0.01);
+            note_event.start_time_seconds = note_event.start_time_seconds.max(0.0);
+
+            let note_event = note_event.clone();
+            self.sort_keeping_selection(Some(note_event));
+        }
+    }
+
+    /// Changes the tempo map as a single undo step
+    pub fn edit_tempo_map(&mut self, edit: impl FnOnce(&mut TempoMap)) {
+        self.push_undo();
+        edit(&mut self.chart.tempo_map);
+    }
+
+    /// Registers a tap on the beat, once there are enough taps the tempo of the section is set from them
+    /// The first tap is taken as a downbeat
+    pub fn tap_tempo(&mut self, seconds: f32) {
+        if self.taps.last().is_some_and(|&last| seconds <= last || seconds - last > TAP_RESET_SECONDS) {
+            self.taps.clear();
+        }
+        self.taps.push(seconds);
+
+        if self.taps.len() < MIN_TAPS {
+            self.status = format!("tap {} more times", MIN_TAPS - self.taps.len());
+            return
+        }
+        let Some((beat_seconds, first_tap)) = fit_beats(&self.taps) else { return };
+
+        // A series of taps is a single undo step
+        if self.taps.len() == MIN_TAPS {
+            self.push_undo();
+        } else {
+            self.mark_changed();
+        }
+
+        let is_first_section = self.chart.tempo_map.changes.iter().filter(|change| change.start_seconds <= first_tap).count() <= 1;
+        self.chart.tempo_map.edit_change_at(first_tap, |change| {
+            change.bpm = 60.0 / beat_seconds;
+            // The first section starts on the earliest downbeat of the song
+            let measure_seconds = beat_seconds * change.beats_per_measure as f32;
+            change.start_seconds = if is_first_section {
+                first_tap - (first_tap / measure_seconds).floor() * measure_seconds
+            } else {
+                first_tap
+            };
+        });
+        self.status = format!("tapped {:.1} bpm", 60.0 / beat_seconds);
+    }
+
+    /// Stores an undo step before a drag starts
+    pub fn begin_drag(&mut self) {
+        self.push_undo();
+    }
+
+    pub fn selected_note(&self) -> Option<&NoteEvent> {
+        self.selected.and_then(|index| self.chart.notes.get(index))
+    }
+}
@@ -1,11 +1,14 @@
-use std::{fs::{self, File}, io::Read};
+use std::{collections::BTreeMap, error::Error, fs::{self, File}, io::Read, path::{Path, PathBuf}, sync::atomic::{AtomicUsize, Ordering}, time::UNIX_EPOCH};
 
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::helpers::persistence::{get_data_dir, get_songs_dir};
+use crate::helpers::{audio_info::AudioInfo, chart::{SongChart, CHART_FILENAME}, persistence::{get_data_dir, get_songs_dir}, song_audio::{find_preview_audio, find_song_audio, PreviewClip}, song_validation::{validate_song_directory, LibraryReport, SongReport}, stems::SongStem};
 
 const FILENAME: &str = "song_library.json";
+// Previews without a clip chosen by the mapper start at this fraction of the song
+const DEFAULT_PREVIEW_POSITION: f32 = 0.4;
+const DEFAULT_PREVIEW_SECONDS: f32 = 20.0;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SongMetadata {
@@ -14,59 +17,189 @@ pub struct SongMetadata {
     pub artists: Vec<String>,
     pub tuning: [String; 6],
     pub duration_seconds: f32,
+    // Arrangements and difficulties the song offers, a song without any only has its main chart
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub charts: Vec<SongChart>,
+    // Name of the audio file in the song directory, when it's not audio.<extension>
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_filename: Option<String>,
+    // Separate guitar, backing and vocal tracks, so the guitar can be muted
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stems: Vec<SongStem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<PreviewClip>,
+    // Read from the audio by the library scan, the metadata file doesn't hold it
+    #[serde(skip)]
+    pub audio_info: Option<AudioInfo>,
+    // Whether the song has a chart.json next to the charts it lists, also filled by the library scan
+    #[serde(skip)]
+    pub has_main_chart: bool,
 }
 
-#[derive(Resource, Debug, Serialize, Deserialize)]
-pub struct SongLibraryResource {
-    pub songs: Vec<SongMetadata>
-}
+impl SongMetadata {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+
+    /// Audio file of the song in the library
+    pub fn audio_path(&self) -> Option<PathBuf> {
+        find_song_audio(&get_songs_dir()?.join(&self.uuid), self.audio_filename.as_deref())
+    }
 
-impl Default for SongLibraryResource {
-    fn default() -> Self {
-        Self { songs: Self::get_song_metadata_list().unwrap() }
+    /// Audio file and the clip of it that is played as a preview in song select
+    /// A clip chosen in the metadata comes first, then a preview file, then a window in the middle of the song
+    pub fn preview_clip(&self) -> Option<(PathBuf, PreviewClip)> {
+        if let Some(preview) = self.preview {
+            return Some((self.audio_path()?, preview))
+        }
+        if let Some(preview_path) = find_preview_audio(&get_songs_dir()?.join(&self.uuid)) {
+            return Some((preview_path, PreviewClip { start_seconds: 0.0, duration_seconds: DEFAULT_PREVIEW_SECONDS }))
+        }
+
+        // Songs of unknown length are previewed from the start
+        let duration_seconds = self.audio_duration_seconds();
+        let start_seconds = duration_seconds * DEFAULT_PREVIEW_POSITION;
+        let preview_clip = if duration_seconds > 0.0 {
+            PreviewClip { start_seconds, duration_seconds: DEFAULT_PREVIEW_SECONDS.min(duration_seconds - start_seconds) }
+        } else {
+            PreviewClip { start_seconds: 0.0, duration_seconds: DEFAULT_PREVIEW_SECONDS }
+        };
+        Some((self.audio_path()?, preview_clip))
+    }
+
+    /// Length of the song, from the audio when the library scan could read it
+    pub fn audio_duration_seconds(&self) -> f32 {
+        self.audio_info.as_ref().map(|audio_info| audio_info.duration_seconds).unwrap_or(self.duration_seconds)
+    }
+
+    /// Lists a chart of the song, keeping the list sorted by arrangement and difficulty
+    pub fn add_chart(&mut self, song_chart: SongChart) {
+        if !self.charts.contains(&song_chart) {
+            self.charts.push(song_chart);
+            self.charts.sort_by_key(|song_chart| (song_chart.arrangement as usize, song_chart.difficulty as usize));
+        }
     }
 }
 
-impl SongLibraryResource {
-    pub fn get_song_metadata_list() -> Result<Vec<SongMetadata>, Box<dyn std::error::Error>> {
-        let songs_dir = get_songs_dir().unwrap();
-        let mut song_metadata_list: Vec<SongMetadata> = vec![];
-
-        // Iterate over the entries in the base directory
-        for entry in fs::read_dir(songs_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-
-            // Check if the entry is a directory
-            if path.is_dir() {
-                // Check if "audio.mp3" exists inside the directory
-                let audio_path = path.join("audio.mp3");
-                if !audio_path.exists() {
-                    error!("audio.mp3 not found in {:?}", path);
-                    continue;
-                }
+/// Result of scanning one folder of the songs directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannedFolder {
+    pub modified_millis: u64, // Latest modification of the folder or any file in it
+    pub song_metadata: Option<SongMetadata>, // None when the song can't be played
+    #[serde(default)]
+    pub audio_info: Option<AudioInfo>,
+    #[serde(default)]
+    pub has_main_chart: bool,
+    pub report: SongReport,
+}
 
-                // Create the path to the "metadata.json" file within the directory
-                let metadata_path = path.join("metadata.json");
+/// Folders scanned so far, shared with the task that scans them
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    pub done: AtomicUsize,
+    pub total: AtomicUsize,
+}
 
-                // Check if the "metadata.json" file exists
-                if metadata_path.exists() && metadata_path.is_file() {
+/// Latest modification time of a folder and the files in it, adding or replacing a file changes it
+fn folder_modified_millis(path: &Path) -> u64 {
+    let modified_millis = |metadata: fs::Metadata| metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0);
+
+    let entries = fs::read_dir(path).into_iter().flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .map(modified_millis);
+    fs::metadata(path).map(modified_millis).unwrap_or(0).max(entries.max().unwrap_or(0))
+}
 
-                    let mut file = File::open(metadata_path).expect("Failed to open file");
-                    let mut contents = String::new();
-                    file.read_to_string(&mut contents).expect("Failed to read file");
-            
-                    // Deserialize the JSON contents into the serializable struct
-                    let song_metadata: SongMetadata =
-                        serde_json::from_str(&contents).expect("Failed to deserialize JSON");
+#[derive(Resource, Debug, Default, Serialize, Deserialize)]
+pub struct SongLibraryResource {
+    #[serde(skip)]
+    pub songs: Vec<SongMetadata>,
+    #[serde(skip)]
+    pub report: LibraryReport, // Problems found in the songs directory during the last scan
+    #[serde(default)]
+    pub folders: BTreeMap<String, ScannedFolder>, // Keyed by folder name
+}
 
-                    // Add the deserialized metadata to the vector
-                    song_metadata_list.push(song_metadata);
+impl SongLibraryResource {
+    /// Scans every folder of the songs directory, only reading the folders that changed since the previous scan
+    pub fn scan_folders(previous: &BTreeMap<String, ScannedFolder>, progress: &ScanProgress) -> Result<BTreeMap<String, ScannedFolder>, Box<dyn std::error::Error>> {
+        let songs_dir = get_songs_dir().ok_or("songs directory is not available")?;
+
+        let song_directories: Vec<PathBuf> = fs::read_dir(songs_dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            // Hidden folders are packages that are still being unpacked
+            .filter(|path| path.is_dir() && !path.file_name().unwrap_or_default().to_string_lossy().starts_with('.'))
+            .collect();
+        progress.total.store(song_directories.len(), Ordering::Relaxed);
+
+        let mut folders = BTreeMap::new();
+        for song_directory in song_directories {
+            let folder = song_directory.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let modified_millis = folder_modified_millis(&song_directory);
+            // Checked on every scan, so a library saved before it was stored still finds its main charts
+            let has_main_chart = song_directory.join(CHART_FILENAME).exists();
+
+            let scanned_folder = match previous.get(&folder) {
+                Some(scanned_folder) if scanned_folder.modified_millis == modified_millis => ScannedFolder { has_main_chart, ..scanned_folder.clone() },
+                _ => {
+                    let (song_metadata, audio_info, report) = validate_song_directory(&song_directory);
+                    for issue in &report.issues {
+                        warn!("{}: {}", report.folder, issue);
+                    }
+                    ScannedFolder { modified_millis, song_metadata, audio_info, has_main_chart, report }
                 }
-            }
+            };
+            folders.insert(folder, scanned_folder);
+            progress.done.fetch_add(1, Ordering::Relaxed);
         }
 
-        Ok(song_metadata_list)
+        Ok(folders)
+    }
+
+    /// Reads the metadata of every playable song, and reports the problems of every folder
+    pub fn scan_songs_dir() -> Result<(Vec<SongMetadata>, LibraryReport), Box<dyn std::error::Error>> {
+        let mut song_library = SongLibraryResource::default();
+        song_library.apply_scan(Self::scan_folders(&BTreeMap::new(), &ScanProgress::default())?);
+
+        Ok((song_library.songs, song_library.report))
+    }
+
+    /// Whether a scan found the same folders, unchanged since the last scan
+    pub fn is_unchanged(&self, folders: &BTreeMap<String, ScannedFolder>) -> bool {
+        self.folders.len() == folders.len() && self.folders.iter().zip(folders).all(|((folder, scanned_folder), (other_folder, other_scanned_folder))| {
+            folder == other_folder && scanned_folder.modified_millis == other_scanned_folder.modified_millis && scanned_folder.has_main_chart == other_scanned_folder.has_main_chart
+        })
+    }
+
+    /// Replaces the scanned folders, updating the song list and the report
+    pub fn apply_scan(&mut self, folders: BTreeMap<String, ScannedFolder>) {
+        self.folders = folders;
+        self.songs = self.folders.values().filter_map(|scanned_folder| {
+            let mut song_metadata = scanned_folder.song_metadata.clone()?;
+            song_metadata.audio_info = scanned_folder.audio_info.clone();
+            song_metadata.has_main_chart = scanned_folder.has_main_chart;
+            Some(song_metadata)
+        }).collect();
+        self.report = LibraryReport {
+            songs: self.folders.values()
+                .filter(|scanned_folder| !scanned_folder.report.issues.is_empty())
+                .map(|scanned_folder| scanned_folder.report.clone())
+                .collect(),
+        };
     }
 
     pub fn load_from_disk() -> Self {
@@ -79,13 +212,18 @@ impl SongLibraryResource {
             let mut contents = String::new();
             file.read_to_string(&mut contents).expect("Failed to read file");
     
-            // Deserialize the JSON contents into the serializable struct
-            let serializable_configuration: SongLibraryResource =
-                serde_json::from_str(&contents).expect("Failed to deserialize JSON");
-            info!("{:?}", serializable_configuration);
-            return serializable_configuration
+            // A library that can't be read is scanned again from scratch
+            match serde_json::from_str::<SongLibraryResource>(&contents) {
+                Ok(mut song_library) => {
+                    let folders = std::mem::take(&mut song_library.folders);
+                    song_library.apply_scan(folders);
+                    return song_library
+                }
+                Err(err) => warn!("could not read {}: {}", FILENAME, err),
+            }
         }
 
+        // Filled by the first scan
         SongLibraryResource::default()
     }
 
@@ -99,10 +237,4 @@ impl SongLibraryResource {
     pub fn find_by_id(&self, uuid: &String) -> Option<&SongMetadata> {
         return self.songs.iter().find(|song_metadata| &song_metadata.uuid == uuid);
     }
-
-    pub fn scan(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        self.songs = Self::get_song_metadata_list()?;
-
-        Ok(())
-    }
 }
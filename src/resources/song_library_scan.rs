@@ -0,0 +1,91 @@
+use std::{collections::BTreeMap, sync::{atomic::Ordering, Arc}};
+
+use bevy::{prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}};
+
+use super::{configuration::ConfigurationResource, song_library::{ScanProgress, ScannedFolder, SongLibraryResource}};
+
+// How often the songs directory is checked for changes while watching it
+const WATCH_INTERVAL_SECONDS: f32 = 3.0;
+
+/// Scans the song library in the background, so the game keeps running while hundreds of folders are read
+#[derive(Resource)]
+pub struct SongLibraryScanResource {
+    task: Option<Task<Result<BTreeMap<String, ScannedFolder>, String>>>,
+    progress: Arc<ScanProgress>,
+    watch_timer: Timer,
+    quiet: bool, // Started by watching the songs directory, so no progress is shown
+}
+
+impl Default for SongLibraryScanResource {
+    fn default() -> Self {
+        Self {
+            task: None,
+            progress: Arc::new(ScanProgress::default()),
+            watch_timer: Timer::from_seconds(WATCH_INTERVAL_SECONDS, TimerMode::Repeating),
+            quiet: false,
+        }
+    }
+}
+
+impl SongLibraryScanResource {
+    /// Starts scanning the folders that changed since the last scan, unless a scan is already running
+    pub fn start(&mut self, song_library: &SongLibraryResource) {
+        // Asking for a scan while watching shows the progress of the scan that is already running
+        self.quiet = false;
+        if self.task.is_some() {
+            return
+        }
+
+        let previous = song_library.folders.clone();
+        let progress = Arc::new(ScanProgress::default());
+        self.progress = progress.clone();
+        self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            SongLibraryResource::scan_folders(&previous, &progress).map_err(|err| err.to_string())
+        }));
+    }
+
+    /// Whether a scan that should show its progress is running
+    pub fn is_scanning(&self) -> bool {
+        self.task.is_some() && !self.quiet
+    }
+
+    /// Folders scanned and the number of folders in the songs directory
+    pub fn progress(&self) -> (usize, usize) {
+        (self.progress.done.load(Ordering::Relaxed), self.progress.total.load(Ordering::Relaxed))
+    }
+}
+
+pub fn song_library_scan_start(
+    mut song_library_scan: ResMut<SongLibraryScanResource>,
+    song_library: Res<SongLibraryResource>,
+) {
+    song_library_scan.start(&song_library);
+}
+
+/// Applies finished scans, and starts a scan now and then when watching the songs directory
+pub fn song_library_scan_update(
+    time: Res<Time>,
+    configuration: Res<ConfigurationResource>,
+    mut song_library_scan: ResMut<SongLibraryScanResource>,
+    mut song_library: ResMut<SongLibraryResource>,
+) {
+    if let Some(task) = song_library_scan.task.as_mut() {
+        if let Some(result) = block_on(future::poll_once(task)) {
+            song_library_scan.task = None;
+            match result {
+                // Only touches the library when something changed, so the song list isn't rebuilt for nothing
+                Ok(folders) => if !song_library.is_unchanged(&folders) {
+                    song_library.apply_scan(folders);
+                    song_library.save_to_disk();
+                },
+                Err(err) => error!("could not scan the song library: {}", err),
+            }
+        }
+    }
+
+    let watch_tick = configuration.watch_songs_dir && song_library_scan.watch_timer.tick(time.delta()).just_finished();
+    if watch_tick && song_library_scan.task.is_none() {
+        song_library_scan.start(&song_library);
+        song_library_scan.quiet = true;
+    }
+}
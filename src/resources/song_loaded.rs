@@ -1,149 +1,292 @@
-use std::{fs::File, io::BufReader};
+use std::time::Duration;
 
 use bevy::prelude::*;
-use uuid::Uuid;
 
-use crate::helpers::{persistence::get_songs_dir, notes::NoteEvent};
+use crate::helpers::{audio_info::AudioInfo, chart::{Chart, Difficulty, Phrase, SongChart, CHART_FILENAME}, difficulty::tag_difficulties, dynamic_difficulty::DynamicDifficulty, notes::{Bend, Legato, NoteEvent}, persistence::get_songs_dir, song_validation::DURATION_TOLERANCE_SECONDS, stems::StemKind, tempo_map::TempoMap};
 
 use super::song_library::SongMetadata;
 
+/// Placeholder notes for songs that don't have a chart yet
+fn demo_note_events() -> Vec<NoteEvent> {
+    vec![
+        NoteEvent {
+            start_time_seconds: 1.0,
+            duration_seconds: 1.0,
+            string_index: 3,
+            fret_index: 10,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 2.0,
+            duration_seconds: 1.0,
+            string_index: 1,
+            fret_index: 11,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 3.0,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 4.0,
+            duration_seconds: 1.0,
+            string_index: 3,
+            fret_index: 10,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 5.0,
+            duration_seconds: 1.0,
+            string_index: 4,
+            fret_index: 11,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 6.0,
+            duration_seconds: 1.0,
+            string_index: 5,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 7.0,
+            duration_seconds: 1.0,
+            string_index: 5,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 8.0,
+            duration_seconds: 1.0,
+            string_index: 5,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 9.0,
+            duration_seconds: 1.0,
+            string_index: 5,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 10.0,
+            duration_seconds: 1.0,
+            string_index: 5,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 11.0,
+            duration_seconds: 1.0,
+            string_index: 4,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 12.0,
+            duration_seconds: 1.0,
+            string_index: 4,
+            fret_index: 9,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 13.0,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 9,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 14.0,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 9,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 15.0,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 9,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 16.0,
+            duration_seconds: 1.0,
+            string_index: 0,
+            fret_index: 0,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 17.0,
+            duration_seconds: 1.0,
+            string_index: 3,
+            fret_index: 10,
+            bend: Some(Bend { semitones: 2.0, curve: vec![] }),
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 18.0,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 10,
+            slide_to_fret: Some(12),
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 19.0,
+            duration_seconds: 0.5,
+            string_index: 2,
+            fret_index: 12,
+            ..Default::default()
+        },
+        NoteEvent {
+            start_time_seconds: 19.5,
+            duration_seconds: 1.0,
+            string_index: 2,
+            fret_index: 10,
+            legato: Some(Legato::PullOff),
+            vibrato: true,
+            ..Default::default()
+        },
+    ]
+}
+
 #[derive(Debug, Clone)]
 pub struct NoteEventWithScore {
-    missed: bool,
-    note_event: NoteEvent
+    pub missed: bool,
+    pub note_event: NoteEvent,
+    pub technique_accuracy: Option<f32>, // 0 to 1, for techniques that are judged over the duration of the note
+    pub sustain_ratio: Option<f32>, // 0 to 1, how much of a long note was held
 }
 
 #[derive(Debug, Clone)]
 pub struct SongLoadedProgress {
     pub timer: Timer,
     pub notes_with_score: Vec<NoteEventWithScore>,
-    pub previous_onset_secs: f32
+    pub previous_onset_secs: f32,
+    pub score: f32,
 }
 
 #[derive(Resource, Debug)]
 #[derive(Default)]
 pub struct SongLoadedResource {
     pub metadata: Option<SongMetadata>,
+    pub song_chart: Option<SongChart>, // None when playing the main chart
     pub audio_path: Option<String>,
+    pub stems: Vec<(StemKind, String)>, // File paths of the stems of the song that exist
     pub notes: Option<Vec<NoteEvent>>,
+    pub tempo_map: TempoMap,
+    pub phrases: Vec<Phrase>,
+    pub dynamic_difficulty: Option<DynamicDifficulty>, // Set when the difficulty adapts to the player
     pub progress: Option<SongLoadedProgress>,
+    pub test_play: bool, // Started from the editor, so leaving the song returns to the editor
 }
 
 
 impl SongLoadedResource {
-    pub fn load_song(&mut self, song_metadata: SongMetadata) {
+    /// Starts a song with one of the charts it lists, or with its main chart
+    pub fn load_song(&mut self, song_metadata: SongMetadata, song_chart: Option<SongChart>) {
+        let song_directory = get_songs_dir().unwrap().join(&song_metadata.uuid);
+
+        // Load the notes from the chart of the song
+        let chart_path = match song_chart {
+            Some(song_chart) if song_directory.join(song_chart.filename()).exists() => song_directory.join(song_chart.filename()),
+            Some(song_chart) => {
+                warn!("chart {} of {} not found, using the main chart", song_chart.filename(), song_metadata.uuid);
+                song_directory.join(CHART_FILENAME)
+            }
+            None => song_directory.join(CHART_FILENAME),
+        };
+        let chart = match Chart::load_from_file(&chart_path) {
+            Ok(chart) => chart,
+            Err(err) => {
+                warn!("could not load chart {:?}, using demo notes: {}", chart_path, err);
+                Chart { notes: demo_note_events(), ..Default::default() }
+            }
+        };
+
+        self.load_chart(song_metadata, chart, 0.0);
+        self.song_chart = song_chart;
+        self.test_play = false;
+    }
+
+    /// Starts a song whose difficulty adapts to the player, from the level of the chosen chart
+    /// Adapting needs every note, so the main chart is played, or the hardest chart of the arrangement when there is no main chart
+    pub fn load_song_with_dynamic_difficulty(&mut self, song_metadata: SongMetadata, song_chart: Option<SongChart>) {
+        let song_directory = get_songs_dir().unwrap().join(&song_metadata.uuid);
+        let full_chart = song_chart
+            .filter(|_| !song_directory.join(CHART_FILENAME).exists())
+            .map(|song_chart| song_metadata.charts.iter()
+                .filter(|listed| listed.arrangement == song_chart.arrangement && song_directory.join(listed.filename()).exists())
+                .max_by_key(|listed| listed.difficulty)
+                .copied()
+                .unwrap_or(song_chart));
+
+        self.load_song(song_metadata, full_chart);
+        self.enable_dynamic_difficulty(song_chart.map(|song_chart| song_chart.difficulty).unwrap_or(Difficulty::Medium));
+    }
+
+    /// Lets the difficulty adapt to the player phrase by phrase, starting at a level
+    /// Charts without difficulty tags are tagged by thinning them out
+    pub fn enable_dynamic_difficulty(&mut self, level: Difficulty) {
+        let Some(note_events) = self.notes.as_mut() else { return };
+        if note_events.iter().all(|note_event| note_event.difficulty.is_none()) {
+            tag_difficulties(note_events);
+        }
+
+        self.dynamic_difficulty = Some(DynamicDifficulty::new(&self.phrases, level));
+    }
+
+    /// Starts the song with the given chart, from a position in seconds
+    pub fn load_chart(&mut self, song_metadata: SongMetadata, chart: Chart, start_seconds: f32) {
         self.metadata = Some(song_metadata.clone());
+        self.song_chart = None;
 
         // Set the audio filepath
-        let song_directory = get_songs_dir().unwrap().join(
-            format!("{}/audio.mp3", song_metadata.uuid)
-        );
-        self.audio_path = Some(song_directory.to_str().unwrap().to_string());
-
-        // TODO: properly load notes
-        let mut note_events = vec![
-            NoteEvent {
-                start_time_seconds: 1.0,
-                duration_seconds: 1.0,
-                string_index: 3,
-                fret_index: 9,
-            },
-            NoteEvent {
-                start_time_seconds: 2.0,
-                duration_seconds: 1.0,
-                string_index: 1,
-                fret_index: 10,
-            },
-            NoteEvent {
-                start_time_seconds: 3.0,
-                duration_seconds: 1.0,
-                string_index: 2,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 4.0,
-                duration_seconds: 1.0,
-                string_index: 3,
-                fret_index: 9,
-            },
-            NoteEvent {
-                start_time_seconds: 5.0,
-                duration_seconds: 1.0,
-                string_index: 4,
-                fret_index: 10,
-            },
-            NoteEvent {
-                start_time_seconds: 6.0,
-                duration_seconds: 1.0,
-                string_index: 5,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 7.0,
-                duration_seconds: 1.0,
-                string_index: 5,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 8.0,
-                duration_seconds: 1.0,
-                string_index: 5,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 9.0,
-                duration_seconds: 1.0,
-                string_index: 5,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 10.0,
-                duration_seconds: 1.0,
-                string_index: 5,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 11.0,
-                duration_seconds: 1.0,
-                string_index: 4,
-                fret_index: 11,
-            },
-            NoteEvent {
-                start_time_seconds: 12.0,
-                duration_seconds: 1.0,
-                string_index: 4,
-                fret_index: 8,
-            },
-            NoteEvent {
-                start_time_seconds: 13.0,
-                duration_seconds: 1.0,
-                string_index: 2,
-                fret_index: 8,
-            },
-            NoteEvent {
-                start_time_seconds: 14.0,
-                duration_seconds: 1.0,
-                string_index: 2,
-                fret_index: 8,
-            },
-            NoteEvent {
-                start_time_seconds: 15.0,
-                duration_seconds: 1.0,
-                string_index: 2,
-                fret_index: 8,
-            },
-        ];
+        let audio_path = song_metadata.audio_path().unwrap_or_else(|| get_songs_dir().unwrap().join(&song_metadata.uuid).join("audio.mp3"));
+        self.audio_path = Some(audio_path.to_str().unwrap().to_string());
+        let song_directory = get_songs_dir().unwrap().join(&song_metadata.uuid);
+        self.stems = song_metadata.stems.iter()
+            .map(|stem| (stem.kind, song_directory.join(&stem.filename)))
+            .filter(|(_, path)| path.is_file())
+            .map(|(kind, path)| (kind, path.to_str().unwrap().to_string()))
+            .collect();
 
+        self.phrases = chart.phrases_or_measures();
 
         // Sort the note events
+        let mut note_events = chart.notes;
         note_events.sort_by(|a, b| a.start_time_seconds.partial_cmp(&b.start_time_seconds).unwrap());
 
         self.notes = Some(note_events.clone());
+        self.tempo_map = chart.tempo_map;
+        self.dynamic_difficulty = None;
+
+        // The audio decides how long the song lasts, the metadata is only a fallback
+        let audio_info = song_metadata.audio_info.clone().or_else(|| AudioInfo::read_from_file(&audio_path).ok());
+        let duration_seconds = match audio_info {
+            Some(audio_info) => {
+                if (audio_info.duration_seconds - song_metadata.duration_seconds).abs() > DURATION_TOLERANCE_SECONDS {
+                    warn!("{}: the metadata says the song lasts {:.1}s but the audio lasts {:.1}s", song_metadata.title, song_metadata.duration_seconds, audio_info.duration_seconds);
+                }
+                audio_info.duration_seconds
+            }
+            None => song_metadata.duration_seconds,
+        };
+
+        let mut timer = Timer::from_seconds(duration_seconds, TimerMode::Once);
+        timer.set_elapsed(Duration::from_secs_f32(start_seconds.clamp(0.0, duration_seconds)));
 
         self.progress = Some(SongLoadedProgress {
-            timer: Timer::from_seconds(song_metadata.duration_seconds, TimerMode::Once),
+            timer,
             notes_with_score: vec![],
-            previous_onset_secs: 0.0,
+            previous_onset_secs: start_seconds,
+            score: 0.0,
         })
     }
 }
@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+
+use crate::{helpers::chart::SongChart, resources::{output_audio_song::OutputAudioControllerSong, song_editor::SongEditorResource, song_loaded::SongLoadedResource}};
+
+use super::editor::set_playing;
+
+#[derive(Component)]
+pub struct EditorChartButton;
+
+pub fn chart_button_label(editor: &SongEditorResource) -> String {
+    format!("chart: {}", editor.chart_label())
+}
+
+/// Cycles through the main chart and every arrangement and difficulty, charts that don't exist yet are created on save
+pub fn editor_chart(
+    buttons: Res<ButtonInput<MouseButton>>,
+    chart_button_query: Query<(&Interaction, &Children), With<EditorChartButton>>,
+    mut text_query: Query<&mut Text>,
+    mut editor: ResMut<SongEditorResource>,
+    song_loaded: Res<SongLoadedResource>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    mut confirm_switch: Local<bool>,
+) {
+    for (interaction, _) in chart_button_query.iter() {
+        if *interaction != Interaction::Pressed || !buttons.just_pressed(MouseButton::Left) {
+            continue
+        }
+
+        // Unsaved changes are only discarded when pressing twice
+        if editor.has_unsaved_changes && !*confirm_switch {
+            *confirm_switch = true;
+            editor.status = "unsaved changes, press again to discard them and switch charts".to_string();
+            continue
+        }
+        *confirm_switch = false;
+
+        let song_charts = SongChart::all();
+        let next_chart = match editor.song_chart {
+            None => song_charts.first().copied(),
+            Some(song_chart) => song_charts.iter().skip_while(|other| **other != song_chart).nth(1).copied(),
+        };
+        set_playing(&mut editor, &song_loaded, &output_audio_song, false);
+        editor.switch_chart(next_chart);
+        editor.status = format!("editing the {}", editor.chart_label());
+    }
+
+    // Saving a new chart also changes the label
+    for (_, children) in chart_button_query.iter() {
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                let label = chart_button_label(&editor);
+                if text.sections[0].value != label {
+                    text.sections[0].value = label;
+                }
+            }
+        }
+    }
+}
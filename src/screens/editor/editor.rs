@@ -0,0 +1,379 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{components::button_minimal::spawn_button_minimal, constants::ingame::TIMELINE_LENGTH, features::timeline::components::{beat_line::{BeatLine, BeatLineAssets}, note::Note}, helpers::{chart::Chart, notes::NoteEvent, tempo_map::{TempoChange, TempoMap}}, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource, output_audio_song::{AudioCommand, OutputAudioControllerSong}, song_editor::SongEditorResource, song_loaded::SongLoadedResource}, screens::song_play::song_play::spawn_highway, states::app_state::AppState};
+
+use super::{chart::{chart_button_label, EditorChartButton}, record::EditorRecordButton, tempo::EditorDetectTempoButton};
+
+#[derive(Component)]
+pub struct EditorMarker;
+#[derive(Component)]
+pub struct EditorBackButton;
+#[derive(Component)]
+pub struct EditorSaveButton;
+#[derive(Component)]
+pub struct EditorTestPlayButton;
+#[derive(Component)]
+pub struct EditorStatusMarker;
+
+const HELP_TEXT: &str = "click: place / select, drag: move, right click: delete, wheel: scrub\n\
+space: play, arrows: scrub (shift: beat), ctrl+arrows: move note / change string, up/down: fret, 1-6: string\n\
+enter: place note, delete: remove, -/=: duration, h p b s m x v t: techniques, l: lowest difficulty\n\
+g: grid subdivision, [ ]: bpm (shift: 10), j: time signature, o: add / remove tempo change (shift: move downbeat), k: tap tempo, r: record\n\
+ctrl+z / ctrl+y: undo / redo, ctrl+s: save, f5: test play";
+
+/// Position of a note on the highway at a point in the song
+pub fn note_position(
+    configuration: &ConfigurationResource,
+    hand_position: &HandPositionResource,
+    note_event: &NoteEvent,
+    playhead_seconds: f32,
+) -> Vec3 {
+    let y = if note_event.is_open_string() {
+        let (first_fret, last_fret) = hand_position.fret_window();
+        (configuration.fret_center(first_fret) + configuration.fret_center(last_fret)) / 2.0
+    } else {
+        configuration.fret_center(note_event.fret_index)
+    };
+
+    Vec3 {
+        x: time_to_x(configuration, note_event.start_time_seconds, playhead_seconds),
+        y,
+        z: configuration.string_center(note_event.string_index),
+    }
+}
+
+/// X coordinate on the highway of a point in the song, the fret board is at 0
+pub fn time_to_x(configuration: &ConfigurationResource, seconds: f32, playhead_seconds: f32) -> f32 {
+    -TIMELINE_LENGTH * (seconds - playhead_seconds) / configuration.approach_rate
+}
+
+/// Tempo map shown on the highway, charts without one are edited on the default grid
+fn highway_tempo_map(chart: &Chart) -> TempoMap {
+    if chart.tempo_map.is_empty() {
+        return TempoMap { changes: vec![TempoChange::default()] }
+    }
+    chart.tempo_map.clone()
+}
+
+/// Starts or pauses the song audio from the playhead
+pub fn set_playing(
+    editor: &mut SongEditorResource,
+    song_loaded: &SongLoadedResource,
+    output_audio_song: &OutputAudioControllerSong,
+    playing: bool,
+) {
+    if playing == editor.playing {
+        return
+    }
+    editor.playing = playing;
+
+    if playing {
+        if let Some(audio_path) = &song_loaded.audio_path {
+            let _ = output_audio_song.sender.send(AudioCommand::Play(audio_path.clone(), editor.playhead_seconds));
+        }
+    } else {
+        let _ = output_audio_song.sender.send(AudioCommand::Pause);
+    }
+}
+
+pub fn save_chart(editor: &mut SongEditorResource) {
+    editor.status = match editor.save() {
+        Ok(()) => "saved".to_string(),
+        Err(err) => {
+            error!("could not save chart: {}", err);
+            format!("could not save: {}", err)
+        }
+    };
+}
+
+/// Plays the edited chart from the playhead, leaving the song returns to the editor
+pub fn start_test_play(
+    editor: &mut SongEditorResource,
+    song_loaded: &mut SongLoadedResource,
+    next_state: &mut NextState<AppState>,
+) {
+    editor.playing = false;
+    song_loaded.load_chart(editor.song_metadata.clone(), editor.chart.clone(), editor.playhead_seconds);
+    song_loaded.test_play = true;
+    next_state.set(AppState::SongPlay);
+}
+
+pub fn editor_load(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut editor: ResMut<SongEditorResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    configuration: Res<ConfigurationResource>,
+) {
+    clear_color.0 = Color::srgb(0.10, 0.10, 0.10);
+
+    // The highway reads the notes and time from the loaded song
+    editor.playing = false;
+    song_loaded.load_chart(editor.song_metadata.clone(), editor.chart.clone(), editor.playhead_seconds);
+    song_loaded.tempo_map = highway_tempo_map(&editor.chart);
+    song_loaded.test_play = false;
+
+    // The highway of the editor starts at the first frets, not where the last song left the hand
+    commands.insert_resource(HandPositionResource::default());
+    commands.insert_resource(BeatLineAssets::new(&configuration, &mut meshes, &mut materials));
+
+    // Content
+    commands.spawn((
+        SpatialBundle {
+            ..Default::default()
+        }, EditorMarker
+    )).with_children(|builder| {
+        spawn_highway(builder, &asset_server, &mut meshes, &mut materials, &configuration);
+    });
+
+    // UI
+    commands.spawn((Camera2dBundle {
+        camera: Camera {
+            order: 0,
+            ..Default::default()
+        },
+        ..Default::default()
+    }, EditorMarker));
+    commands.spawn((NodeBundle {
+        style: Style {
+            width: Val::Percent(100.),
+            height: Val::Percent(100.),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::SpaceBetween,
+            ..Default::default()
+        },
+        ..Default::default()
+    }, EditorMarker))
+        .with_children(|builder| {
+
+            // Header
+            builder.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Px(60.0),
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }).with_children(|builder| {
+                // Back button
+                spawn_button_minimal(
+                    builder,
+                    &asset_server,
+                    "< back to song select",
+                    EditorBackButton
+                );
+
+                // Title
+                builder.spawn(TextBundle::from_section(
+                    format!(
+                        "editing: {} - {}",
+                        editor.song_metadata.artists.join(", "),
+                        editor.song_metadata.title.clone()
+                    ),
+                    TextStyle {
+                        font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                        font_size: 18.0,
+                        color: Color::WHITE,
+                    },
+                ));
+
+                builder.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }).with_children(|builder| {
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &chart_button_label(&editor),
+                        EditorChartButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "record",
+                        EditorRecordButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "detect tempo",
+                        EditorDetectTempoButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "save",
+                        EditorSaveButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "test play",
+                        EditorTestPlayButton
+                    );
+                });
+            });
+
+            // Footer
+            builder.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(8.0),
+                    padding: UiRect::all(Val::Px(12.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }).with_children(|builder| {
+                builder.spawn((TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/IBMPlexMono-Medium.ttf"),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ), EditorStatusMarker));
+
+                builder.spawn(TextBundle::from_section(
+                    HELP_TEXT,
+                    TextStyle {
+                        font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                        font_size: 12.0,
+                        color: Color::srgb(0.6, 0.6, 0.6),
+                    },
+                ));
+            });
+        });
+}
+
+fn status_label(editor: &SongEditorResource) -> String {
+    let selected = match editor.selected_note() {
+        Some(note_event) => format!(
+            "selected: string {} fret {} {:.2}s{}",
+            note_event.string_index + 1,
+            note_event.fret_index,
+            note_event.duration_seconds,
+            note_event.difficulty.map(|difficulty| format!(" from {}", difficulty.name())).unwrap_or_default(),
+        ),
+        None => "nothing selected".to_string(),
+    };
+
+    let tempo_change = editor.chart.tempo_map.change_at(editor.playhead_seconds);
+
+    format!(
+        "{:.2} / {:.2}s   bpm {:.1} {}  grid 1/{}   string {} ({})  fret {}   {}   {} notes{}   {}",
+        editor.playhead_seconds,
+        editor.song_metadata.audio_duration_seconds(),
+        tempo_change.bpm,
+        tempo_change.time_signature(),
+        editor.grid_subdivision,
+        editor.string_index + 1,
+        editor.song_metadata.tuning[editor.string_index],
+        editor.fret_index,
+        selected,
+        editor.chart.notes.len(),
+        if editor.has_unsaved_changes { " (unsaved)" } else { "" },
+        editor.status,
+    )
+}
+
+pub fn editor_update(
+    mut commands: Commands,
+    time: Res<Time>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    back_button_query_interaction: Query<&Interaction, With<EditorBackButton>>,
+    save_button_query_interaction: Query<&Interaction, With<EditorSaveButton>>,
+    test_play_button_query_interaction: Query<&Interaction, With<EditorTestPlayButton>>,
+    mut status_query: Query<&mut Text, With<EditorStatusMarker>>,
+    notes_query: Query<(Entity, &Note)>,
+    beat_lines_query: Query<Entity, With<BeatLine>>,
+    mut editor: ResMut<SongEditorResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    mut next_state: ResMut<NextState<AppState>>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    configuration: Res<ConfigurationResource>,
+    mut synced_revision: Local<u64>,
+    mut confirm_back: Local<bool>,
+) {
+    for interaction in back_button_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            // Unsaved changes are only discarded when pressing back twice
+            if editor.has_unsaved_changes && !*confirm_back {
+                *confirm_back = true;
+                editor.status = "unsaved changes, press back again to discard them".to_string();
+            } else {
+                *confirm_back = false;
+                commands.remove_resource::<SongEditorResource>();
+                next_state.set(AppState::SongSelect);
+                return
+            }
+        }
+    }
+    for interaction in save_button_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            save_chart(&mut editor);
+        }
+    }
+    for interaction in test_play_button_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            start_test_play(&mut editor, &mut song_loaded, &mut next_state);
+            return
+        }
+    }
+
+    // Playback
+    if editor.playing {
+        editor.playhead_seconds += time.delta_seconds();
+        if editor.playhead_seconds >= editor.song_metadata.audio_duration_seconds() {
+            editor.playhead_seconds = editor.song_metadata.audio_duration_seconds();
+            set_playing(&mut editor, &song_loaded, &output_audio_song, false);
+        }
+    }
+
+    // Keep the highway in sync with the chart and the playhead
+    let chart_changed = *synced_revision != editor.revision;
+    if chart_changed {
+        *synced_revision = editor.revision;
+        song_loaded.notes = Some(editor.chart.notes.clone());
+        song_loaded.tempo_map = highway_tempo_map(&editor.chart);
+    }
+    if let Some(song_progress) = song_loaded.progress.as_mut() {
+        song_progress.timer.set_elapsed(Duration::from_secs_f32(editor.playhead_seconds.max(0.0)));
+    }
+    for (entity, note) in notes_query.iter() {
+        // Edited notes are respawned, and notes are removed when scrubbing back past their approach
+        if chart_changed || note.note_event.start_time_seconds - configuration.approach_rate > editor.playhead_seconds {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    if chart_changed {
+        for entity in beat_lines_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for mut text in status_query.iter_mut() {
+        text.sections[0].value = status_label(&editor);
+    }
+}
+
+pub fn editor_cleanup(
+    mut commands: Commands,
+    query: Query<Entity, With<EditorMarker>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,42 @@
+use bevy::prelude::*;
+
+use crate::{constants::ingame::{FRET_AMOUNT, STRING_COLORS}, helpers::{notes::NoteEvent, tempo_map::GridLineKind}, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource, song_editor::SongEditorResource}};
+
+use super::editor::{note_position, time_to_x};
+
+/// Draws the grid subdivisions, the selected note and the note that would be placed under the pointer
+pub fn editor_grid(
+    mut gizmos: Gizmos,
+    editor: Res<SongEditorResource>,
+    configuration: Res<ConfigurationResource>,
+    hand_position: Res<HandPositionResource>,
+) {
+    let fret_direction = configuration.fret_direction();
+    let grid_start = configuration.fret_center(1) - 0.6 * fret_direction;
+    let grid_end = configuration.fret_center(FRET_AMOUNT) + 0.6 * fret_direction;
+
+    // Subdivisions between the beat lines of the highway, from the playhead to the end of the approach
+    let grid_lines = editor.chart.tempo_map.grid_lines(editor.playhead_seconds, editor.playhead_seconds + configuration.approach_rate, editor.grid_subdivision);
+    for grid_line in grid_lines.iter().filter(|grid_line| grid_line.kind == GridLineKind::Subdivision) {
+        let x = time_to_x(&configuration, grid_line.seconds, editor.playhead_seconds);
+        gizmos.line(Vec3::new(x, grid_start, 0.0), Vec3::new(x, grid_end, 0.0), Color::srgba(1.0, 1.0, 1.0, 0.12));
+    }
+
+    // Selected note
+    if let Some(note_event) = editor.selected_note() {
+        let position = note_position(&configuration, &hand_position, note_event, editor.playhead_seconds);
+        gizmos.cuboid(Transform::from_translation(position).with_scale(Vec3::new(0.8, 1.3, 0.5)), Color::WHITE);
+    }
+
+    // Placement preview
+    if let Some((seconds, fret_index)) = editor.hover {
+        let note_event = NoteEvent {
+            start_time_seconds: seconds,
+            string_index: editor.string_index,
+            fret_index,
+            ..Default::default()
+        };
+        let position = note_position(&configuration, &hand_position, &note_event, editor.playhead_seconds);
+        gizmos.cuboid(Transform::from_translation(position).with_scale(Vec3::new(0.6, 1.1, 0.3)), STRING_COLORS[editor.string_index].with_alpha(0.6));
+    }
+}
@@ -0,0 +1,305 @@
+use bevy::{input::mouse::MouseWheel, prelude::*, window::PrimaryWindow};
+
+use crate::{constants::ingame::{FRET_AMOUNT, FRET_CENTERS, TIMELINE_LENGTH}, helpers::{chart::Difficulty, notes::{Bend, Legato}, tempo_map::TempoChange}, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource, output_audio_song::OutputAudioControllerSong, song_editor::{SongEditorResource, GRID_SUBDIVISIONS}, song_loaded::SongLoadedResource}, screens::song_play::camera::Camera3DMarker, states::app_state::AppState};
+
+use super::editor::{note_position, save_chart, set_playing, start_test_play};
+
+// Distance in pixels within which a click hits a note
+const NOTE_HIT_RADIUS: f32 = 18.0;
+// Time signatures the time signature key cycles through
+const TIME_SIGNATURES: [(u32, u32); 7] = [(4, 4), (3, 4), (2, 4), (5, 4), (6, 8), (7, 8), (12, 8)];
+// Bend targets in semitones the bend key cycles through
+const BEND_STEPS: [f32; 3] = [0.5, 1.0, 2.0];
+
+/// Fret under a point on the highway, 0 when the point is before the first fret
+fn fret_at(configuration: &ConfigurationResource, y: f32) -> usize {
+    let y = y * configuration.fret_direction();
+    let first_fret_start = FRET_CENTERS[0] - (FRET_CENTERS[1] - FRET_CENTERS[0]) / 2.0;
+    if y < first_fret_start {
+        return 0
+    }
+
+    (1..=FRET_AMOUNT)
+        .min_by(|&a, &b| (FRET_CENTERS[a - 1] - y).abs().partial_cmp(&(FRET_CENTERS[b - 1] - y).abs()).unwrap())
+        .unwrap_or(1)
+}
+
+pub fn editor_keyboard(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut editor: ResMut<SongEditorResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    mut next_state: ResMut<NextState<AppState>>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+
+    if ctrl {
+        if keys.just_pressed(KeyCode::KeyZ) && !shift {
+            editor.undo();
+        }
+        if keys.just_pressed(KeyCode::KeyY) || (keys.just_pressed(KeyCode::KeyZ) && shift) {
+            editor.redo();
+        }
+        if keys.just_pressed(KeyCode::KeyS) {
+            save_chart(&mut editor);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::F5) {
+        start_test_play(&mut editor, &mut song_loaded, &mut next_state);
+        return
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        let playing = !editor.playing;
+        set_playing(&mut editor, &song_loaded, &output_audio_song, playing);
+    }
+
+    // Scrubbing, a beat at a time with shift
+    let scrub_steps = if shift { editor.grid_subdivision as i32 } else { 1 };
+    for (key, direction) in [(KeyCode::ArrowRight, 1), (KeyCode::ArrowLeft, -1)] {
+        if !keys.just_pressed(key) {
+            continue
+        }
+
+        if ctrl {
+            // Move the selected note along the grid
+            let step = editor.grid_step_seconds() * direction as f32;
+            editor.edit_selected(true, |note_event| note_event.start_time_seconds += step);
+        } else {
+            set_playing(&mut editor, &song_loaded, &output_audio_song, false);
+            editor.move_playhead(scrub_steps * direction);
+        }
+    }
+
+    // String and fret of the cursor, which also apply to the selected note
+    for (key, direction) in [(KeyCode::ArrowUp, 1), (KeyCode::ArrowDown, -1)] {
+        if !keys.just_pressed(key) {
+            continue
+        }
+
+        if ctrl {
+            let string_index = (editor.string_index as i32 + direction).clamp(0, 5) as usize;
+            editor.string_index = string_index;
+            editor.edit_selected(true, |note_event| note_event.string_index = string_index);
+        } else {
+            let fret_index = (editor.fret_index as i32 + direction).clamp(0, FRET_AMOUNT as i32) as usize;
+            editor.fret_index = fret_index;
+            editor.edit_selected(true, |note_event| note_event.fret_index = fret_index);
+        }
+    }
+    for (string_index, key) in [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5, KeyCode::Digit6].into_iter().enumerate() {
+        if keys.just_pressed(key) {
+            editor.string_index = string_index;
+            editor.edit_selected(true, |note_event| note_event.string_index = string_index);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Enter) {
+        let playhead_seconds = editor.playhead_seconds;
+        editor.place_note(playhead_seconds);
+    }
+    if keys.any_just_pressed([KeyCode::Delete, KeyCode::Backspace]) {
+        editor.delete_selected();
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        editor.selected = None;
+    }
+
+    // Duration
+    let grid_step_seconds = editor.grid_step_seconds();
+    if keys.just_pressed(KeyCode::Equal) {
+        editor.edit_selected(true, |note_event| note_event.duration_seconds += grid_step_seconds);
+    }
+    if keys.just_pressed(KeyCode::Minus) {
+        editor.edit_selected(true, |note_event| note_event.duration_seconds = (note_event.duration_seconds - grid_step_seconds).max(grid_step_seconds));
+    }
+
+    // Playing techniques
+    if !ctrl {
+        if keys.just_pressed(KeyCode::KeyH) {
+            editor.edit_selected(true, |note_event| note_event.legato = if note_event.legato == Some(Legato::HammerOn) { None } else { Some(Legato::HammerOn) });
+        }
+        if keys.just_pressed(KeyCode::KeyP) {
+            editor.edit_selected(true, |note_event| note_event.legato = if note_event.legato == Some(Legato::PullOff) { None } else { Some(Legato::PullOff) });
+        }
+        if keys.just_pressed(KeyCode::KeyB) {
+            editor.edit_selected(true, |note_event| {
+                let next_step = match &note_event.bend {
+                    None => Some(0),
+                    Some(bend) => BEND_STEPS.iter().position(|&semitones| semitones > bend.semitones),
+                };
+                note_event.bend = next_step.map(|step| Bend { semitones: BEND_STEPS[step], curve: vec![] });
+            });
+        }
+        if keys.just_pressed(KeyCode::KeyS) {
+            // Cycles between sliding up, sliding down and no slide
+            editor.edit_selected(true, |note_event| {
+                let fret_index = note_event.fret_index;
+                note_event.slide_to_fret = match note_event.slide_to_fret {
+                    None => Some((fret_index + 2).min(FRET_AMOUNT)),
+                    Some(slide_to_fret) if slide_to_fret > fret_index && fret_index > 1 => Some(fret_index.saturating_sub(2).max(1)),
+                    Some(_) => None,
+                };
+            });
+        }
+        if keys.just_pressed(KeyCode::KeyM) {
+            editor.edit_selected(true, |note_event| note_event.palm_mute = !note_event.palm_mute);
+        }
+        if keys.just_pressed(KeyCode::KeyX) {
+            editor.edit_selected(true, |note_event| note_event.harmonic = !note_event.harmonic);
+        }
+        if keys.just_pressed(KeyCode::KeyV) {
+            editor.edit_selected(true, |note_event| note_event.vibrato = !note_event.vibrato);
+        }
+        if keys.just_pressed(KeyCode::KeyT) {
+            editor.edit_selected(true, |note_event| note_event.tremolo = !note_event.tremolo);
+        }
+        if keys.just_pressed(KeyCode::KeyL) {
+            // Cycles the lowest difficulty the note is played at, from untagged through easy to expert
+            editor.edit_selected(true, |note_event| note_event.difficulty = match note_event.difficulty {
+                None => Some(Difficulty::Easy),
+                Some(Difficulty::Expert) => None,
+                Some(difficulty) => Some(difficulty.harder()),
+            });
+        }
+    }
+
+    // Grid
+    if keys.just_pressed(KeyCode::KeyG) {
+        let index = GRID_SUBDIVISIONS.iter().position(|&subdivision| subdivision == editor.grid_subdivision).unwrap_or(0);
+        editor.grid_subdivision = GRID_SUBDIVISIONS[(index + 1) % GRID_SUBDIVISIONS.len()];
+    }
+
+    // Tempo map, edited at the playhead
+    let playhead_seconds = editor.playhead_seconds;
+    let bpm_step = if shift { 10.0 } else { 1.0 };
+    for (key, direction) in [(KeyCode::BracketRight, 1.0), (KeyCode::BracketLeft, -1.0)] {
+        if keys.just_pressed(key) {
+            editor.edit_tempo_map(|tempo_map| tempo_map.edit_change_at(playhead_seconds, |change| {
+                change.bpm = (change.bpm.round() + bpm_step * direction).clamp(20.0, 400.0);
+            }));
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyJ) {
+        editor.edit_tempo_map(|tempo_map| tempo_map.edit_change_at(playhead_seconds, |change| {
+            let index = TIME_SIGNATURES.iter().position(|&signature| signature == (change.beats_per_measure, change.beat_unit)).unwrap_or(0);
+            (change.beats_per_measure, change.beat_unit) = TIME_SIGNATURES[(index + 1) % TIME_SIGNATURES.len()];
+        }));
+    }
+    if keys.just_pressed(KeyCode::KeyO) {
+        if shift {
+            // Moves the downbeat of the section to the playhead
+            editor.edit_tempo_map(|tempo_map| tempo_map.edit_change_at(playhead_seconds, |change| change.start_seconds = playhead_seconds));
+        } else {
+            // Starts a new section at the playhead, or removes the section that starts there
+            let change = editor.chart.tempo_map.change_at(playhead_seconds);
+            editor.edit_tempo_map(|tempo_map| {
+                if !tempo_map.is_empty() && (change.start_seconds - playhead_seconds).abs() < 0.001 {
+                    tempo_map.remove_change(change.start_seconds);
+                } else {
+                    if tempo_map.is_empty() {
+                        tempo_map.set_change(change.clone());
+                    }
+                    tempo_map.set_change(TempoChange { start_seconds: playhead_seconds, ..change });
+                }
+            });
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct EditorDrag {
+    active: bool,
+}
+
+pub fn editor_mouse(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera3DMarker>>,
+    interaction_query: Query<&Interaction>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut editor: ResMut<SongEditorResource>,
+    song_loaded: Res<SongLoadedResource>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    configuration: Res<ConfigurationResource>,
+    hand_position: Res<HandPositionResource>,
+    mut drag: Local<EditorDrag>,
+) {
+    // Scrubbing
+    let scroll: f32 = mouse_wheel_events.read().map(|event| event.y.signum()).sum();
+    if scroll != 0.0 {
+        set_playing(&mut editor, &song_loaded, &output_audio_song, false);
+        editor.move_playhead(scroll as i32);
+    }
+
+    let (Ok(window), Ok((camera, camera_transform))) = (windows.get_single(), camera_query.get_single()) else { return };
+    let Some(cursor_position) = window.cursor_position() else {
+        editor.hover = None;
+        return
+    };
+
+    // Point under the pointer on the plane of the string notes are placed on
+    let string_center = configuration.string_center(editor.string_index);
+    let hover = camera.viewport_to_world(camera_transform, cursor_position).and_then(|ray| {
+        let distance = ray.intersect_plane(Vec3::new(0.0, 0.0, string_center), InfinitePlane3d::new(Vec3::Z))?;
+        let point = ray.get_point(distance);
+        let seconds = editor.playhead_seconds - point.x * configuration.approach_rate / TIMELINE_LENGTH;
+
+        Some((editor.snap(seconds), fret_at(&configuration, point.y)))
+    });
+    if editor.hover != hover {
+        editor.hover = hover;
+    }
+
+    if buttons.just_released(MouseButton::Left) {
+        drag.active = false;
+    }
+
+    // Clicks on the UI are left to the buttons
+    if interaction_query.iter().any(|interaction| *interaction != Interaction::None) {
+        return
+    }
+
+    // Note closest to the pointer on screen
+    let hit = editor.chart.notes.iter().enumerate()
+        .filter_map(|(index, note_event)| {
+            let position = note_position(&configuration, &hand_position, note_event, editor.playhead_seconds);
+            let screen_position = camera.world_to_viewport(camera_transform, position)?;
+            Some((index, screen_position.distance(cursor_position)))
+        })
+        .filter(|(_, distance)| *distance <= NOTE_HIT_RADIUS)
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(index, _)| index);
+
+    if buttons.just_pressed(MouseButton::Left) {
+        if let Some(index) = hit {
+            editor.selected = Some(index);
+            editor.string_index = editor.chart.notes[index].string_index;
+            editor.fret_index = editor.chart.notes[index].fret_index;
+            editor.begin_drag();
+            drag.active = true;
+        } else if let Some((seconds, fret_index)) = hover {
+            editor.fret_index = fret_index;
+            editor.place_note(seconds);
+        }
+    } else if buttons.pressed(MouseButton::Left) && drag.active {
+        // Dragging moves the selected note along the grid and across frets
+        if let (Some((seconds, fret_index)), Some(note_event)) = (hover, editor.selected_note()) {
+            if note_event.start_time_seconds != seconds || note_event.fret_index != fret_index {
+                editor.fret_index = fret_index;
+                editor.edit_selected(false, |note_event| {
+                    note_event.start_time_seconds = seconds;
+                    note_event.fret_index = fret_index;
+                });
+            }
+        }
+    }
+
+    if buttons.just_pressed(MouseButton::Right) {
+        if let Some(index) = hit {
+            editor.selected = Some(index);
+            editor.delete_selected();
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+
+use crate::{features::timeline::{components::{beat_line::update_beat_lines, note::update_note}, timeline::update_timeline}, resources::output_audio_song::{output_audio_song_cleanup, output_audio_song_load}, screens::song_play::camera::camera_system, states::app_state::AppState};
+
+use super::{chart::editor_chart, editor::{editor_cleanup, editor_load, editor_update}, grid::editor_grid, input::{editor_keyboard, editor_mouse}, record::editor_record, tempo::editor_tempo};
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::Editor), output_audio_song_load);
+        app.add_systems(OnEnter(AppState::Editor), editor_load.after(output_audio_song_load));
+
+        app.add_systems(Update, editor_keyboard.run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_mouse.run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_record.after(editor_keyboard).after(editor_mouse).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_tempo.after(editor_keyboard).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_chart.after(editor_keyboard).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_update.after(editor_record).after(editor_tempo).after(editor_chart).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, update_timeline.after(editor_update).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, update_note.after(editor_update).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, update_beat_lines.after(editor_update).run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, camera_system.run_if(in_state(AppState::Editor)));
+        app.add_systems(Update, editor_grid.after(editor_update).run_if(in_state(AppState::Editor)));
+
+        app.add_systems(OnExit(AppState::Editor), editor_cleanup);
+        app.add_systems(OnExit(AppState::Editor), output_audio_song_cleanup);
+    }
+}
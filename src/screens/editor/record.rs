@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+
+use crate::{helpers::{fingering::tuning_pitches, input_device::AudioStream, recording::Recording, tuning::STANDARD_TUNING}, resources::{configuration::ConfigurationResource, input_device::InputDeviceResource, output_audio_song::OutputAudioControllerSong, song_editor::SongEditorResource, song_loaded::SongLoadedResource}};
+
+use super::editor::set_playing;
+
+#[derive(Component)]
+pub struct EditorRecordButton;
+
+// Time between playing a note and detecting it, roughly the length of the input buffer
+const RECORD_LATENCY_SECONDS: f32 = 0.05;
+
+fn record_label(recording: bool) -> &'static str {
+    if recording { "stop recording" } else { "record" }
+}
+
+fn start_recording(
+    editor: &mut SongEditorResource,
+    song_loaded: &SongLoadedResource,
+    output_audio_song: &OutputAudioControllerSong,
+    input_device: &mut InputDeviceResource,
+    configuration: &ConfigurationResource,
+) {
+    if input_device.audio_stream_main.is_none() {
+        let Some(device) = configuration.device.clone() else {
+            editor.status = "select an input device to record".to_string();
+            return
+        };
+        match AudioStream::new(device, configuration.selected_device_channels.clone(), 1024) {
+            Ok(audio_stream) => input_device.audio_stream_main = Some(audio_stream),
+            Err(err) => {
+                error!("could not open the input device: {}", err);
+                editor.status = format!("could not open the input device: {}", err);
+                return
+            }
+        }
+    }
+
+    set_playing(editor, song_loaded, output_audio_song, false);
+    editor.recording = Some(Recording::new(editor.playhead_seconds));
+    editor.status = "recording, play along".to_string();
+    set_playing(editor, song_loaded, output_audio_song, true);
+}
+
+/// Turns a finished recording into notes, replacing the notes in the recorded range
+fn finish_recording(editor: &mut SongEditorResource, recording: Recording) {
+    let tuning = tuning_pitches(&editor.song_metadata.tuning)
+        .or_else(|| tuning_pitches(&STANDARD_TUNING.map(|note| note.to_string())))
+        .unwrap();
+
+    let note_events = recording.to_note_events(&tuning, |seconds| editor.snap(seconds), editor.grid_step_seconds());
+    editor.status = format!("recorded {} notes", note_events.len());
+    editor.replace_range(editor.snap(recording.start_seconds), recording.last_seconds, note_events);
+}
+
+pub fn editor_record(
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    record_button_query: Query<(&Interaction, &Children), With<EditorRecordButton>>,
+    mut text_query: Query<&mut Text>,
+    mut editor: ResMut<SongEditorResource>,
+    song_loaded: Res<SongLoadedResource>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    mut input_device: ResMut<InputDeviceResource>,
+    configuration: Res<ConfigurationResource>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let mut toggle = keys.just_pressed(KeyCode::KeyR) && !ctrl;
+    for (interaction, _) in record_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            toggle = true;
+        }
+    }
+
+    if toggle {
+        if editor.recording.is_some() {
+            set_playing(&mut editor, &song_loaded, &output_audio_song, false);
+        } else {
+            start_recording(&mut editor, &song_loaded, &output_audio_song, &mut input_device, &configuration);
+        }
+    }
+
+    // Analyse the performance while the song plays, any way of stopping playback ends the recording
+    if editor.playing {
+        let seconds = editor.playhead_seconds - RECORD_LATENCY_SECONDS;
+        if let (Some(recording), Some(audio_stream)) = (editor.recording.as_mut(), &input_device.audio_stream_main) {
+            let has_onset = audio_stream.get_onset().unwrap_or(false);
+            recording.update(seconds, has_onset, audio_stream.get_pitch());
+        }
+    } else if let Some(recording) = editor.recording.take() {
+        finish_recording(&mut editor, recording);
+    }
+
+    for (_, children) in record_button_query.iter() {
+        for &child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(child) {
+                let label = record_label(editor.recording.is_some());
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.to_string();
+                }
+            }
+        }
+    }
+}
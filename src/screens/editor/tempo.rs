@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+
+use crate::resources::song_editor::SongEditorResource;
+
+#[derive(Component)]
+pub struct EditorDetectTempoButton;
+
+/// Tap tempo along with the song on K, and estimating the tempo map from the audio
+pub fn editor_tempo(
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    detect_tempo_button_query_interaction: Query<&Interaction, With<EditorDetectTempoButton>>,
+    mut editor: ResMut<SongEditorResource>,
+) {
+    if keys.just_pressed(KeyCode::KeyK) {
+        let seconds = editor.playhead_seconds;
+        editor.tap_tempo(seconds);
+    }
+
+    for interaction in detect_tempo_button_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            if let Err(err) = editor.detect_tempo() {
+                error!("could not detect the tempo: {}", err);
+                editor.status = format!("could not detect the tempo: {}", err);
+            }
+        }
+    }
+
+    // Only touches the editor when the estimate is done, so it isn't marked as changed every frame
+    if editor.bypass_change_detection().apply_detected_tempo() {
+        editor.set_changed();
+    }
+}
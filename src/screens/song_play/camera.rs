@@ -1,11 +1,12 @@
 use bevy::prelude::*;
 
-use crate::{constants::ingame::{CAMERA_Y_RANGE, FRET_AMOUNT}, features::timeline::components::note::Note};
+use crate::{constants::ingame::{CAMERA_Y_RANGE, FRET_AMOUNT}, features::timeline::components::note::Note, resources::{configuration::ConfigurationResource, hand_position::HandPositionResource}};
 
 #[derive(Component)]
 pub struct Camera3DMarker;
 
 pub fn spawn_camera(
+    configuration: &Res<ConfigurationResource>,
     builder: &mut ChildBuilder,
     starting_position: Vec3,
 ) {
@@ -18,8 +19,8 @@ pub fn spawn_camera(
             ..Default::default()
         }),
         transform: Transform {
-            translation: starting_position,
-            rotation: Quat { w: 0.386419,  x: 0.223099, y: 0.447467, z: 0.775036 },
+            translation: starting_position * Vec3::new(1.0, configuration.fret_direction(), 1.0),
+            rotation: configuration.mirror_rotation(Quat { w: 0.386419,  x: 0.223099, y: 0.447467, z: 0.775036 }),
             ..Default::default()
         },
         camera: Camera {
@@ -36,19 +37,27 @@ pub fn camera_system(
         Query<&Note, With<Note>>
     )>,
     time: Res<Time>,
+    configuration: Res<ConfigurationResource>,
+    mut hand_position: ResMut<HandPositionResource>,
 ) {
     let mut song_note_total_fret_index = 0;
     let mut song_note_amount = 0;
     for note in set.p1().iter() {
+        // Open strings can be played from any hand position
+        if note.note_event.is_open_string() {
+            continue
+        }
+
         song_note_amount += 1;
         song_note_total_fret_index += note.note_event.fret_index;
     }
     if song_note_amount > 0 {
         let song_note_average_fret_index = song_note_total_fret_index / song_note_amount;
+        hand_position.fret = song_note_total_fret_index as f32 / song_note_amount as f32;
     
         /*
         Map the fret index to a camera Z position using linear interploration
-        - source_min = 0 (fret index 0)
+        - source_min = 0 (fret 1)
         - source_max = FRET_AMOUNT
         - target_min = CAMERA_Y_RANGE[0]
         - target_max = CAMERA_Y_RANGE[1]
@@ -56,7 +65,7 @@ pub fn camera_system(
         formula:
             mapped_value = target_min + (value - source_min / source_max - source_min) * (target_max - target_min)
         */
-        let new_y = CAMERA_Y_RANGE[0] + (song_note_average_fret_index as f32 / FRET_AMOUNT as f32) * (CAMERA_Y_RANGE[1] - CAMERA_Y_RANGE[0]);
+        let new_y = (CAMERA_Y_RANGE[0] + ((song_note_average_fret_index - 1) as f32 / FRET_AMOUNT as f32) * (CAMERA_Y_RANGE[1] - CAMERA_Y_RANGE[0])) * configuration.fret_direction();
         
         for mut transform in set.p0().iter_mut() {
             transform.translation.y = transform.translation.y + time.delta_seconds() * (new_y - transform.translation.y);
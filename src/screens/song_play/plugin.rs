@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{features::timeline::{components::note::update_note, timeline::update_timeline}, resources::output_audio_song::{output_audio_song_cleanup, output_audio_song_load}, states::app_state::AppState};
+use crate::{features::timeline::{components::{beat_line::update_beat_lines, note::update_note, pitch_track::update_pitch_track, sustain::update_sustain}, timeline::update_timeline}, resources::{guitar_stem_fade::{guitar_stem_fade_update, GuitarStemFadeResource}, output_audio_song::{output_audio_song_cleanup, output_audio_song_load}, play_history::play_history_record}, states::app_state::AppState};
 
 use super::{camera::camera_system, song_play::{song_play_cleanup, song_play_load, song_play_update}};
 pub struct SongPlayPlugin;
@@ -13,9 +13,14 @@ impl Plugin for SongPlayPlugin {
         app.add_systems(Update, song_play_update.run_if(in_state(AppState::SongPlay)));
         app.add_systems(Update, update_timeline.run_if(in_state(AppState::SongPlay)));
         app.add_systems(Update, update_note.run_if(in_state(AppState::SongPlay)));
+        app.add_systems(Update, update_beat_lines.run_if(in_state(AppState::SongPlay)));
+        app.add_systems(Update, update_pitch_track.after(song_play_update).run_if(in_state(AppState::SongPlay)));
+        app.add_systems(Update, update_sustain.after(song_play_update).run_if(in_state(AppState::SongPlay)));
         app.add_systems(Update, camera_system.run_if(in_state(AppState::SongPlay)));
+        app.add_systems(Update, guitar_stem_fade_update.after(song_play_update).run_if(in_state(AppState::SongPlay).and_then(resource_exists::<GuitarStemFadeResource>)));
 
         app.add_systems(OnExit(AppState::SongPlay), song_play_cleanup);
+        app.add_systems(OnExit(AppState::SongPlay), play_history_record);
         app.add_systems(OnExit(AppState::SongPlay), output_audio_song_cleanup);
     }
 }
@@ -2,7 +2,7 @@ use bevy::prelude::*;
 use bevy_mod_billboard::{prelude::*, BillboardLockAxis};
 
 
-use crate::{components::button_minimal::spawn_button_minimal, constants::ingame::{CAMERA_Y_RANGE, FRET_AMOUNT, FRET_CENTERS}, features::timeline::{components::note::{Note, NoteTriggeredEvent}, timeline::spawn_timeline}, helpers::input_device::AudioStream, resources::{configuration::ConfigurationResource, input_device::InputDeviceResource, output_audio_song::{AudioCommand, OutputAudioControllerSong}, song_loaded::SongLoadedResource}, states::app_state::AppState};
+use crate::{components::button_minimal::spawn_button_minimal, constants::ingame::{CAMERA_Y_RANGE, FRET_AMOUNT}, features::timeline::{components::{beat_line::BeatLineAssets, note::{Note, NoteTriggeredEvent}, pitch_track::PitchCurveAssets}, timeline::spawn_timeline}, helpers::{input_device::AudioStream, stems::{GuitarStemMode, StemKind}, tuning::note_from_pitch}, resources::{configuration::ConfigurationResource, guitar_stem_fade::GuitarStemFadeResource, input_device::InputDeviceResource, hand_position::HandPositionResource, output_audio_song::{AudioCommand, OutputAudioControllerSong, StemPlayback}, song_loaded::SongLoadedResource}, states::app_state::AppState};
 
 use super::camera::spawn_camera;
 
@@ -15,6 +15,80 @@ pub struct BackButtonMarker;
 pub struct SecondsPassedMarker;
 #[derive(Component)]
 pub struct DebugOnsetMarker;
+#[derive(Component)]
+pub struct ScoreMarker;
+
+/// The 3D scene of the song: camera, guitar neck, timeline and fret labels
+pub fn spawn_highway(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    configuration: &Res<ConfigurationResource>,
+) {
+    // 3D camera
+    spawn_camera(configuration, builder, Vec3 { x: 8.5, y: CAMERA_Y_RANGE[0], z: 10.6 });
+
+    // Guitar neck model
+    // The strings in the model are evenly spaced, so only the fret direction needs mirroring
+    builder.spawn(SceneBundle {
+        scene: asset_server.load("models/ingame/guitar_neck.glb#Scene0"),
+        transform: Transform {
+            rotation: Quat::from_rotation_x(90_f32.to_radians()),
+            scale: Vec3::new(1.0, 1.0, configuration.fret_direction()),
+            ..Default::default()
+        },
+        ..default()
+    });
+
+    // Song timeline
+    spawn_timeline(configuration, builder, meshes, materials);
+
+    let font = asset_server.load("fonts/IBMPlexMono-Regular.ttf");
+
+    for fret_index in 1..=FRET_AMOUNT {
+        builder.spawn((BillboardTextBundle {
+            transform: Transform {
+                translation: Vec3 { x: 0.0, y: configuration.fret_center(fret_index), z: -0.35 },
+                rotation: configuration.mirror_rotation(Quat { w: 0.5, x: 0.5, y: -0.5, z: -0.5 }),
+                scale: Vec3::splat(0.0085),
+                ..Default::default()
+            },
+            text: Text::from_sections([
+                TextSection {
+                    value: format!("{}", fret_index),
+                    style: TextStyle {
+                        font_size: 28.0,
+                        font: font.clone(),
+                        color: Color::WHITE,
+                    },
+                },
+            ])
+            .with_justify(JustifyText::Center),
+            ..default()
+        }, BillboardLockAxis{
+            rotation: true,
+            ..default()
+        }));
+    }
+
+    // Directional 'sun' light
+    builder.spawn(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: light_consts::lux::AMBIENT_DAYLIGHT * 1.5,
+            shadows_enabled: false,
+            shadow_depth_bias: 0.3,
+            shadow_normal_bias: 0.3,
+            ..default()
+        },
+        transform: Transform {
+            translation: Vec3::new(0.0, 0.0, 0.0),
+            rotation: configuration.mirror_rotation(Quat::from_euler(EulerRot::XYZ, (-16_f32).to_radians(), 26_f32.to_radians(), 0.0)),
+            ..default()
+        },
+        ..default()
+    });
+}
 
 pub fn song_play_load(
     mut commands: Commands,
@@ -32,72 +106,18 @@ pub fn song_play_load(
 
     clear_color.0 = Color::srgb(0.10, 0.10, 0.10);
 
+    // Inserted on entering rather than at startup, so each song starts with the hand at the first frets
+    commands.insert_resource(HandPositionResource::default());
+    commands.insert_resource(PitchCurveAssets::new(&mut meshes, &mut materials));
+    commands.insert_resource(BeatLineAssets::new(&configuration, &mut meshes, &mut materials));
+
     // Content
     commands.spawn((
         SpatialBundle {
             ..Default::default()
         }, SongPlayMarker
     )).with_children(|builder| {
-        // 3D camera
-        spawn_camera(builder, Vec3 { x: 8.5, y: CAMERA_Y_RANGE[0], z: 10.6 });
-
-        // Guitar neck model
-        builder.spawn(SceneBundle {
-            scene: asset_server.load("models/ingame/guitar_neck.glb#Scene0"),
-            transform: Transform {
-                rotation: Quat::from_rotation_x(90_f32.to_radians()),
-                ..Default::default()
-            },
-            ..default()
-        });
-
-        // Song timeline
-        spawn_timeline(builder, &mut meshes, &mut materials);
-    
-        let font = asset_server.load("fonts/IBMPlexMono-Regular.ttf");
-    
-        for fret_index in 0..FRET_AMOUNT {
-            builder.spawn((BillboardTextBundle {
-                transform: Transform {
-                    translation: Vec3 { x: 0.0, y: FRET_CENTERS[fret_index], z: -0.35 },
-                    rotation: Quat { w: 0.5, x: 0.5, y: -0.5, z: -0.5 },
-                    scale: Vec3::splat(0.0085),
-                    ..Default::default()
-                },
-                text: Text::from_sections([
-                    TextSection {
-                        value: format!("{}", fret_index+1),
-                        style: TextStyle {
-                            font_size: 28.0,
-                            font: font.clone(),
-                            color: Color::WHITE,
-                        },
-                    },
-                ])
-                .with_justify(JustifyText::Center),
-                ..default()
-            }, BillboardLockAxis{
-                rotation: true,
-                ..default()
-            }));
-        }
-    
-        // Directional 'sun' light
-        builder.spawn(DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                illuminance: light_consts::lux::AMBIENT_DAYLIGHT * 1.5,
-                shadows_enabled: false,
-                shadow_depth_bias: 0.3,
-                shadow_normal_bias: 0.3,
-                ..default()
-            },
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.0),
-                rotation: Quat::from_euler(EulerRot::XYZ, (-16_f32).to_radians(), 26_f32.to_radians(), 0.0),
-                ..default()
-            },
-            ..default()
-        });
+        spawn_highway(builder, &asset_server, &mut meshes, &mut materials, &configuration);
     });
 
     // UI
@@ -140,6 +160,16 @@ pub fn song_play_load(
                     BackButtonMarker
                 );
 
+                // Score
+                builder.spawn((TextBundle::from_section(
+                    "0",
+                    TextStyle {
+                        font: asset_server.load("fonts/IBMPlexMono-Bold.ttf"),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ), ScoreMarker));
+
                 // Seconds passed
                 builder.spawn(NodeBundle {
                     style: Style {
@@ -175,11 +205,13 @@ pub fn song_play_load(
             .with_children(|builder|{
                 // Title
                 let song_metadata = song_loaded.metadata.clone().unwrap();
+                let chart_label = song_loaded.song_chart.map(|song_chart| format!(" ({})", song_chart.label())).unwrap_or_default();
                 builder.spawn(TextBundle::from_section(
                     format!(
-                        "{} - {}",
+                        "{} - {}{}",
                         song_metadata.artists.join(", "),
-                        song_metadata.title.clone()
+                        song_metadata.title.clone(),
+                        chart_label
                     ),
                     TextStyle {
                         font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
@@ -203,14 +235,28 @@ pub fn song_play_load(
             });
         });
 
-    // Play the audio
-    let _ = output_audio_song.sender.send(AudioCommand::Play(song_loaded.audio_path.as_ref().unwrap().clone()));
+    // Play the audio, as separate stems when the guitar is muted and the song has a guitar stem
+    let start_seconds = song_loaded.progress.as_ref().map(|song_progress| song_progress.timer.elapsed_secs()).unwrap_or(0.0);
+    let has_guitar_stem = song_loaded.stems.iter().any(|(kind, _)| *kind == StemKind::Guitar);
+    if has_guitar_stem && configuration.guitar_stem != GuitarStemMode::Normal {
+        let stems = song_loaded.stems.iter().map(|(kind, file_path)| StemPlayback {
+            kind: *kind,
+            file_path: file_path.clone(),
+            volume: if *kind == StemKind::Guitar { 0.0 } else { 1.0 },
+        }).collect();
+        let _ = output_audio_song.sender.send(AudioCommand::PlayStems(stems, start_seconds));
+        commands.insert_resource(GuitarStemFadeResource::new(configuration.guitar_stem == GuitarStemMode::FadeOnMiss));
+    } else {
+        let _ = output_audio_song.sender.send(AudioCommand::Play(song_loaded.audio_path.as_ref().unwrap().clone(), start_seconds));
+        commands.insert_resource(GuitarStemFadeResource::default());
+    }
 }
 
 pub fn song_play_update(
     time: Res<Time>,
     back_button_query_interaction: Query<&Interaction, With<BackButtonMarker>>,
     mut seconds_passed_query: Query<&mut Text, With<SecondsPassedMarker>>,
+    mut score_query: Query<&mut Text, (With<ScoreMarker>, Without<SecondsPassedMarker>)>,
     mut debug_onset_marker: Query<&mut BackgroundColor, With<DebugOnsetMarker>>,
     mut next_state: ResMut<NextState<AppState>>,
     mut song_loaded: ResMut<SongLoadedResource>,
@@ -220,10 +266,14 @@ pub fn song_play_update(
 ) {
     for interaction in back_button_query_interaction.iter() {
         if *interaction == Interaction::Pressed {
-            next_state.set(AppState::SongSelect);
+            next_state.set(if song_loaded.test_play { AppState::Editor } else { AppState::SongSelect });
         }
     }
 
+    // Reborrowed so the progress and the adaptive difficulty can be changed together
+    let song_loaded = &mut *song_loaded;
+    let tuning = song_loaded.metadata.as_ref().map(|song_metadata| song_metadata.tuning.clone());
+
     if let Some(song_progress) = song_loaded.progress.as_mut() {
         song_progress.timer.tick(time.delta());
         let elapsed_secs = song_progress.timer.elapsed_secs();
@@ -232,6 +282,18 @@ pub fn song_play_update(
             text.sections[0].value = elapsed_secs.to_string()
         }
 
+        // Feeds the results of notes that were scored since the last frame to the adaptive difficulty
+        if let Some(dynamic_difficulty) = song_loaded.dynamic_difficulty.as_mut() {
+            for note_with_score in song_progress.notes_with_score.iter().skip(dynamic_difficulty.results_recorded()) {
+                dynamic_difficulty.record(&note_with_score.note_event, !note_with_score.missed);
+            }
+        }
+
+        let level = song_loaded.dynamic_difficulty.as_ref().map(|dynamic_difficulty| format!("  {}", dynamic_difficulty.level().name())).unwrap_or_default();
+        for mut text in score_query.iter_mut() {
+            text.sections[0].value = format!("{:.0}{}", song_progress.score, level)
+        }
+
         if let Some(audio_stream) = &input_device.audio_stream_main {
             // Debounce - exact value yet to be determined after more experimentation
             if song_progress.previous_onset_secs + 0.1 < elapsed_secs {
@@ -256,6 +318,23 @@ pub fn song_play_update(
                     }
                 }
             }
+
+            // Hammer-ons and pull-offs are played without picking, so they are judged on reaching their pitch instead of on an onset
+            let legato_notes: Vec<&Note> = notes_query.iter().filter(|note| {
+                !note.triggered &&
+                note.note_event.legato.is_some() &&
+                note.note_event.start_time_seconds > elapsed_secs - 0.2 && note.note_event.start_time_seconds < elapsed_secs + 0.2
+            }).collect();
+            if let (false, Some(tuning)) = (legato_notes.is_empty(), &tuning) {
+                if let Some(pitch) = audio_stream.get_pitch() {
+                    let played_note = note_from_pitch(pitch);
+                    for note in legato_notes {
+                        if note.note_event.midi_pitch(tuning).map(|midi_pitch| midi_pitch as i32) == Some(played_note) {
+                            event_song_note_triggered.send(NoteTriggeredEvent(note.clone()));
+                        }
+                    }
+                }
+            }
         } else {
             error!("no audio stream :c")
         }
@@ -0,0 +1,126 @@
+use bevy::prelude::*;
+
+use crate::{helpers::song_validation::LibraryReport, resources::{configuration::ConfigurationResource, song_library::SongLibraryResource, song_library_scan::SongLibraryScanResource}};
+
+#[derive(Component)]
+pub struct LibraryReportButton;
+#[derive(Component)]
+pub struct LibraryReportPanelMarker;
+#[derive(Component)]
+pub struct WatchSongsDirButton;
+
+pub fn library_report_label(report: &LibraryReport, song_library_scan: &SongLibraryScanResource) -> String {
+    if song_library_scan.is_scanning() {
+        let (done, total) = song_library_scan.progress();
+        return format!("library: scanning {}/{}", done, total)
+    }
+
+    match report.issue_count() {
+        0 => "library: no issues".to_string(),
+        issue_count => format!("library: {} issues", issue_count),
+    }
+}
+
+pub fn watch_songs_dir_label(configuration: &ConfigurationResource) -> String {
+    format!("watch songs folder: {}", if configuration.watch_songs_dir { "on" } else { "off" })
+}
+
+/// Text of the report, a line per problem grouped by song
+fn library_report_text(report: &LibraryReport) -> String {
+    let mut lines = vec![];
+    for song_report in &report.songs {
+        let skipped = if song_report.is_playable() { "" } else { " (skipped)" };
+        lines.push(format!("{}{}", song_report.name(), skipped));
+        for issue in &song_report.issues {
+            lines.push(format!("  - {}", issue));
+        }
+    }
+    lines.join("\n")
+}
+
+/// The problems found when scanning the library, hidden until the report button is pressed
+pub fn spawn_library_report(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+    report: &LibraryReport,
+) {
+    builder.spawn((NodeBundle {
+        style: Style {
+            display: Display::None,
+            max_width: Val::Px(700.0),
+            padding: UiRect::all(Val::Px(12.0)),
+            border: UiRect::all(Val::Px(2.0)),
+            ..Default::default()
+        },
+        border_color: BorderColor(Color::srgb(0.6, 0.35, 0.2)),
+        ..Default::default()
+    }, LibraryReportPanelMarker)).with_children(|builder| {
+        builder.spawn(TextBundle::from_section(
+            library_report_text(report),
+            TextStyle {
+                font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                font_size: 14.0,
+                color: Color::WHITE,
+            },
+        ));
+    });
+}
+
+/// Shows or hides the report, and keeps it and the scan progress up to date
+pub fn library_report_update(
+    buttons: Res<ButtonInput<MouseButton>>,
+    song_library: Res<SongLibraryResource>,
+    song_library_scan: Res<SongLibraryScanResource>,
+    mut configuration: ResMut<ConfigurationResource>,
+    report_button_query: Query<(&Interaction, &Children), With<LibraryReportButton>>,
+    watch_button_query: Query<(&Interaction, &Children), With<WatchSongsDirButton>>,
+    mut report_panel_query: Query<(&mut Style, &Children), With<LibraryReportPanelMarker>>,
+    mut text_query: Query<&mut Text>,
+    mut was_scanning: Local<bool>,
+) {
+    for (interaction, children) in watch_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            configuration.watch_songs_dir = !configuration.watch_songs_dir;
+            configuration.save_to_disk();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = watch_songs_dir_label(&configuration);
+                }
+            }
+        }
+    }
+
+    for (interaction, _) in report_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            for (mut style, _) in report_panel_query.iter_mut() {
+                style.display = if style.display == Display::None && !song_library.report.is_empty() { Display::Flex } else { Display::None };
+            }
+        }
+    }
+
+    let is_scanning = song_library_scan.is_scanning();
+    if is_scanning || *was_scanning || song_library.is_changed() {
+        for (_, children) in report_button_query.iter() {
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = library_report_label(&song_library.report, &song_library_scan);
+                }
+            }
+        }
+    }
+    *was_scanning = is_scanning;
+
+    if song_library.is_changed() {
+        for (mut style, children) in report_panel_query.iter_mut() {
+            if song_library.report.is_empty() {
+                style.display = Display::None;
+            }
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = library_report_text(&song_library.report);
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,27 @@
 use bevy::prelude::*;
 
-use crate::states::app_state::AppState;
+use crate::{resources::output_audio_song::{output_audio_song_cleanup, output_audio_song_load}, states::app_state::AppState};
 
-use super::song_select::{song_select_cleanup, song_select_load, song_select_update};
+use super::{library_report::library_report_update, song_details::song_details_update, song_filter::{song_filter_update, SongListFilterResource}, song_import::{song_import_update, SongImportResource}, song_preview::song_preview_update, song_list::{song_list_update, song_list_view_update, SongListViewResource}, song_select::{song_select_cleanup, song_select_load, song_select_update}};
 pub struct SongSelectPlugin;
 
 impl Plugin for SongSelectPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::SongSelect), song_select_load);
+        app.insert_resource(SongListFilterResource::default());
+        app.insert_resource(SongListViewResource::default());
+        app.insert_resource(SongImportResource::default());
+
+        app.add_systems(OnEnter(AppState::SongSelect), output_audio_song_load);
+        app.add_systems(OnEnter(AppState::SongSelect), song_select_load.after(output_audio_song_load));
         app.add_systems(Update, song_select_update.run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_list_update.run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_import_update.run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, library_report_update.run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_filter_update.before(song_list_view_update).run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_list_view_update.before(song_list_update).run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_details_update.run_if(in_state(AppState::SongSelect)));
+        app.add_systems(Update, song_preview_update.after(song_list_update).run_if(in_state(AppState::SongSelect)));
         app.add_systems(OnExit(AppState::SongSelect), song_select_cleanup);
+        app.add_systems(OnExit(AppState::SongSelect), output_audio_song_cleanup);
     }
 }
@@ -0,0 +1,146 @@
+use std::fs;
+
+use bevy::{prelude::*, render::{render_asset::RenderAssetUsages, texture::{CompressedImageFormats, ImageSampler, ImageType}}};
+
+use crate::{helpers::persistence::get_songs_dir, resources::{play_history::PlayHistoryResource, song_library::{SongLibraryResource, SongMetadata}}};
+
+use super::{song_filter::tuning_label, song_list::SongListViewResource};
+
+// Album art is looked for under these names in the song directory
+const COVER_FILENAMES: [&str; 3] = ["cover.png", "cover.jpg", "cover.jpeg"];
+
+#[derive(Component)]
+pub struct SongDetailsTextMarker;
+#[derive(Component)]
+pub struct SongDetailsCoverMarker;
+
+fn format_duration(seconds: f32) -> String {
+    let seconds = seconds.max(0.0).round() as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+fn song_details_text(song_metadata: &SongMetadata, play_history: &PlayHistoryResource) -> String {
+    let arrangements = if song_metadata.charts.is_empty() {
+        "lead".to_string()
+    } else {
+        let mut labels: Vec<String> = song_metadata.charts.iter().map(|song_chart| song_chart.label()).collect();
+        if song_metadata.has_main_chart {
+            labels.push("lead full".to_string());
+        }
+        labels.join(", ")
+    };
+    let best_score = match play_history.get(&song_metadata.uuid) {
+        Some(record) => format!("personal best: {:.0} ({} plays)", record.best_score, record.play_count),
+        None => "not played yet".to_string(),
+    };
+
+    [
+        song_metadata.title.clone(),
+        song_metadata.artists.join(", "),
+        String::new(),
+        format!("tuning: {}", tuning_label(&song_metadata.tuning)),
+        format!("duration: {}", format_duration(song_metadata.audio_duration_seconds())),
+        format!("charts: {}", arrangements),
+        best_score,
+    ].join("\n")
+}
+
+/// Album art of a song, read from its directory since the asset server only reads the assets folder
+fn load_cover(song_metadata: &SongMetadata) -> Option<Image> {
+    let song_directory = get_songs_dir()?.join(&song_metadata.uuid);
+    COVER_FILENAMES.iter().find_map(|filename| {
+        let bytes = fs::read(song_directory.join(filename)).ok()?;
+        let extension = filename.rsplit('.').next().unwrap_or_default();
+        Image::from_buffer(
+            &bytes,
+            ImageType::Extension(extension),
+            CompressedImageFormats::NONE,
+            true,
+            ImageSampler::Default,
+            RenderAssetUsages::RENDER_WORLD,
+        ).map_err(|err| warn!("could not read the cover of {}: {}", song_metadata.uuid, err)).ok()
+    })
+}
+
+/// Panel next to the song list with the details of the highlighted song
+pub fn spawn_song_details(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            width: Val::Px(280.0),
+            padding: UiRect::all(Val::Px(12.0)),
+            border: UiRect::all(Val::Px(2.0)),
+            row_gap: Val::Px(12.0),
+            ..Default::default()
+        },
+        border_color: BorderColor(Color::srgb(0.35, 0.35, 0.35)),
+        ..Default::default()
+    }).with_children(|builder| {
+        builder.spawn((ImageBundle {
+            style: Style {
+                display: Display::None,
+                width: Val::Px(160.0),
+                height: Val::Px(160.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        }, SongDetailsCoverMarker));
+
+        builder.spawn((TextBundle::from_section(
+            "point at a song to see its details",
+            TextStyle {
+                font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                font_size: 14.0,
+                color: Color::WHITE,
+            },
+        ), SongDetailsTextMarker));
+    });
+}
+
+/// Shows the details of the song under the cursor of the song list
+pub fn song_details_update(
+    song_list_view: Res<SongListViewResource>,
+    song_library: Res<SongLibraryResource>,
+    play_history: Res<PlayHistoryResource>,
+    mut images: ResMut<Assets<Image>>,
+    added_panel_query: Query<(), Added<SongDetailsTextMarker>>,
+    mut text_query: Query<&mut Text, With<SongDetailsTextMarker>>,
+    mut cover_query: Query<(&mut UiImage, &mut Style), With<SongDetailsCoverMarker>>,
+    mut shown_uuid: Local<Option<String>>,
+) {
+    if !song_list_view.is_changed() && added_panel_query.is_empty() {
+        return
+    }
+    let song_metadata = song_list_view.cursor_row().and_then(|song_list_row| song_library.songs.get(song_list_row.song_index()));
+    // A new panel shows nothing yet
+    if !added_panel_query.is_empty() {
+        *shown_uuid = None;
+    }
+    if shown_uuid.as_ref() == song_metadata.map(|song_metadata| &song_metadata.uuid) {
+        return
+    }
+    *shown_uuid = song_metadata.map(|song_metadata| song_metadata.uuid.clone());
+    let Some(song_metadata) = song_metadata else { return };
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = song_details_text(song_metadata, &play_history);
+    }
+
+    let cover = load_cover(song_metadata);
+    // The previous cover is dropped with its handle
+    for (mut ui_image, mut style) in cover_query.iter_mut() {
+        match &cover {
+            Some(cover) => {
+                ui_image.texture = images.add(cover.clone());
+                style.display = Display::Flex;
+            }
+            None => {
+                ui_image.texture = Handle::default();
+                style.display = Display::None;
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::cmp::Ordering;
+
+use bevy::{input::{keyboard::{Key, KeyboardInput}, ButtonState}, prelude::*};
+
+use crate::{components::button_minimal::spawn_button_minimal, helpers::{chart::{Arrangement, Difficulty}, tuning::STANDARD_TUNING}, resources::{play_history::PlayHistoryResource, song_library::{SongLibraryResource, SongMetadata}}};
+
+#[derive(Component)]
+pub struct SongSearchTextMarker;
+#[derive(Component)]
+pub struct SongSortButton;
+#[derive(Component)]
+pub struct TuningFilterButton;
+#[derive(Component)]
+pub struct ArrangementFilterButton;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SongSort {
+    #[default]
+    Title,
+    Artist,
+    Duration,
+    Difficulty,
+    LastPlayed,
+    BestScore,
+}
+
+impl SongSort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SongSort::Title => "title",
+            SongSort::Artist => "artist",
+            SongSort::Duration => "duration",
+            SongSort::Difficulty => "difficulty",
+            SongSort::LastPlayed => "last played",
+            SongSort::BestScore => "best score",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            SongSort::Title => SongSort::Artist,
+            SongSort::Artist => SongSort::Duration,
+            SongSort::Duration => SongSort::Difficulty,
+            SongSort::Difficulty => SongSort::LastPlayed,
+            SongSort::LastPlayed => SongSort::BestScore,
+            SongSort::BestScore => SongSort::Title,
+        }
+    }
+}
+
+/// "E standard" for standard tuning, otherwise the notes of the strings
+pub fn tuning_label(tuning: &[String; 6]) -> String {
+    if tuning.iter().zip(STANDARD_TUNING).all(|(note, standard_note)| note == standard_note) {
+        return "E standard".to_string()
+    }
+    tuning.join(" ")
+}
+
+/// Hardest chart of a song, songs with only a main chart have none
+fn hardest_difficulty(song_metadata: &SongMetadata) -> Option<Difficulty> {
+    song_metadata.charts.iter().map(|song_chart| song_chart.difficulty).max()
+}
+
+/// What the song list shows and in which order, kept while leaving song select
+#[derive(Resource, Debug, Default)]
+pub struct SongListFilterResource {
+    pub query: String,
+    pub sort: SongSort,
+    pub tuning: Option<[String; 6]>,
+    pub arrangement: Option<Arrangement>,
+}
+
+impl SongListFilterResource {
+    fn matches(&self, song_metadata: &SongMetadata) -> bool {
+        let query = self.query.to_lowercase();
+        let matches_query = query.split_whitespace().all(|word| {
+            song_metadata.title.to_lowercase().contains(word) ||
+            song_metadata.artists.iter().any(|artist| artist.to_lowercase().contains(word))
+        });
+        let matches_tuning = self.tuning.as_ref().map(|tuning| tuning == &song_metadata.tuning).unwrap_or(true);
+        // The main chart is a lead chart, also for songs that list other charts
+        let matches_arrangement = match self.arrangement {
+            Some(Arrangement::Lead) if song_metadata.charts.is_empty() || song_metadata.has_main_chart => true,
+            Some(arrangement) => song_metadata.charts.iter().any(|song_chart| song_chart.arrangement == arrangement),
+            None => true,
+        };
+
+        matches_query && matches_tuning && matches_arrangement
+    }
+
+    /// Indices of the songs of the library that match the search and filters, sorted
+    pub fn apply(&self, songs: &[SongMetadata], play_history: &PlayHistoryResource) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..songs.len()).filter(|&index| self.matches(&songs[index])).collect();
+
+        let by_title = |a: &&SongMetadata, b: &&SongMetadata| a.title.to_lowercase().cmp(&b.title.to_lowercase());
+        let last_played = |song_metadata: &SongMetadata| play_history.get(&song_metadata.uuid).map(|record| record.last_played_millis);
+        let best_score = |song_metadata: &SongMetadata| play_history.get(&song_metadata.uuid).map(|record| record.best_score).unwrap_or(-1.0);
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (&songs[a], &songs[b]);
+            let ordering = match self.sort {
+                SongSort::Title => Ordering::Equal,
+                SongSort::Artist => a.artists.join(", ").to_lowercase().cmp(&b.artists.join(", ").to_lowercase()),
+                SongSort::Duration => a.audio_duration_seconds().total_cmp(&b.audio_duration_seconds()),
+                SongSort::Difficulty => hardest_difficulty(a).cmp(&hardest_difficulty(b)),
+                // Most recent and best first, songs that were never played last
+                SongSort::LastPlayed => last_played(b).cmp(&last_played(a)),
+                SongSort::BestScore => best_score(b).total_cmp(&best_score(a)),
+            };
+            ordering.then_with(|| by_title(&a, &b))
+        });
+
+        indices
+    }
+
+    /// Cycles through the tunings of the songs in the library
+    pub fn next_tuning(&mut self, songs: &[SongMetadata]) {
+        let mut tunings: Vec<&[String; 6]> = songs.iter().map(|song_metadata| &song_metadata.tuning).collect();
+        tunings.sort();
+        tunings.dedup();
+
+        let next_index = match &self.tuning {
+            Some(tuning) => tunings.iter().position(|other| *other == tuning).map(|index| index + 1).unwrap_or(tunings.len()),
+            None => 0,
+        };
+        self.tuning = tunings.get(next_index).map(|tuning| (*tuning).clone());
+    }
+
+    pub fn next_arrangement(&mut self) {
+        self.arrangement = match self.arrangement {
+            None => Some(Arrangement::ALL[0]),
+            Some(arrangement) => Arrangement::ALL.iter().skip_while(|other| **other != arrangement).nth(1).copied(),
+        };
+    }
+}
+
+fn search_label(song_list_filter: &SongListFilterResource) -> String {
+    if song_list_filter.query.is_empty() {
+        "type to search".to_string()
+    } else {
+        format!("search: {}_", song_list_filter.query)
+    }
+}
+
+fn sort_label(song_list_filter: &SongListFilterResource) -> String {
+    format!("sort: {}", song_list_filter.sort.name())
+}
+
+fn tuning_filter_label(song_list_filter: &SongListFilterResource) -> String {
+    format!("tuning: {}", song_list_filter.tuning.as_ref().map(tuning_label).unwrap_or("all".to_string()))
+}
+
+fn arrangement_filter_label(song_list_filter: &SongListFilterResource) -> String {
+    format!("arrangement: {}", song_list_filter.arrangement.map(|arrangement| arrangement.name()).unwrap_or("all"))
+}
+
+/// Search text and the sort and filter options above the song list
+pub fn spawn_song_filter(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+    song_list_filter: &SongListFilterResource,
+) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|builder| {
+        builder.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(300.0),
+                padding: UiRect { left: Val::Px(12.0), right: Val::Px(12.0), top: Val::Px(8.0), bottom: Val::Px(8.0) },
+                ..Default::default()
+            },
+            ..Default::default()
+        }).with_children(|builder| {
+            builder.spawn((TextBundle::from_section(
+                search_label(song_list_filter),
+                TextStyle {
+                    font: asset_server.load("fonts/IBMPlexMono-Medium.ttf"),
+                    font_size: 16.0,
+                    color: Color::srgb(0.7, 0.7, 0.7),
+                },
+            ), SongSearchTextMarker));
+        });
+
+        spawn_button_minimal(builder, asset_server, &sort_label(song_list_filter), SongSortButton);
+        spawn_button_minimal(builder, asset_server, &tuning_filter_label(song_list_filter), TuningFilterButton);
+        spawn_button_minimal(builder, asset_server, &arrangement_filter_label(song_list_filter), ArrangementFilterButton);
+    });
+}
+
+/// Typing searches the song list, escape clears the search
+pub fn song_filter_update(
+    mut keyboard_input_events: EventReader<KeyboardInput>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    song_library: Res<SongLibraryResource>,
+    mut song_list_filter: ResMut<SongListFilterResource>,
+    sort_button_query: Query<(&Interaction, &Children), With<SongSortButton>>,
+    tuning_button_query: Query<(&Interaction, &Children), With<TuningFilterButton>>,
+    arrangement_button_query: Query<(&Interaction, &Children), With<ArrangementFilterButton>>,
+    mut search_text_query: Query<&mut Text, With<SongSearchTextMarker>>,
+    mut text_query: Query<&mut Text, Without<SongSearchTextMarker>>,
+) {
+    let mut query = song_list_filter.query.clone();
+    for keyboard_input in keyboard_input_events.read() {
+        if keyboard_input.state != ButtonState::Pressed {
+            continue
+        }
+        match &keyboard_input.logical_key {
+            Key::Character(characters) => query.push_str(characters),
+            Key::Space => query.push(' '),
+            Key::Backspace => { query.pop(); }
+            Key::Escape => query.clear(),
+            _ => {}
+        }
+    }
+    // Only marks the filter as changed when the search changed, so the list isn't rebuilt every frame
+    if query != song_list_filter.query {
+        song_list_filter.query = query;
+        for mut text in search_text_query.iter_mut() {
+            text.sections[0].value = search_label(&song_list_filter);
+        }
+    }
+
+    for (interaction, children) in sort_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            song_list_filter.sort = song_list_filter.sort.next();
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = sort_label(&song_list_filter);
+                }
+            }
+        }
+    }
+    for (interaction, children) in tuning_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            song_list_filter.next_tuning(&song_library.songs);
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = tuning_filter_label(&song_list_filter);
+                }
+            }
+        }
+    }
+    for (interaction, children) in arrangement_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            song_list_filter.next_arrangement();
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = arrangement_filter_label(&song_list_filter);
+                }
+            }
+        }
+    }
+}
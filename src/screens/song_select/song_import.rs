@@ -0,0 +1,356 @@
+use std::path::{Path, PathBuf};
+
+use bevy::{prelude::*, tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task}};
+use rfd::{AsyncFileDialog, AsyncMessageDialog, MessageButtons, MessageDialogResult};
+
+use crate::{components::button_minimal::spawn_button_minimal, helpers::song_audio::AUDIO_EXTENSIONS, importers::imported_song::{ImportChoice, ImportSource, ImportedSong, IMPORT_EXTENSIONS}, package::package::{export_package, SongPackage, PACKAGE_EXTENSION}, resources::{song_library::{SongLibraryResource, SongMetadata}, song_library_scan::SongLibraryScanResource}};
+
+// Issues listed in the status, the rest are only counted
+const SHOWN_ISSUE_AMOUNT: usize = 5;
+
+#[derive(Component)]
+pub struct ImportSongButton;
+#[derive(Component)]
+pub struct ImportPackageButton;
+#[derive(Component)]
+pub struct SongImportStatusMarker;
+#[derive(Component)]
+pub struct SongImportChoiceMarker;
+#[derive(Component)]
+pub struct ImportTrackButton;
+#[derive(Component)]
+pub struct ImportConfirmButton;
+#[derive(Component)]
+pub struct ImportCancelButton;
+
+/// Where an import is, the file dialogs run as tasks so the game keeps running while they are open
+#[derive(Default)]
+enum SongImportStep {
+    #[default]
+    Idle,
+    PickingTab(Task<Option<Result<(PathBuf, ImportSource), String>>>),
+    ChoosingTrack {
+        tab_path: PathBuf,
+        source: Box<ImportSource>,
+        choices: Vec<ImportChoice>,
+        choice: usize,
+    },
+    PickingAudio(Task<Option<Result<String, String>>>),
+    PickingPackage(Task<Option<Result<String, String>>>),
+    Exporting(Task<Option<Result<String, String>>>),
+}
+
+#[derive(Resource, Default)]
+pub struct SongImportResource {
+    step: SongImportStep,
+    status: String, // Outcome of the last import
+}
+
+impl SongImportResource {
+    pub fn status(&self) -> String {
+        match &self.step {
+            SongImportStep::Idle => self.status.clone(),
+            SongImportStep::PickingTab(_) => "choose a tab, MIDI or MusicXML file".to_string(),
+            SongImportStep::ChoosingTrack { tab_path, .. } => format!("choose the track to import from {}", file_name(tab_path)),
+            SongImportStep::PickingAudio(_) => "choose the audio of the song".to_string(),
+            SongImportStep::PickingPackage(_) => "choose a song package".to_string(),
+            SongImportStep::Exporting(_) => "exporting, choose where to save the package".to_string(),
+        }
+    }
+
+    /// Writes a song into a package, unless an import or export is already running
+    pub fn export(&mut self, song_metadata: &SongMetadata) {
+        if !self.is_busy() {
+            self.step = SongImportStep::Exporting(pick_export_path(song_metadata));
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        !matches!(self.step, SongImportStep::Idle)
+    }
+
+    fn track_label(&self) -> String {
+        match &self.step {
+            SongImportStep::ChoosingTrack { choices, choice, .. } => format!("track: {} ({}/{})", choices[*choice].label, choice + 1, choices.len()),
+            _ => String::new(),
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+/// Outcome of an import, with the parts of the file that were left out of the chart
+fn imported_status(imported_song: &ImportedSong, title: &str) -> String {
+    let mut lines = vec![format!("imported {} with {} notes", title, imported_song.chart.notes.len())];
+    for issue in imported_song.issues.iter().take(SHOWN_ISSUE_AMOUNT) {
+        lines.push(format!("  - {}", issue));
+    }
+    if imported_song.issues.len() > SHOWN_ISSUE_AMOUNT {
+        lines.push(format!("  and {} more issues", imported_song.issues.len() - SHOWN_ISSUE_AMOUNT));
+    }
+    lines.join("\n")
+}
+
+fn pick_tab() -> Task<Option<Result<(PathBuf, ImportSource), String>>> {
+    AsyncComputeTaskPool::get().spawn(async move {
+        let file = AsyncFileDialog::new()
+            .set_title("select a tab, MIDI or MusicXML file")
+            .add_filter("tab, MIDI or MusicXML", &IMPORT_EXTENSIONS)
+            .pick_file()
+            .await?;
+        let tab_path = file.path().to_path_buf();
+        Some(ImportSource::load_from_file(&tab_path)
+            .map(|source| (tab_path.clone(), source))
+            .map_err(|err| format!("could not read {}: {}", file_name(&tab_path), err)))
+    })
+}
+
+/// Creates a new song from the imported chart and the audio the player picks
+fn pick_audio(imported_song: ImportedSong) -> Task<Option<Result<String, String>>> {
+    AsyncComputeTaskPool::get().spawn(async move {
+        let file = AsyncFileDialog::new()
+            .set_title("select the audio of the song")
+            .add_filter("audio", &AUDIO_EXTENSIONS)
+            .pick_file()
+            .await?;
+        Some(match imported_song.create_song(file.path()) {
+            Ok(song_metadata) => {
+                info!("imported song {}", song_metadata.uuid);
+                Ok(imported_status(&imported_song, &song_metadata.title))
+            }
+            Err(err) => Err(format!("could not create the song: {}", err)),
+        })
+    })
+}
+
+/// Unpacks a song package into the library, asking before it replaces a song that is already there
+fn pick_package() -> Task<Option<Result<String, String>>> {
+    AsyncComputeTaskPool::get().spawn(async move {
+        let file = AsyncFileDialog::new()
+            .set_title("select a song package")
+            .add_filter("song package", &[PACKAGE_EXTENSION])
+            .pick_file()
+            .await?;
+        let package = match SongPackage::load_from_file(file.path()) {
+            Ok(package) => package,
+            Err(err) => return Some(Err(format!("could not read {}: {}", file.file_name(), err))),
+        };
+
+        let metadata = &package.manifest.metadata;
+        let name = format!("{} - {}", metadata.artists.join(", "), metadata.title);
+        if package.exists() {
+            let answer = AsyncMessageDialog::new()
+                .set_title("replace song")
+                .set_description(format!("{} is already in the library, replace it with the package?", name))
+                .set_buttons(MessageButtons::YesNo)
+                .show()
+                .await;
+            if answer != MessageDialogResult::Yes {
+                return None
+            }
+        }
+
+        Some(match package.unpack() {
+            Ok(song_metadata) => {
+                info!("imported package {:?} as song {}", file.path(), song_metadata.uuid);
+                Ok(format!("imported {}", name))
+            }
+            Err(err) => Err(format!("could not unpack {}: {}", file.file_name(), err)),
+        })
+    })
+}
+
+/// Writes a song into a package file the player picks, reading and zipping the audio off the main thread
+fn pick_export_path(song_metadata: &SongMetadata) -> Task<Option<Result<String, String>>> {
+    let uuid = song_metadata.uuid.clone();
+    let title = song_metadata.title.clone();
+    AsyncComputeTaskPool::get().spawn(async move {
+        let file = AsyncFileDialog::new()
+            .set_title("export song package")
+            .set_file_name(format!("{}.{}", title, PACKAGE_EXTENSION))
+            .add_filter("song package", &[PACKAGE_EXTENSION])
+            .save_file()
+            .await?;
+        Some(match export_package(&uuid, file.path()) {
+            Ok(manifest) => {
+                info!("exported {} files to {:?}", manifest.files.len(), file.path());
+                Ok(format!("exported {} to {}", title, file.file_name()))
+            }
+            Err(err) => Err(format!("could not export {}: {}", title, err)),
+        })
+    })
+}
+
+/// Progress and outcome of imports and exports, with the track choice of a file that is being imported
+pub fn spawn_song_import(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+    song_import: &SongImportResource,
+) {
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            max_width: Val::Px(700.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|builder| {
+        builder.spawn((TextBundle::from_section(
+            song_import.status(),
+            TextStyle {
+                font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                font_size: 14.0,
+                color: Color::WHITE,
+            },
+        ), SongImportStatusMarker));
+
+        builder.spawn((NodeBundle {
+            style: Style {
+                display: if matches!(song_import.step, SongImportStep::ChoosingTrack { .. }) { Display::Flex } else { Display::None },
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        }, SongImportChoiceMarker)).with_children(|builder| {
+            spawn_button_minimal(builder, asset_server, &song_import.track_label(), ImportTrackButton);
+            spawn_button_minimal(builder, asset_server, "import", ImportConfirmButton);
+            spawn_button_minimal(builder, asset_server, "cancel", ImportCancelButton);
+        });
+    });
+}
+
+/// Runs the import dialogs, lets the player choose the track to import and shows how the import went
+pub fn song_import_update(
+    buttons: Res<ButtonInput<MouseButton>>,
+    song_library: Res<SongLibraryResource>,
+    mut song_library_scan: ResMut<SongLibraryScanResource>,
+    mut song_import: ResMut<SongImportResource>,
+    import_song_button_query: Query<&Interaction, With<ImportSongButton>>,
+    import_package_button_query: Query<&Interaction, With<ImportPackageButton>>,
+    track_button_query: Query<(&Interaction, &Children), With<ImportTrackButton>>,
+    confirm_button_query: Query<&Interaction, With<ImportConfirmButton>>,
+    cancel_button_query: Query<&Interaction, With<ImportCancelButton>>,
+    mut status_query: Query<&mut Text, With<SongImportStatusMarker>>,
+    mut choice_query: Query<&mut Style, With<SongImportChoiceMarker>>,
+    mut text_query: Query<&mut Text, Without<SongImportStatusMarker>>,
+) {
+    let clicked = |interaction: &Interaction| *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left);
+
+    // Only one import at a time, a second dialog would stack on the first one
+    if !song_import.is_busy() {
+        if import_song_button_query.iter().any(clicked) {
+            song_import.step = SongImportStep::PickingTab(pick_tab());
+        } else if import_package_button_query.iter().any(clicked) {
+            song_import.step = SongImportStep::PickingPackage(pick_package());
+        }
+    }
+
+    let mut refresh_song_library = false;
+    // Cycling through the tracks updates its own button, so the status isn't rewritten every frame
+    match &mut song_import.bypass_change_detection().step {
+        SongImportStep::PickingTab(task) => if let Some(result) = block_on(future::poll_once(task)) {
+            song_import.step = SongImportStep::Idle;
+            match result {
+                Some(Ok((tab_path, source))) => {
+                    let choices = source.choices();
+                    if choices.is_empty() {
+                        song_import.status = format!("{} has no track that can be played on a six string guitar", file_name(&tab_path));
+                    } else {
+                        let choice = source.default_choice(&choices);
+                        song_import.step = SongImportStep::ChoosingTrack { tab_path, source: Box::new(source), choices, choice };
+                    }
+                }
+                Some(Err(err)) => song_import.status = err,
+                None => song_import.status = String::new(),
+            }
+        },
+        SongImportStep::ChoosingTrack { tab_path, source, choices, choice } => {
+            for (interaction, children) in track_button_query.iter() {
+                if clicked(interaction) {
+                    *choice = (*choice + 1) % choices.len();
+                    for &child in children.iter() {
+                        if let Ok(mut text) = text_query.get_mut(child) {
+                            text.sections[0].value = format!("track: {} ({}/{})", choices[*choice].label, *choice + 1, choices.len());
+                        }
+                    }
+                }
+            }
+
+            if confirm_button_query.iter().any(clicked) {
+                // Converting is quick, so a track that can't be imported can be swapped right away
+                match source.import(&choices[*choice]) {
+                    Ok(imported_song) => {
+                        for issue in &imported_song.issues {
+                            warn!("{:?} {}", tab_path, issue);
+                        }
+                        song_import.step = SongImportStep::PickingAudio(pick_audio(imported_song));
+                    }
+                    Err(err) => {
+                        let status = format!("could not import {}: {}", choices[*choice].label, err);
+                        error!("{:?} {}", tab_path, status);
+                        song_import.status = status;
+                    }
+                }
+            } else if cancel_button_query.iter().any(clicked) {
+                song_import.step = SongImportStep::Idle;
+                song_import.status = String::new();
+            }
+        }
+        SongImportStep::PickingAudio(task) | SongImportStep::PickingPackage(task) => if let Some(result) = block_on(future::poll_once(task)) {
+            song_import.step = SongImportStep::Idle;
+            song_import.status = match result {
+                Some(Ok(status)) => {
+                    refresh_song_library = true;
+                    status
+                }
+                Some(Err(err)) => {
+                    error!("{}", err);
+                    err
+                }
+                None => String::new(),
+            };
+        },
+        SongImportStep::Exporting(task) => if let Some(result) = block_on(future::poll_once(task)) {
+            song_import.step = SongImportStep::Idle;
+            song_import.status = match result {
+                Some(Ok(status)) => status,
+                Some(Err(err)) => {
+                    error!("{}", err);
+                    err
+                }
+                None => String::new(),
+            };
+        },
+        SongImportStep::Idle => {}
+    }
+
+    // The song list is rebuilt once the scan is done
+    if refresh_song_library {
+        song_library_scan.start(&song_library);
+    }
+
+    if song_import.is_changed() {
+        let status = song_import.status();
+        for mut text in status_query.iter_mut() {
+            text.sections[0].value = status.clone();
+        }
+        let is_choosing = matches!(song_import.step, SongImportStep::ChoosingTrack { .. });
+        for mut style in choice_query.iter_mut() {
+            style.display = if is_choosing { Display::Flex } else { Display::None };
+        }
+        if is_choosing {
+            let track_label = song_import.track_label();
+            for (_, children) in track_button_query.iter() {
+                for &child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(child) {
+                        text.sections[0].value = track_label.clone();
+                    }
+                }
+            }
+        }
+    }
+}
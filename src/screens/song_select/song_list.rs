@@ -1,41 +1,228 @@
-use bevy::prelude::*;
+use bevy::{input::mouse::{MouseScrollUnit, MouseWheel}, prelude::*};
 
-use crate::resources::song_library::SongMetadata;
+use crate::{components::button_minimal::spawn_button_minimal, helpers::{chart::{Arrangement, SongChart, CHART_FILENAME}, persistence::get_songs_dir}, resources::{configuration::ConfigurationResource, play_history::PlayHistoryResource, song_editor::SongEditorResource, song_library::{SongLibraryResource, SongMetadata}, song_loaded::SongLoadedResource}, states::app_state::AppState};
 
+use super::{song_filter::SongListFilterResource, song_import::SongImportResource};
+
+// Every row has the same height, so the rows in view follow from the scroll position alone
+const ROW_HEIGHT: f32 = 52.0;
+// Rows scrolled by a notch of the mouse wheel
+const WHEEL_ROWS: f32 = 3.0;
+const CURSOR_BORDER_COLOR: Color = Color::srgb(0.85, 0.85, 0.85);
+const BORDER_COLOR: Color = Color::srgb(0.35, 0.35, 0.35);
+const CHART_CURSOR_COLOR: Color = Color::srgb(0.3, 0.3, 0.3);
+
+#[derive(Component)]
+pub struct SongListViewportMarker;
+#[derive(Component)]
+pub struct SongListRowsMarker;
 #[derive(Component)]
 pub struct SongListItemMarker {
+    pub row: usize,
+}
+#[derive(Component)]
+pub struct SongListEditMarker {
     pub uuid: String
 }
+#[derive(Component)]
+pub struct SongListExportMarker {
+    pub uuid: String
+}
+#[derive(Component)]
+pub struct SongListChartMarker {
+    pub uuid: String,
+    pub song_chart: Option<SongChart>, // None for the main chart
+    pub row: usize,
+    pub column: usize,
+}
 
-pub fn spawn_song_list(
-    builder: &mut ChildBuilder,
-    asset_server: &Res<AssetServer>,
-    song_metadata_list: &Vec<SongMetadata>,
-) {
-    builder.spawn(
-        NodeBundle {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SongListRow {
+    Song(usize), // Index into the songs of the library
+    Charts(usize, Arrangement), // Difficulties of one arrangement of the expanded song
+}
+
+impl SongListRow {
+    pub fn song_index(&self) -> usize {
+        match self {
+            SongListRow::Song(index) | SongListRow::Charts(index, _) => *index,
+        }
+    }
+}
+
+/// Charts of an arrangement of a song, easiest first
+/// The main chart holds every note and counts as a lead chart, so it comes after the lead charts, None stands for it
+fn arrangement_charts(song_metadata: &SongMetadata, arrangement: Arrangement) -> Vec<Option<SongChart>> {
+    let mut charts: Vec<Option<SongChart>> = song_metadata.charts.iter()
+        .filter(|song_chart| song_chart.arrangement == arrangement)
+        .map(|&song_chart| Some(song_chart))
+        .collect();
+    if arrangement == Arrangement::Lead && song_metadata.has_main_chart {
+        charts.push(None);
+    }
+    charts
+}
+
+/// Every chart a song can be played with, a song without any only has its main chart
+fn song_charts(song_metadata: &SongMetadata) -> Vec<Option<SongChart>> {
+    let charts: Vec<Option<SongChart>> = Arrangement::ALL.iter().flat_map(|&arrangement| arrangement_charts(song_metadata, arrangement)).collect();
+    if charts.is_empty() { vec![None] } else { charts }
+}
+
+/// The rows of the song list and which of them are in view
+/// Only the rows in view have entities, so the list stays quick with thousands of songs
+#[derive(Resource, Debug, Default)]
+pub struct SongListViewResource {
+    pub rows: Vec<SongListRow>,
+    pub cursor: usize, // Row highlighted by the mouse, keyboard or gamepad
+    pub chart_cursor: usize, // Chart highlighted on a row of charts
+    pub expanded_uuid: Option<String>, // Song that shows its arrangements and difficulties to choose from
+    song_indices: Vec<usize>,
+    scroll_rows: f32, // Rows scrolled past the top of the viewport
+    viewport_rows: f32,
+    spawned: Option<(usize, usize)>, // First row and number of rows that have entities
+}
+
+impl SongListViewResource {
+    /// Lists the songs in the given order, keeping the cursor and scroll position where possible
+    pub fn set_songs(&mut self, song_indices: Vec<usize>, songs: &[SongMetadata]) {
+        self.song_indices = song_indices;
+        self.rebuild_rows(songs);
+    }
+
+    fn rebuild_rows(&mut self, songs: &[SongMetadata]) {
+        self.rows.clear();
+        for &index in &self.song_indices {
+            self.rows.push(SongListRow::Song(index));
+            if self.expanded_uuid.as_ref() == Some(&songs[index].uuid) {
+                for arrangement in Arrangement::ALL {
+                    if !arrangement_charts(&songs[index], arrangement).is_empty() {
+                        self.rows.push(SongListRow::Charts(index, arrangement));
+                    }
+                }
+            }
+        }
+
+        self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+        self.scroll_by(0.0);
+        self.spawned = None;
+    }
+
+    /// Shows or hides the charts of a song below it
+    pub fn toggle_expanded(&mut self, uuid: &str, songs: &[SongMetadata]) {
+        let collapse = self.expanded_uuid.as_deref() == Some(uuid);
+        self.expanded_uuid = if collapse { None } else { Some(uuid.to_string()) };
+        self.rebuild_rows(songs);
+    }
+
+    /// Makes the rows be spawned again, for when the list was despawned
+    pub fn invalidate(&mut self) {
+        self.spawned = None;
+    }
+
+    pub fn reset_position(&mut self) {
+        self.cursor = 0;
+        self.chart_cursor = 0;
+        self.scroll_rows = 0.0;
+    }
+
+    fn max_scroll_rows(&self) -> f32 {
+        (self.rows.len() as f32 - self.viewport_rows).max(0.0)
+    }
+
+    pub fn scroll_by(&mut self, rows: f32) {
+        self.scroll_rows = (self.scroll_rows + rows).clamp(0.0, self.max_scroll_rows());
+    }
+
+    /// Moves the cursor by a number of rows and scrolls it into view
+    pub fn move_cursor(&mut self, rows: isize) {
+        if self.rows.is_empty() {
+            return
+        }
+        self.cursor = self.cursor.saturating_add_signed(rows).min(self.rows.len() - 1);
+        self.chart_cursor = 0;
+
+        let cursor = self.cursor as f32;
+        if cursor < self.scroll_rows {
+            self.scroll_rows = cursor;
+        } else if cursor + 1.0 > self.scroll_rows + self.viewport_rows {
+            self.scroll_rows = cursor + 1.0 - self.viewport_rows;
+        }
+        self.scroll_by(0.0);
+    }
+
+    pub fn cursor_row(&self) -> Option<SongListRow> {
+        self.rows.get(self.cursor).copied()
+    }
+
+    /// Number of rows that fit in the viewport, used for paging
+    pub fn page_rows(&self) -> isize {
+        (self.viewport_rows.floor() as isize).max(1)
+    }
+
+    /// First row in view and the number of rows that are at least partly in view
+    fn visible_range(&self) -> (usize, usize) {
+        let first = self.scroll_rows.floor() as usize;
+        let count = (self.viewport_rows.ceil() as usize + 1).min(self.rows.len().saturating_sub(first));
+        (first, count)
+    }
+}
+
+/// The clipped viewport the rows of the song list are spawned into
+pub fn spawn_song_list(builder: &mut ChildBuilder) {
+    builder.spawn((NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            width: Val::Px(600.0),
+            height: Val::Vh(65.0),
+            overflow: Overflow::clip(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }, SongListViewportMarker)).with_children(|builder| {
+        builder.spawn((NodeBundle {
             style: Style {
                 flex_direction: FlexDirection::Column,
-                align_items: AlignItems::Center,
-                justify_content: JustifyContent::Center,
+                width: Val::Percent(100.0),
                 ..Default::default()
             },
             ..Default::default()
-        })
-    .with_children(|builder| {
-        if !song_metadata_list.is_empty() {
-            for song_metadata in song_metadata_list {
+        }, SongListRowsMarker));
+    });
+}
+
+fn spawn_song_list_row(
+    builder: &mut ChildBuilder,
+    asset_server: &Res<AssetServer>,
+    song_metadata: &SongMetadata,
+    song_list_row: SongListRow,
+    row: usize,
+    song_list_view: &SongListViewResource,
+) {
+    let is_cursor = song_list_view.cursor == row;
+    builder.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Row,
+            align_items: AlignItems::Center,
+            height: Val::Px(ROW_HEIGHT),
+            flex_shrink: 0.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    }).with_children(|builder| {
+        match song_list_row {
+            SongListRow::Song(_) => {
                 builder.spawn((ButtonBundle {
                     style: Style {
                         padding: UiRect { left: Val::Px(12.0), right: Val::Px(12.0), top: Val::Px(8.0), bottom: Val::Px(12.0) },
                         width: Val::Px(350.0),
                         border: UiRect::all(Val::Px(4.0)),
-                        margin: UiRect::bottom(Val::Px(8.0)),
+                        overflow: Overflow::clip(),
                         ..Default::default()
                     },
-                    border_color: BorderColor(Color::srgb(0.35, 0.35, 0.35)),
+                    border_color: BorderColor(if is_cursor { CURSOR_BORDER_COLOR } else { BORDER_COLOR }),
                     ..Default::default()
-                }, SongListItemMarker { uuid: song_metadata.uuid.clone() })).with_children(|builder| {
+                }, SongListItemMarker { row })).with_children(|builder| {
                     builder.spawn(TextBundle::from_section(
                         format!(
                             "{} - {}",
@@ -47,18 +234,274 @@ pub fn spawn_song_list(
                             font_size: 16.0,
                             color: Color::WHITE,
                         },
-                    ));
+                    ).with_no_wrap());
                 });
+
+                // Opens the song in the chart editor
+                spawn_button_minimal(
+                    builder,
+                    asset_server,
+                    "edit",
+                    SongListEditMarker { uuid: song_metadata.uuid.clone() }
+                );
+                // Writes the song into a package to share it
+                spawn_button_minimal(
+                    builder,
+                    asset_server,
+                    "export",
+                    SongListExportMarker { uuid: song_metadata.uuid.clone() }
+                );
+            }
+            SongListRow::Charts(_, arrangement) => {
+                builder.spawn(TextBundle {
+                    text: Text::from_section(
+                        arrangement.name(),
+                        TextStyle {
+                            font: asset_server.load("fonts/IBMPlexMono-Regular.ttf"),
+                            font_size: 14.0,
+                            color: BORDER_COLOR,
+                        },
+                    ),
+                    style: Style {
+                        width: Val::Px(80.0),
+                        margin: UiRect::left(Val::Px(24.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+                for (column, song_chart) in arrangement_charts(song_metadata, arrangement).into_iter().enumerate() {
+                    spawn_button_minimal(
+                        builder,
+                        asset_server,
+                        song_chart.map(|song_chart| song_chart.difficulty.name()).unwrap_or("full"),
+                        SongListChartMarker { uuid: song_metadata.uuid.clone(), song_chart, row, column }
+                    );
+                }
             }
-        } else {
-            builder.spawn(TextBundle::from_section(
-                "no songs found",
-                TextStyle {
-                    font: asset_server.load("fonts/IBMPlexMono-Medium.ttf"),
-                    font_size: 16.0,
-                    color: Color::WHITE,
-                },
-            ));
         }
     });
 }
+
+/// Scrolls the song list and moves its cursor, and spawns the rows that came into view
+pub fn song_list_view_update(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    song_library: Res<SongLibraryResource>,
+    song_list_filter: Res<SongListFilterResource>,
+    play_history: Res<PlayHistoryResource>,
+    mut song_list_view: ResMut<SongListViewResource>,
+    viewport_query: Query<&Node, With<SongListViewportMarker>>,
+    mut rows_query: Query<(Entity, &mut Style), With<SongListRowsMarker>>,
+    hovered_item_query: Query<(&SongListItemMarker, &Interaction), Changed<Interaction>>,
+    mut item_query: Query<(&SongListItemMarker, &mut BorderColor)>,
+    mut chart_query: Query<(&SongListChartMarker, &mut BackgroundColor)>,
+) {
+    // Also after a scan of the library or when the search or filters change
+    if song_library.is_changed() || song_list_filter.is_changed() {
+        if song_list_filter.is_changed() {
+            song_list_view.reset_position();
+        }
+        let song_indices = song_list_filter.apply(&song_library.songs, &play_history);
+        song_list_view.set_songs(song_indices, &song_library.songs);
+    }
+
+    if let Ok(node) = viewport_query.get_single() {
+        let viewport_rows = node.size().y / ROW_HEIGHT;
+        if viewport_rows > 0.0 && viewport_rows != song_list_view.viewport_rows {
+            song_list_view.viewport_rows = viewport_rows;
+            song_list_view.scroll_by(0.0);
+        }
+    }
+
+    for mouse_wheel in mouse_wheel_events.read() {
+        let rows = match mouse_wheel.unit {
+            MouseScrollUnit::Line => mouse_wheel.y * WHEEL_ROWS,
+            MouseScrollUnit::Pixel => mouse_wheel.y / ROW_HEIGHT,
+        };
+        song_list_view.scroll_by(-rows);
+    }
+
+    let gamepad_just_pressed = |button_type: GamepadButtonType| gamepad_buttons.get_just_pressed().any(|button| button.button_type == button_type);
+    let page_rows = song_list_view.page_rows();
+    if keys.just_pressed(KeyCode::ArrowDown) || gamepad_just_pressed(GamepadButtonType::DPadDown) {
+        song_list_view.move_cursor(1);
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) || gamepad_just_pressed(GamepadButtonType::DPadUp) {
+        song_list_view.move_cursor(-1);
+    }
+    if keys.just_pressed(KeyCode::PageDown) || gamepad_just_pressed(GamepadButtonType::RightTrigger) {
+        song_list_view.move_cursor(page_rows);
+    }
+    if keys.just_pressed(KeyCode::PageUp) || gamepad_just_pressed(GamepadButtonType::LeftTrigger) {
+        song_list_view.move_cursor(-page_rows);
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        song_list_view.move_cursor(isize::MIN);
+    }
+    if keys.just_pressed(KeyCode::End) {
+        song_list_view.move_cursor(isize::MAX);
+    }
+    if let Some(SongListRow::Charts(index, arrangement)) = song_list_view.cursor_row() {
+        let charts = song_library.songs.get(index).map(|song| arrangement_charts(song, arrangement).len()).unwrap_or_default();
+        if keys.just_pressed(KeyCode::ArrowRight) || gamepad_just_pressed(GamepadButtonType::DPadRight) {
+            song_list_view.chart_cursor = (song_list_view.chart_cursor + 1).min(charts.saturating_sub(1));
+        }
+        if keys.just_pressed(KeyCode::ArrowLeft) || gamepad_just_pressed(GamepadButtonType::DPadLeft) {
+            song_list_view.chart_cursor = song_list_view.chart_cursor.saturating_sub(1);
+        }
+    }
+
+    // Pointing at a song moves the cursor to it, without scrolling
+    // Rows that appear under a mouse that stands still don't, so scrolling with the keyboard isn't undone
+    let mouse_moved = cursor_moved_events.read().count() > 0;
+    for (song_list_item, interaction) in hovered_item_query.iter() {
+        if mouse_moved && *interaction == Interaction::Hovered && song_list_view.cursor != song_list_item.row {
+            song_list_view.cursor = song_list_item.row;
+            song_list_view.chart_cursor = 0;
+        }
+    }
+
+    let (first, count) = song_list_view.visible_range();
+    for (entity, mut style) in rows_query.iter_mut() {
+        let top = Val::Px(-song_list_view.scroll_rows.fract() * ROW_HEIGHT);
+        if style.top != top {
+            style.top = top;
+        }
+
+        if song_list_view.spawned == Some((first, count)) {
+            continue
+        }
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|builder| {
+            if song_list_view.rows.is_empty() {
+                builder.spawn(TextBundle::from_section(
+                    if song_library.songs.is_empty() { "no songs found" } else { "no songs match the search" },
+                    TextStyle {
+                        font: asset_server.load("fonts/IBMPlexMono-Medium.ttf"),
+                        font_size: 16.0,
+                        color: Color::WHITE,
+                    },
+                ));
+            }
+            for row in first..first + count {
+                let song_list_row = song_list_view.rows[row];
+                spawn_song_list_row(builder, &asset_server, &song_library.songs[song_list_row.song_index()], song_list_row, row, &song_list_view);
+            }
+        });
+        song_list_view.spawned = Some((first, count));
+    }
+
+    // Rows that stay in view only need their highlight updated
+    for (song_list_item, mut border_color) in item_query.iter_mut() {
+        let color = if song_list_item.row == song_list_view.cursor { CURSOR_BORDER_COLOR } else { BORDER_COLOR };
+        if border_color.0 != color {
+            border_color.0 = color;
+        }
+    }
+    for (song_list_chart, mut background_color) in chart_query.iter_mut() {
+        let is_cursor = song_list_chart.row == song_list_view.cursor && song_list_chart.column == song_list_view.chart_cursor;
+        let color = if is_cursor { CHART_CURSOR_COLOR } else { Color::NONE };
+        if background_color.0 != color {
+            background_color.0 = color;
+        }
+    }
+}
+
+/// Starts, edits or expands the song that was clicked or chosen with the keyboard or gamepad
+pub fn song_list_update(
+    mut commands: Commands,
+    song_list_element_query_interaction: Query<(&SongListItemMarker, &Interaction), With<SongListItemMarker>>,
+    song_list_edit_query_interaction: Query<(&SongListEditMarker, &Interaction), With<SongListEditMarker>>,
+    song_list_chart_query_interaction: Query<(&SongListChartMarker, &Interaction), With<SongListChartMarker>>,
+    song_list_export_query_interaction: Query<(&SongListExportMarker, &Interaction), With<SongListExportMarker>>,
+    song_library: Res<SongLibraryResource>,
+    mut song_list_view: ResMut<SongListViewResource>,
+    mut song_loaded: ResMut<SongLoadedResource>,
+    mut next_state: ResMut<NextState<AppState>>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+    configuration: Res<ConfigurationResource>,
+    mut song_import: ResMut<SongImportResource>,
+) {
+    let gamepad_just_pressed = |button_type: GamepadButtonType| gamepad_buttons.get_just_pressed().any(|button| button.button_type == button_type);
+
+    // The row that was clicked, or the row of the cursor when it's chosen with the keyboard or gamepad
+    let mut chosen_row: Option<usize> = None;
+    if keys.just_pressed(KeyCode::Enter) || gamepad_just_pressed(GamepadButtonType::South) {
+        chosen_row = Some(song_list_view.cursor);
+    }
+    for (song_list_element, interaction) in song_list_element_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            chosen_row = Some(song_list_element.row);
+        }
+    }
+    if gamepad_just_pressed(GamepadButtonType::East) {
+        if let Some(uuid) = song_list_view.expanded_uuid.clone() {
+            song_list_view.toggle_expanded(&uuid, &song_library.songs);
+        }
+    }
+
+    let mut selected_chart: Option<(SongMetadata, Option<SongChart>)> = None;
+    // Rows are rebuilt after a scan, a row that still points at a song that is gone is ignored
+    let chosen_song = chosen_row
+        .and_then(|row| song_list_view.rows.get(row).copied())
+        .and_then(|song_list_row| song_library.songs.get(song_list_row.song_index()).map(|song| (song_list_row, song)));
+    match chosen_song {
+        Some((SongListRow::Song(_), selected_song)) => {
+            // Songs with a choice of charts show them first
+            let charts = song_charts(selected_song);
+            if charts.len() > 1 {
+                song_list_view.cursor = chosen_row.unwrap_or_default();
+                song_list_view.toggle_expanded(&selected_song.uuid, &song_library.songs);
+            } else {
+                selected_chart = Some((selected_song.clone(), charts[0]));
+            }
+        }
+        Some((SongListRow::Charts(_, arrangement), selected_song)) => {
+            if let Some(song_chart) = arrangement_charts(selected_song, arrangement).get(song_list_view.chart_cursor) {
+                selected_chart = Some((selected_song.clone(), *song_chart));
+            }
+        }
+        None => {}
+    }
+    for (song_list_chart, interaction) in song_list_chart_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            if let Some(selected_song) = song_library.find_by_id(&song_list_chart.uuid) {
+                selected_chart = Some((selected_song.clone(), song_list_chart.song_chart));
+            }
+        }
+    }
+    if let Some((song_metadata, song_chart)) = selected_chart {
+        if configuration.dynamic_difficulty {
+            // Starts at the chosen difficulty and adapts from there
+            song_loaded.load_song_with_dynamic_difficulty(song_metadata, song_chart);
+        } else {
+            song_loaded.load_song(song_metadata, song_chart);
+        }
+        next_state.set(AppState::SongPlay);
+    }
+    for (song_list_edit, interaction) in song_list_edit_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            if let Some(selected_song) = song_library.find_by_id(&song_list_edit.uuid) {
+                // Songs without a main chart open their first chart, the editor can switch between them
+                let has_main_chart = get_songs_dir().is_some_and(|songs_dir| songs_dir.join(&selected_song.uuid).join(CHART_FILENAME).exists());
+                let song_chart = selected_song.charts.first().copied().filter(|_| !has_main_chart);
+                commands.insert_resource(SongEditorResource::load(selected_song.clone(), song_chart));
+                next_state.set(AppState::Editor);
+            }
+        }
+    }
+
+    for (song_list_export, interaction) in song_list_export_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            if let Some(selected_song) = song_library.find_by_id(&song_list_export.uuid) {
+                song_import.export(selected_song);
+            }
+        }
+    }
+}
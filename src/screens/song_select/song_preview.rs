@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+
+use crate::resources::{output_audio_song::{AudioCommand, OutputAudioControllerSong}, song_library::SongLibraryResource};
+
+use super::song_list::SongListViewResource;
+
+// The preview only starts once a song stays highlighted this long, so scrolling past songs stays quiet
+const PREVIEW_DELAY_SECONDS: f32 = 0.3;
+
+#[derive(Default)]
+pub struct SongPreviewState {
+    uuid: Option<String>, // Song under the cursor
+    highlighted_seconds: f32,
+    playing: bool,
+}
+
+/// Plays a preview clip of the song under the cursor of the song list
+pub fn song_preview_update(
+    time: Res<Time>,
+    song_list_view: Res<SongListViewResource>,
+    song_library: Res<SongLibraryResource>,
+    output_audio_song: Res<OutputAudioControllerSong>,
+    mut state: Local<SongPreviewState>,
+) {
+    // A new audio thread plays nothing yet
+    if output_audio_song.is_added() {
+        *state = SongPreviewState::default();
+    }
+
+    let song_metadata = song_list_view.cursor_row().and_then(|song_list_row| song_library.songs.get(song_list_row.song_index()));
+    let uuid = song_metadata.map(|song_metadata| song_metadata.uuid.clone());
+    if uuid != state.uuid {
+        if state.playing {
+            let _ = output_audio_song.sender.send(AudioCommand::StopPreview);
+        }
+        *state = SongPreviewState { uuid, ..Default::default() };
+        return
+    }
+
+    let Some(song_metadata) = song_metadata else { return };
+    if state.playing {
+        return
+    }
+    state.highlighted_seconds += time.delta_seconds();
+    if state.highlighted_seconds < PREVIEW_DELAY_SECONDS {
+        return
+    }
+
+    // Songs without audio are only tried once
+    state.playing = true;
+    let Some((audio_path, preview_clip)) = song_metadata.preview_clip() else { return };
+    let _ = output_audio_song.sender.send(AudioCommand::Preview(
+        audio_path.to_string_lossy().to_string(),
+        preview_clip.start_seconds,
+        preview_clip.duration_seconds,
+    ));
+}
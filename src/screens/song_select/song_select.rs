@@ -1,8 +1,8 @@
 use bevy::prelude::*;
 
-use crate::{components::button_minimal::spawn_button_minimal, resources::{song_library::SongLibraryResource, song_loaded::SongLoadedResource}, states::app_state::AppState};
+use crate::{components::button_minimal::spawn_button_minimal, resources::{configuration::ConfigurationResource, song_library::SongLibraryResource, song_library_scan::SongLibraryScanResource}, states::app_state::AppState};
 
-use super::song_list::{spawn_song_list, SongListItemMarker};
+use super::{library_report::{library_report_label, spawn_library_report, watch_songs_dir_label, LibraryReportButton, WatchSongsDirButton}, song_details::spawn_song_details, song_filter::{spawn_song_filter, SongListFilterResource}, song_import::{spawn_song_import, ImportPackageButton, ImportSongButton, SongImportResource}, song_list::{spawn_song_list, SongListViewResource}};
 
 #[derive(Component)]
 pub struct SongSelectMarker;
@@ -11,13 +11,43 @@ pub struct ChangeInputDeviceButton;
 #[derive(Component)]
 pub struct RefreshSongLibraryButton;
 #[derive(Component)]
-pub struct SongListWrapperMarker;
+pub struct LeftHandedButton;
+#[derive(Component)]
+pub struct InvertStringsButton;
+#[derive(Component)]
+pub struct DynamicDifficultyButton;
+#[derive(Component)]
+pub struct GuitarStemButton;
+
+fn left_handed_label(configuration: &ConfigurationResource) -> String {
+    format!("left-handed: {}", if configuration.left_handed { "on" } else { "off" })
+}
+
+fn invert_strings_label(configuration: &ConfigurationResource) -> String {
+    format!("string order: {}", if configuration.invert_strings { "inverted" } else { "normal" })
+}
+
+fn dynamic_difficulty_label(configuration: &ConfigurationResource) -> String {
+    format!("difficulty: {}", if configuration.dynamic_difficulty { "adaptive" } else { "fixed" })
+}
+
+fn guitar_stem_label(configuration: &ConfigurationResource) -> String {
+    format!("guitar stem: {}", configuration.guitar_stem.name())
+}
 
 pub fn song_select_load(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     song_library: Res<SongLibraryResource>,
+    song_library_scan: Res<SongLibraryScanResource>,
+    configuration: Res<ConfigurationResource>,
+    song_list_filter: Res<SongListFilterResource>,
+    song_import: Res<SongImportResource>,
+    mut song_list_view: ResMut<SongListViewResource>,
 ) {
+    // The rows of the song list are spawned once the list is in place
+    song_list_view.invalidate();
+
     commands.spawn((Camera2dBundle::default(), SongSelectMarker));
     commands.spawn((NodeBundle {
         style: Style {
@@ -51,13 +81,77 @@ pub fn song_select_load(
                     "< change input device",
                     ChangeInputDeviceButton
                 );
-                // Refresh song library button
-                spawn_button_minimal(
-                    builder,
-                    &asset_server,
-                    "refresh song library",
-                    RefreshSongLibraryButton
-                );
+
+                builder.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }).with_children(|builder| {
+                    // Display options
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &left_handed_label(&configuration),
+                        LeftHandedButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &invert_strings_label(&configuration),
+                        InvertStringsButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &dynamic_difficulty_label(&configuration),
+                        DynamicDifficultyButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &guitar_stem_label(&configuration),
+                        GuitarStemButton
+                    );
+
+                    // Import button
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "import song",
+                        ImportSongButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "import package",
+                        ImportPackageButton
+                    );
+
+                    // Problems found in the song library
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &library_report_label(&song_library.report, &song_library_scan),
+                        LibraryReportButton
+                    );
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        &watch_songs_dir_label(&configuration),
+                        WatchSongsDirButton
+                    );
+
+                    // Refresh song library button
+                    spawn_button_minimal(
+                        builder,
+                        &asset_server,
+                        "refresh song library",
+                        RefreshSongLibraryButton
+                    );
+                });
         });
 
         // Content
@@ -84,55 +178,107 @@ pub fn song_select_load(
                     }
                 ));
 
+                spawn_song_import(builder, &asset_server, &song_import);
+
+                spawn_library_report(builder, &asset_server, &song_library.report);
+
+                spawn_song_filter(builder, &asset_server, &song_list_filter);
 
-                builder.spawn((NodeBundle {
+                // Song list and the details of the highlighted song
+                builder.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::FlexStart,
+                        column_gap: Val::Px(24.0),
+                        ..Default::default()
+                    },
                     ..Default::default()
-                }, SongListWrapperMarker))
-                    .with_children(|builder| {
-                        spawn_song_list(builder, &asset_server, &song_library.songs);
-                    });
+                }).with_children(|builder| {
+                    spawn_song_list(builder);
+
+                    spawn_song_details(builder, &asset_server);
+                });
             });
         });
 }
 
 pub fn song_select_update(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
     change_input_device_button_query_interaction: Query<&Interaction, With<ChangeInputDeviceButton>>,
     refresh_song_library_button_query_interaction: Query<&Interaction, With<RefreshSongLibraryButton>>,
-    song_list_element_query_interaction: Query<(&SongListItemMarker, &Interaction), With<SongListItemMarker>>,
-    song_list_wrapper_query: Query<Entity, With<SongListWrapperMarker>>,
-    mut song_library: ResMut<SongLibraryResource>,
-    mut song_loaded: ResMut<SongLoadedResource>,
+    song_library: Res<SongLibraryResource>,
+    mut song_library_scan: ResMut<SongLibraryScanResource>,
     mut next_state: ResMut<NextState<AppState>>,
     buttons: Res<ButtonInput<MouseButton>>,
+    left_handed_button_query: Query<(&Interaction, &Children), With<LeftHandedButton>>,
+    invert_strings_button_query: Query<(&Interaction, &Children), With<InvertStringsButton>>,
+    dynamic_difficulty_button_query: Query<(&Interaction, &Children), With<DynamicDifficultyButton>>,
+    guitar_stem_button_query: Query<(&Interaction, &Children), With<GuitarStemButton>>,
+    mut text_query: Query<&mut Text>,
+    mut configuration: ResMut<ConfigurationResource>,
 ) {
     for interaction in change_input_device_button_query_interaction.iter() {
         if *interaction == Interaction::Pressed {
             next_state.set(AppState::InputDeviceOverview);
         }
     }
-    for interaction in refresh_song_library_button_query_interaction.iter() {
+    for (interaction, children) in left_handed_button_query.iter() {
         if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
-            song_library.scan().unwrap();
-            song_library.save_to_disk();
+            configuration.left_handed = !configuration.left_handed;
+            configuration.save_to_disk();
 
-            for entity in song_list_wrapper_query.iter() {
-                commands.entity(entity).despawn_descendants();
-                commands.entity(entity).with_children(|builder| {
-                    spawn_song_list(builder, &asset_server, &song_library.songs);
-                });
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = left_handed_label(&configuration);
+                }
             }
         }
     }
-    for (song_list_element, interaction) in song_list_element_query_interaction.iter() {
+    for (interaction, children) in invert_strings_button_query.iter() {
         if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
-            if let Some(selected_song) = song_library.find_by_id(&song_list_element.uuid) {
-                song_loaded.load_song(selected_song.clone());
-                next_state.set(AppState::SongPlay);
+            configuration.invert_strings = !configuration.invert_strings;
+            configuration.save_to_disk();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = invert_strings_label(&configuration);
+                }
             }
         }
     }
+    for (interaction, children) in dynamic_difficulty_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            configuration.dynamic_difficulty = !configuration.dynamic_difficulty;
+            configuration.save_to_disk();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = dynamic_difficulty_label(&configuration);
+                }
+            }
+        }
+    }
+    for (interaction, children) in guitar_stem_button_query.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            configuration.guitar_stem = configuration.guitar_stem.next();
+            configuration.save_to_disk();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = guitar_stem_label(&configuration);
+                }
+            }
+        }
+    }
+    let mut refresh_song_library = false;
+    for interaction in refresh_song_library_button_query_interaction.iter() {
+        if *interaction == Interaction::Pressed && buttons.just_pressed(MouseButton::Left) {
+            refresh_song_library = true;
+        }
+    }
+    // The song list is rebuilt once the scan is done
+    if refresh_song_library {
+        song_library_scan.start(&song_library);
+    }
 }
 
 pub fn song_select_cleanup(
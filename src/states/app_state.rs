@@ -8,4 +8,5 @@ pub enum AppState {
     Tune,
     SongSelect,
     SongPlay,
+    Editor,
 }
@@ -0,0 +1,134 @@
+use std::{error::Error, path::Path};
+
+use aubio::{OnsetMode, Tempo};
+
+use crate::{helpers::{chart::{Chart, CHART_FILENAME}, song_audio::find_song_audio, tempo_map::{TempoChange, TempoMap}}, resources::song_library::SongMetadata};
+
+use super::transcription::DecodedAudio;
+
+const TEMPO_BUFFER_SIZE: usize = 1024;
+const TEMPO_HOP_SIZE: usize = 512;
+// Energy around a beat used to tell which beat starts the measure
+const DOWNBEAT_WINDOW_SECONDS: f32 = 0.05;
+
+/// Estimated grid of a song
+#[derive(Debug, Clone)]
+pub struct TempoEstimate {
+    pub bpm: f32,
+    pub first_downbeat_seconds: f32,
+    pub confidence: f32, // As reported by the beat tracker, higher is better
+}
+
+impl TempoEstimate {
+    /// A tempo map with a single section starting on the first downbeat
+    pub fn to_tempo_map(&self, beats_per_measure: u32) -> TempoMap {
+        TempoMap {
+            changes: vec![TempoChange {
+                start_seconds: self.first_downbeat_seconds,
+                bpm: self.bpm,
+                beats_per_measure,
+                beat_unit: 4,
+            }],
+        }
+    }
+}
+
+/// Fits an evenly spaced grid to beat times with least squares, returns the beat length and the time of the first beat
+/// Beats that were skipped are accounted for by rounding every interval to a whole number of beats
+pub fn fit_beats(beat_times: &[f32]) -> Option<(f32, f32)> {
+    if beat_times.len() < 2 {
+        return None
+    }
+
+    let mut intervals: Vec<f32> = beat_times.windows(2).map(|pair| pair[1] - pair[0]).filter(|interval| *interval > 0.0).collect();
+    if intervals.is_empty() {
+        return None
+    }
+    intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median_interval = intervals[intervals.len() / 2];
+
+    // Index of every beat on the grid, using the median interval as the first guess
+    let indices: Vec<f32> = beat_times.iter().map(|time| ((time - beat_times[0]) / median_interval).round()).collect();
+
+    let count = beat_times.len() as f32;
+    let mean_index = indices.iter().sum::<f32>() / count;
+    let mean_time = beat_times.iter().sum::<f32>() / count;
+    let covariance: f32 = indices.iter().zip(beat_times).map(|(index, time)| (index - mean_index) * (time - mean_time)).sum();
+    let variance: f32 = indices.iter().map(|index| (index - mean_index).powi(2)).sum();
+    if variance <= 0.0 {
+        return None
+    }
+
+    let beat_seconds = covariance / variance;
+    let first_beat_seconds = mean_time - beat_seconds * mean_index;
+
+    Some((beat_seconds, first_beat_seconds))
+}
+
+fn energy_around(audio: &DecodedAudio, seconds: f32) -> f32 {
+    let half_window = (DOWNBEAT_WINDOW_SECONDS * audio.sample_rate as f32) as usize;
+    let center = (seconds.max(0.0) * audio.sample_rate as f32) as usize;
+    let start = center.saturating_sub(half_window).min(audio.samples.len());
+    let end = (center + half_window).min(audio.samples.len());
+
+    audio.samples[start..end].iter().map(|sample| sample * sample).sum()
+}
+
+/// Estimates the tempo of a song with beat tracking, and which beat starts the measures
+pub fn estimate_tempo(audio: &DecodedAudio, beats_per_measure: u32) -> Result<TempoEstimate, Box<dyn Error>> {
+    let mut tempo = Tempo::new(OnsetMode::SpecFlux, TEMPO_BUFFER_SIZE, TEMPO_HOP_SIZE, audio.sample_rate)?;
+
+    let mut beat_times = vec![];
+    let mut confidence = 0.0;
+    for chunk in audio.samples.chunks_exact(TEMPO_HOP_SIZE) {
+        if tempo.do_result(chunk)? > 0.0 {
+            beat_times.push(tempo.get_last_s());
+            confidence = tempo.get_confidence();
+        }
+    }
+
+    let (beat_seconds, first_beat_seconds) = fit_beats(&beat_times).ok_or("no beats were found in the audio")?;
+    if beat_seconds <= 0.0 {
+        return Err("no steady beat was found in the audio".into())
+    }
+
+    // The beat of the measure with the most energy is taken as the downbeat
+    let beats_per_measure = beats_per_measure.max(1) as usize;
+    let beat_count = ((audio.duration_seconds() - first_beat_seconds) / beat_seconds).floor().max(0.0) as usize;
+    let mut phase_energy = vec![0.0; beats_per_measure];
+    for beat in 0..beat_count {
+        phase_energy[beat % beats_per_measure] += energy_around(audio, first_beat_seconds + beat as f32 * beat_seconds);
+    }
+    let downbeat_phase = phase_energy.iter().enumerate()
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+        .map(|(phase, _)| phase)
+        .unwrap_or(0);
+
+    // Earliest downbeat in the song
+    let measure_seconds = beat_seconds * beats_per_measure as f32;
+    let mut first_downbeat_seconds = first_beat_seconds + downbeat_phase as f32 * beat_seconds;
+    first_downbeat_seconds -= (first_downbeat_seconds / measure_seconds).floor() * measure_seconds;
+
+    Ok(TempoEstimate {
+        bpm: 60.0 / beat_seconds,
+        first_downbeat_seconds,
+        confidence,
+    })
+}
+
+/// Estimates the tempo of the audio of a song and writes it into the tempo map of its chart
+/// A song without a chart gets an empty chart with only the tempo map
+pub fn detect_song_tempo(song_directory: &Path, beats_per_measure: u32) -> Result<TempoEstimate, Box<dyn Error>> {
+    let song_metadata = SongMetadata::load_from_file(&song_directory.join("metadata.json"))?;
+    let audio_path = find_song_audio(song_directory, song_metadata.audio_filename.as_deref()).ok_or("the song has no audio file")?;
+    let audio = DecodedAudio::load_from_file(&audio_path)?;
+    let estimate = estimate_tempo(&audio, beats_per_measure)?;
+
+    let chart_path = song_directory.join(CHART_FILENAME);
+    // Read as it is on disk, so notes that would be left out when playing are kept
+    let mut chart = if chart_path.exists() { Chart::read_from_file(&chart_path)? } else { Chart::default() };
+    chart.tempo_map = estimate.to_tempo_map(beats_per_measure);
+    chart.save_to_file(&chart_path)?;
+
+    Ok(estimate)
+}
@@ -0,0 +1,101 @@
+use crate::helpers::tuning::frequency_from_note_number;
+
+// Range of fundamentals on a guitar, E2 up to the 24th fret of the high E string
+pub const LOWEST_PITCH: usize = 40;
+pub const HIGHEST_PITCH: usize = 88;
+// Semitones between a fundamental and its 2nd to 6th harmonic
+const HARMONIC_OFFSETS: [usize; 6] = [0, 12, 19, 24, 28, 31];
+// Weight of each harmonic in the salience of a pitch
+const HARMONIC_WEIGHTS: [f32; 6] = [1.0, 0.8, 0.6, 0.45, 0.35, 0.25];
+// Pitches are only picked while their salience is at least this fraction of the strongest pitch
+const RELATIVE_THRESHOLD: f32 = 0.3;
+// Amplitude below which a window is considered silent
+const SILENCE_AMPLITUDE: f32 = 0.005;
+const MAX_POLYPHONY: usize = 6;
+
+/// Amplitude of a frequency in a windowed signal, using the Goertzel algorithm
+fn goertzel_amplitude(samples: &[f32], window: &[f32], sample_rate: u32, frequency: f32) -> f32 {
+    let coefficient = 2.0 * (2.0 * std::f32::consts::PI * frequency / sample_rate as f32).cos();
+    let (mut previous, mut before_previous) = (0.0, 0.0);
+    for (sample, weight) in samples.iter().zip(window) {
+        let current = sample * weight + coefficient * previous - before_previous;
+        before_previous = previous;
+        previous = current;
+    }
+    let power = previous * previous + before_previous * before_previous - coefficient * previous * before_previous;
+
+    // A Hann windowed sine of amplitude A peaks at A * N / 4
+    power.max(0.0).sqrt() * 4.0 / samples.len() as f32
+}
+
+pub fn hann_window(length: usize) -> Vec<f32> {
+    (0..length)
+        .map(|index| 0.5 * (1.0 - (2.0 * std::f32::consts::PI * index as f32 / (length - 1) as f32).cos()))
+        .collect()
+}
+
+/// Amplitude of every semitone from the lowest fundamental up to the highest harmonic, indexed by midi pitch
+pub fn semitone_amplitudes(samples: &[f32], window: &[f32], sample_rate: u32) -> Vec<f32> {
+    let highest = HIGHEST_PITCH + HARMONIC_OFFSETS[HARMONIC_OFFSETS.len() - 1];
+    let nyquist = sample_rate as f32 / 2.0;
+
+    (0..=highest)
+        .map(|pitch| {
+            let frequency = frequency_from_note_number(pitch as i32);
+            if pitch < LOWEST_PITCH || frequency >= nyquist {
+                return 0.0
+            }
+            goertzel_amplitude(samples, window, sample_rate, frequency)
+        })
+        .collect()
+}
+
+fn salience(amplitudes: &[f32], pitch: usize) -> f32 {
+    HARMONIC_OFFSETS.iter().zip(HARMONIC_WEIGHTS)
+        .map(|(&offset, weight)| amplitudes.get(pitch + offset).copied().unwrap_or(0.0) * weight)
+        .sum()
+}
+
+/// Salience of a single pitch in a window, used to follow how long a note rings
+pub fn pitch_salience(samples: &[f32], window: &[f32], sample_rate: u32, pitch: usize) -> f32 {
+    let nyquist = sample_rate as f32 / 2.0;
+    HARMONIC_OFFSETS.iter().zip(HARMONIC_WEIGHTS)
+        .map(|(&offset, weight)| {
+            let frequency = frequency_from_note_number((pitch + offset) as i32);
+            if frequency >= nyquist {
+                return 0.0
+            }
+            goertzel_amplitude(samples, window, sample_rate, frequency) * weight
+        })
+        .sum()
+}
+
+/// Pitches sounding in a window of audio, strongest first
+/// The strongest pitch by harmonic salience is picked and its harmonics are taken out of the spectrum before the next one is searched
+pub fn detect_pitches(samples: &[f32], window: &[f32], sample_rate: u32) -> Vec<(usize, f32)> {
+    let mut amplitudes = semitone_amplitudes(samples, window, sample_rate);
+    let mut pitches: Vec<(usize, f32)> = vec![];
+
+    while pitches.len() < MAX_POLYPHONY {
+        let Some((pitch, strength)) = (LOWEST_PITCH..=HIGHEST_PITCH)
+            .map(|pitch| (pitch, salience(&amplitudes, pitch)))
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap()) else { break };
+
+        let strongest = pitches.first().map(|first| first.1).unwrap_or(strength);
+        if amplitudes[pitch] < SILENCE_AMPLITUDE || strength < strongest * RELATIVE_THRESHOLD {
+            break
+        }
+        pitches.push((pitch, strength));
+
+        // Remove the energy the pitch explains from its harmonics
+        let fundamental = amplitudes[pitch];
+        for (&offset, weight) in HARMONIC_OFFSETS.iter().zip(HARMONIC_WEIGHTS) {
+            if let Some(amplitude) = amplitudes.get_mut(pitch + offset) {
+                *amplitude = (*amplitude - fundamental * weight).max(0.0);
+            }
+        }
+        amplitudes[pitch] = 0.0;
+    }
+
+    pitches
+}
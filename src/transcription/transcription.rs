@@ -0,0 +1,162 @@
+use std::{error::Error, path::Path};
+
+use aubio::{Onset, OnsetMode};
+use rodio::Source;
+
+use crate::{helpers::{chart::{Arrangement, Chart, Difficulty, SongChart, CHART_FILENAME}, difficulty::{reduce_notes, tag_difficulties}, fingering::{assign_fingerings, tuning_pitches}, notes::NoteEvent, song_audio::{find_song_audio, open_audio_source}}, resources::song_library::SongMetadata};
+
+use super::multi_pitch::{detect_pitches, hann_window, pitch_salience};
+
+const ONSET_BUFFER_SIZE: usize = 1024;
+const ONSET_HOP_SIZE: usize = 512;
+// Length of the window pitches are detected in, long enough to tell apart semitones in the lowest octave
+const ANALYSIS_WINDOW_SECONDS: f32 = 0.186;
+// The pick attack is noisy, so pitches are detected slightly after the onset
+const ATTACK_SKIP_SECONDS: f32 = 0.03;
+// A note rings until its salience drops below this fraction of its salience at the attack
+const SUSTAIN_RATIO: f32 = 0.25;
+const SUSTAIN_STEP_SECONDS: f32 = 0.05;
+const MAX_NOTE_SECONDS: f32 = 4.0;
+
+/// Mono samples of an audio file
+pub struct DecodedAudio {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+impl DecodedAudio {
+    pub fn load_from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let decoder = open_audio_source(path)?;
+        let channels = decoder.channels().max(1) as usize;
+        let sample_rate = decoder.sample_rate();
+
+        let interleaved: Vec<i16> = decoder.collect();
+        let samples = interleaved.chunks(channels)
+            .map(|frame| frame.iter().map(|&sample| sample as f32 / i16::MAX as f32).sum::<f32>() / frame.len() as f32)
+            .collect();
+
+        Ok(Self { samples, sample_rate })
+    }
+
+    pub fn duration_seconds(&self) -> f32 {
+        self.samples.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Samples of a window starting at a time, None when the window runs past the end
+    fn window_at(&self, seconds: f32, length: usize) -> Option<&[f32]> {
+        let start = (seconds.max(0.0) * self.sample_rate as f32) as usize;
+        self.samples.get(start..start + length)
+    }
+}
+
+fn detect_onsets(audio: &DecodedAudio) -> Result<Vec<f32>, Box<dyn Error>> {
+    let mut onset = Onset::new(OnsetMode::SpecFlux, ONSET_BUFFER_SIZE, ONSET_HOP_SIZE, audio.sample_rate)?;
+    onset.set_silence(-40.0);
+
+    let mut onsets = vec![];
+    for chunk in audio.samples.chunks_exact(ONSET_HOP_SIZE) {
+        if onset.do_result(chunk)? > 0.0 {
+            onsets.push(onset.get_last_s());
+        }
+    }
+
+    Ok(onsets)
+}
+
+/// Transcribes the notes of a recording and fingers them on a tuning
+/// Every onset becomes a chord of the pitches heard right after it, each ringing until it fades or the next onset
+pub fn transcribe_notes(audio: &DecodedAudio, tuning: &[usize; 6]) -> Result<Vec<NoteEvent>, Box<dyn Error>> {
+    let window_length = (audio.sample_rate as f32 * ANALYSIS_WINDOW_SECONDS) as usize;
+    let window = hann_window(window_length);
+    let onsets = detect_onsets(audio)?;
+
+    let mut chord_times: Vec<(f32, Vec<f32>)> = vec![]; // Start and the duration of every pitch
+    let mut chords: Vec<Vec<usize>> = vec![];
+    for (index, &start_seconds) in onsets.iter().enumerate() {
+        let end_seconds = onsets.get(index + 1).copied()
+            .unwrap_or(audio.duration_seconds())
+            .min(start_seconds + MAX_NOTE_SECONDS);
+        let Some(samples) = audio.window_at(start_seconds + ATTACK_SKIP_SECONDS, window_length) else { continue };
+
+        let pitches = detect_pitches(samples, &window, audio.sample_rate);
+        if pitches.is_empty() {
+            continue
+        }
+
+        let durations = pitches.iter().map(|&(pitch, attack_salience)| {
+            let mut seconds = start_seconds + ATTACK_SKIP_SECONDS + SUSTAIN_STEP_SECONDS;
+            while seconds < end_seconds {
+                let ringing = audio.window_at(seconds, window_length)
+                    .map(|samples| pitch_salience(samples, &window, audio.sample_rate, pitch) >= attack_salience * SUSTAIN_RATIO)
+                    .unwrap_or(false);
+                if !ringing {
+                    break
+                }
+                seconds += SUSTAIN_STEP_SECONDS;
+            }
+            seconds.min(end_seconds) - start_seconds
+        }).collect();
+
+        chords.push(pitches.iter().map(|&(pitch, _)| pitch).collect());
+        chord_times.push((start_seconds, durations));
+    }
+
+    let fingerings = assign_fingerings(&chords, tuning);
+
+    let mut note_events = vec![];
+    for ((start_seconds, durations), chord_fingerings) in chord_times.iter().zip(fingerings) {
+        for (duration_seconds, fingering) in durations.iter().zip(chord_fingerings) {
+            let Some(fingering) = fingering else { continue };
+            note_events.push(NoteEvent {
+                start_time_seconds: *start_seconds,
+                duration_seconds: *duration_seconds,
+                string_index: fingering.string_index,
+                fret_index: fingering.fret_index,
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(note_events)
+}
+
+/// Writes a draft lead chart for every difficulty into a song directory and lists them in its metadata, transcribed from one of its audio files
+/// Without an audio file name the audio of the song is used
+/// The expert chart also becomes the main chart when the song has none, or when overwriting
+pub fn transcribe_song(song_directory: &Path, audio_filename: Option<&str>, overwrite: bool) -> Result<Chart, Box<dyn Error>> {
+    let metadata_path = song_directory.join("metadata.json");
+    let mut song_metadata = SongMetadata::load_from_file(&metadata_path)?;
+    let tuning = tuning_pitches(&song_metadata.tuning).ok_or("the tuning of the song is not valid")?;
+
+    let audio_path = match audio_filename {
+        Some(audio_filename) => song_directory.join(audio_filename),
+        None => find_song_audio(song_directory, song_metadata.audio_filename.as_deref()).ok_or("the song has no audio file")?,
+    };
+    let audio = DecodedAudio::load_from_file(&audio_path)?;
+    let mut chart = Chart { notes: transcribe_notes(&audio, &tuning)?, ..Default::default() };
+    chart.sort_notes();
+
+    // The drafts are lead charts, other arrangements need their own recording
+    // Charts that are already there may be made by hand, so they are only replaced when overwriting
+    for difficulty in Difficulty::ALL {
+        let song_chart = SongChart { arrangement: Arrangement::Lead, difficulty };
+        if !overwrite && song_directory.join(song_chart.filename()).exists() {
+            continue
+        }
+        let mut difficulty_chart = Chart { notes: reduce_notes(&chart.notes, difficulty), tempo_map: chart.tempo_map.clone(), ..Default::default() };
+        difficulty_chart.sort_notes();
+        difficulty_chart.save_to_file(&song_directory.join(song_chart.filename()))?;
+        song_metadata.add_chart(song_chart);
+    }
+    song_metadata.save_to_file(&metadata_path)?;
+
+    // The main chart keeps every note, tagged so the difficulty can adapt to the player
+    tag_difficulties(&mut chart.notes);
+
+    let chart_path = song_directory.join(CHART_FILENAME);
+    if overwrite || !chart_path.exists() {
+        chart.save_to_file(&chart_path)?;
+    }
+
+    Ok(chart)
+}